@@ -0,0 +1,2 @@
+-- SMALLINT cannot hold the full 0-65535 range allowed by the vnc_port check
+ALTER TABLE nodes ALTER COLUMN vnc_port TYPE INTEGER;
@@ -0,0 +1,2 @@
+-- Whether a running node's guest is frozen via the monitor
+ALTER TABLE nodes ADD COLUMN paused BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Per-node VM sizing; NULL falls back to the QEMU defaults
+ALTER TABLE nodes ADD COLUMN memory_mb INTEGER CHECK (memory_mb > 0);
+ALTER TABLE nodes ADD COLUMN cpu_cores INTEGER CHECK (cpu_cores > 0);
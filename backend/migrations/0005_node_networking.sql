@@ -0,0 +1,4 @@
+-- How a node's NIC is connected; NULL picks the server default
+ALTER TABLE nodes ADD COLUMN network_mode TEXT CHECK (network_mode IN ('Isolated', 'User', 'Bridge'));
+-- Host to guest TCP port forwards for user-mode networking
+ALTER TABLE nodes ADD COLUMN port_forwards JSONB NOT NULL DEFAULT '[]';
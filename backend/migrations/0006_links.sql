@@ -0,0 +1,16 @@
+-- Point-to-point L2 links between nodes, realized as a bridge per link with
+-- one TAP device for each running end
+CREATE TABLE IF NOT EXISTS links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    node_a UUID NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    node_b UUID NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    latency_ms INTEGER CHECK (latency_ms >= 0),
+    bandwidth_kbps INTEGER CHECK (bandwidth_kbps > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (node_a <> node_b)
+);
+
+-- At most one link between any pair of nodes, in either direction
+CREATE UNIQUE INDEX IF NOT EXISTS idx_links_node_pair
+    ON links (LEAST(node_a, node_b), GREATEST(node_a, node_b));
+CREATE INDEX IF NOT EXISTS idx_links_node_b ON links(node_b);
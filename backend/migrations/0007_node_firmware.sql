@@ -0,0 +1,2 @@
+-- Firmware the node boots with
+ALTER TABLE nodes ADD COLUMN firmware TEXT NOT NULL DEFAULT 'Bios' CHECK (firmware IN ('Bios', 'Uefi'));
@@ -0,0 +1,4 @@
+-- Whether the node exposes a SPICE display alongside VNC
+ALTER TABLE nodes ADD COLUMN spice BOOLEAN NOT NULL DEFAULT FALSE;
+-- SPICE port of a running node
+ALTER TABLE nodes ADD COLUMN spice_port INTEGER;
@@ -0,0 +1,2 @@
+-- SHA-256 of the image file, verified before a node using it starts
+ALTER TABLE images ADD COLUMN sha256 TEXT CHECK (sha256 ~ '^[0-9a-f]{64}$');
@@ -0,0 +1,2 @@
+-- netem delay, loss and rate applied to a bridged node's TAP device; NULL means unshaped
+ALTER TABLE nodes ADD COLUMN netem JSONB;
@@ -0,0 +1,98 @@
+use std::convert::Infallible;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use tracing::warn;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::AppState;
+
+/// Actor recorded for requests that aren't attributed to anyone
+const ANONYMOUS_ACTOR: &str = "anonymous";
+
+/// Operation recorded in the audit log
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq, Eq, ToSchema)]
+#[sqlx(type_name = "text", rename_all = "PascalCase")]
+pub enum AuditAction {
+    NodeCreated,
+    NodeStarted,
+    NodeStopped,
+    NodeRestarted,
+    NodePaused,
+    NodeResumed,
+    NodeWiped,
+    NodeDeleted,
+    NodeRenamed,
+    NodeCommitted,
+    SnapshotCreated,
+    SnapshotRestored,
+    ConnectionCreated,
+    ConnectionDeleted,
+    ConnectionShared,
+    MonitorCommand,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub actor: String,
+    pub action: AuditAction,
+    /// Node id or Guacamole connection identifier the action applied to
+    pub target_id: Option<String>,
+    pub details: Value,
+}
+
+/// Who made a request, as recorded in the audit log.
+///
+/// Authentication middleware attributes a request by inserting an `Actor`
+/// into its extensions; unattributed requests are recorded as anonymous.
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Actor>()
+            .cloned()
+            .unwrap_or_else(|| Actor(ANONYMOUS_ACTOR.to_string())))
+    }
+}
+
+/// Append an entry to the audit log.
+///
+/// The operation being audited has already happened, so a failed write is
+/// logged rather than turned into an error response.
+pub async fn record(
+    state: &AppState,
+    actor: &Actor,
+    action: AuditAction,
+    target_id: impl ToString,
+    details: Value,
+) {
+    let target_id = target_id.to_string();
+    let result = sqlx::query(
+        "INSERT INTO audit_log (id, actor, action, target_id, details) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(Uuid::now_v7())
+    .bind(&actor.0)
+    .bind(action)
+    .bind(&target_id)
+    .bind(&details)
+    .execute(&state.db)
+    .await;
+
+    if let Err(e) = result {
+        warn!(
+            "Failed to record {:?} of {} by {} in the audit log: {}",
+            action, target_id, actor.0, e
+        );
+    }
+}
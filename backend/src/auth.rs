@@ -0,0 +1,121 @@
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderMap, Method, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use subtle::ConstantTimeEq;
+
+use crate::audit::Actor;
+use crate::models::{ApiError, AppState};
+
+/// Actor recorded in the audit log for requests made with the API key
+const API_KEY_ACTOR: &str = "api-key";
+/// Actor recorded in the audit log for requests made with the admin key
+const ADMIN_ACTOR: &str = "admin";
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Whether `API_KEY` is set, i.e. whether mutating requests are authenticated
+pub fn api_key_configured(state: &AppState) -> bool {
+    configured_key(state).is_some()
+}
+
+fn configured_key(state: &AppState) -> Option<&str> {
+    state.config.server.api_key.as_deref()
+}
+
+fn configured_admin_key(state: &AppState) -> Option<&str> {
+    state.config.server.admin_api_key.as_deref()
+}
+
+fn key_matches(presented: &str, expected: &str) -> bool {
+    // ct_eq only short-circuits on a length mismatch, which reveals the
+    // key's length but none of its contents
+    bool::from(presented.as_bytes().ct_eq(expected.as_bytes()))
+}
+
+/// Middleware rejecting mutating requests that don't carry the API key.
+///
+/// The key is accepted as `Authorization: Bearer <key>` or `X-API-Key: <key>`,
+/// and the admin key is accepted wherever the API key is. Read-only requests,
+/// including `/health`, pass through unauthenticated, as does everything when
+/// no `API_KEY` is configured.
+pub async fn require_api_key(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = configured_key(&state) else {
+        return next.run(request).await;
+    };
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return next.run(request).await;
+    }
+
+    let admin_key = configured_admin_key(&state);
+    let actor = match presented_key(request.headers()) {
+        Some(key) if key_matches(key, expected) => API_KEY_ACTOR,
+        Some(key) if admin_key.is_some_and(|admin_key| key_matches(key, admin_key)) => ADMIN_ACTOR,
+        Some(_) => return unauthorized("Invalid API key"),
+        None => return unauthorized("Missing API key"),
+    };
+    request.extensions_mut().insert(Actor(actor.to_string()));
+    next.run(request).await
+}
+
+/// Extractor for routes that only admins may use, such as raw QMP access.
+///
+/// Requires the `ADMIN_API_KEY`, presented the same way as the API key, and
+/// attributes the request to the admin. Unlike `require_api_key` this fails
+/// closed: without an `ADMIN_API_KEY` the routes are unavailable.
+#[derive(Debug)]
+pub struct Admin;
+
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(expected) = configured_admin_key(state) else {
+            return Err(ApiError::Forbidden(
+                "Admin access is disabled; set ADMIN_API_KEY to enable it".to_string(),
+            )
+            .into_response());
+        };
+
+        match presented_key(&parts.headers) {
+            Some(key) if key_matches(key, expected) => {
+                parts.extensions.insert(Actor(ADMIN_ACTOR.to_string()));
+                Ok(Admin)
+            }
+            Some(_) => Err(unauthorized("Invalid admin key")),
+            None => Err(unauthorized("Missing admin key")),
+        }
+    }
+}
+
+fn unauthorized(message: &str) -> Response {
+    (
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        ApiError::Unauthorized(message.to_string()),
+    )
+        .into_response()
+}
+
+/// The key from the `Authorization` bearer token or the `X-API-Key` header
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer.or_else(|| {
+        headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+    })
+}
@@ -0,0 +1,799 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::http::HeaderValue;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::qemu::KvmMode;
+
+const DEFAULT_DB_MAX_CONNECTIONS: u32 = 5;
+const DEFAULT_DB_MIN_CONNECTIONS: u32 = 0;
+const DEFAULT_DB_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_DB_CONNECT_ATTEMPTS: u32 = 10;
+const DEFAULT_DB_CONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_VNC_BIND_HOST: &str = "127.0.0.1";
+const DEFAULT_MAX_MEMORY_MB: u32 = 16384;
+const DEFAULT_MAX_CPU_CORES: u32 = 16;
+const DEFAULT_SPICE_PORT_BASE: u16 = 6100;
+const DEFAULT_IMAGE_UPLOAD_MAX_MB: u64 = 20480;
+const DEFAULT_REQUEST_BODY_LIMIT_KB: usize = 2048;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Timeout for a whole Guacamole request unless overridden by `GUAC_REQUEST_TIMEOUT`
+const DEFAULT_GUAC_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum ConfigFileError {
+    #[error("Failed to read config file: {0}")]
+    Read(#[from] io::Error),
+    #[error("Failed to parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// A variable that is missing or can't be parsed into its setting
+#[derive(Debug, Error)]
+#[error("Invalid value {value:?} for {name}: {reason}")]
+pub struct InvalidValue {
+    pub name: &'static str,
+    pub value: String,
+    pub reason: &'static str,
+}
+
+/// Settings the backend runs with, parsed once at startup from the
+/// variables gathered from `.env` or `--config`.
+///
+/// Every value is checked here, so a typo fails startup instead of
+/// surfacing in the first request that needs it.
+#[derive(Debug)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub qemu: QemuHostConfig,
+    pub guacamole: GuacamoleConfig,
+}
+
+#[derive(Debug)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub host: String,
+    pub port: u16,
+    /// Times to try connecting at startup before giving up
+    pub connect_attempts: u32,
+    /// Wait after the first failed attempt, doubled each retry
+    pub connect_base_delay: Duration,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long to wait for a free pooled connection
+    pub acquire_timeout: Duration,
+}
+
+#[derive(Debug)]
+pub struct ServerConfig {
+    /// `host:port` to listen on
+    pub bind_address: String,
+    /// Key required by mutating requests; unauthenticated when unset
+    pub api_key: Option<String>,
+    /// Key required by admin-only routes; those routes are disabled when unset
+    pub admin_api_key: Option<String>,
+    /// Origins browser frontends may call the API from
+    pub cors_allowed_origins: CorsOrigins,
+    /// Largest request body accepted outside of image uploads, in bytes
+    pub request_body_limit: usize,
+    /// How long a request may take before it is answered with 408; routes
+    /// doing long disk or network transfers are exempt
+    pub request_timeout: Duration,
+}
+
+/// Origins allowed to make cross-origin requests, from `CORS_ALLOWED_ORIGINS`
+#[derive(Debug, Clone, Default)]
+pub enum CorsOrigins {
+    /// Only pages served from the API's own origin
+    #[default]
+    None,
+    /// `*`: any origin
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl FromStr for CorsOrigins {
+    type Err = ();
+
+    /// Parse `*` or a comma-separated list of `scheme://host[:port]` origins
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.trim() == "*" {
+            return Ok(Self::Any);
+        }
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| {
+                let (scheme, host) = origin.split_once("://").ok_or(())?;
+                if !matches!(scheme, "http" | "https") || host.is_empty() || host.contains('/') {
+                    return Err(());
+                }
+                HeaderValue::from_str(origin).map_err(|_| ())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::List)
+    }
+}
+
+/// Host-wide QEMU settings, as opposed to the per-VM `qemu::QemuConfig`
+#[derive(Debug)]
+pub struct QemuHostConfig {
+    pub image_dir: PathBuf,
+    pub overlay_dir: PathBuf,
+    /// Directory for serial console logs, defaulting to `overlay_dir`
+    pub console_dir: PathBuf,
+    /// Directory ISO images can be inserted from; CD-ROM changes are refused without it
+    pub iso_dir: Option<PathBuf>,
+    /// Directory packet captures are written to; captures are refused without it
+    pub capture_dir: Option<PathBuf>,
+    /// How long a guest gets to power off before it is killed
+    pub shutdown_timeout: Duration,
+    pub vnc_bind_host: String,
+    /// Largest memory size a node may request, in MB
+    pub max_memory_mb: u32,
+    /// Largest CPU core count a node may request
+    pub max_cpu_cores: u32,
+    /// Memory all running nodes may use together, in MB
+    pub max_total_memory_mb: Option<u64>,
+    /// CPU cores all running nodes may use together
+    pub max_total_cores: Option<u64>,
+    /// Linux bridge that node NICs are attached to
+    pub bridge: Option<String>,
+    /// First port handed out to nodes with a SPICE display
+    pub spice_port_base: u16,
+    pub kvm_mode: KvmMode,
+    /// OVMF firmware code for UEFI nodes
+    pub ovmf_code_path: Option<PathBuf>,
+    /// OVMF variable store template copied for each UEFI node
+    pub ovmf_vars_path: Option<PathBuf>,
+    /// Largest image file accepted by `POST /image/upload`, in bytes
+    pub image_upload_max_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct GuacamoleConfig {
+    /// Base URL of the Guacamole web application, without a trailing slash
+    pub url: String,
+    pub tunnel_path: String,
+    pub api_path: String,
+    pub connection_prefix: String,
+    pub user: String,
+    pub pass: String,
+    /// How long a request to the Guacamole API may take before it is abandoned
+    pub request_timeout: Duration,
+    /// Directory on the guacd host that session recordings are written to
+    pub recording_dir: Option<String>,
+}
+
+impl Config {
+    /// Parse the variables collected at startup, keyed by their `.env` names
+    pub fn from_env(env: &HashMap<String, String>) -> Result<Self, InvalidValue> {
+        let vars = Vars(env);
+
+        let max_connections: u32 = vars.parse_or(
+            "DB_MAX_CONNECTIONS",
+            DEFAULT_DB_MAX_CONNECTIONS,
+            "expected a non-negative integer",
+        )?;
+        if max_connections == 0 {
+            return Err(vars.invalid("DB_MAX_CONNECTIONS", "must be greater than zero"));
+        }
+        let min_connections: u32 = vars.parse_or(
+            "DB_MIN_CONNECTIONS",
+            DEFAULT_DB_MIN_CONNECTIONS,
+            "expected a non-negative integer",
+        )?;
+        if min_connections > max_connections {
+            return Err(vars.invalid("DB_MIN_CONNECTIONS", "must not exceed DB_MAX_CONNECTIONS"));
+        }
+        let connect_attempts: u32 = vars.parse_or(
+            "DB_CONNECT_ATTEMPTS",
+            DEFAULT_DB_CONNECT_ATTEMPTS,
+            "expected a non-negative integer",
+        )?;
+        if connect_attempts == 0 {
+            return Err(vars.invalid("DB_CONNECT_ATTEMPTS", "must be greater than zero"));
+        }
+
+        let database = DatabaseConfig {
+            url: format!(
+                "postgres://{}:{}@{}:{}/{}",
+                vars.required("POSTGRES_USER")?,
+                vars.required("POSTGRES_PASSWORD")?,
+                vars.required("POSTGRES_HOST")?,
+                vars.required("POSTGRES_PORT")?,
+                vars.required("BACKEND_DB")?,
+            ),
+            host: vars.required("POSTGRES_HOST")?.to_string(),
+            port: vars.parse("POSTGRES_PORT", "expected a port number")?,
+            connect_attempts,
+            connect_base_delay: vars
+                .parse_optional("DB_CONNECT_BASE_DELAY_MS", "expected milliseconds")?
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_DB_CONNECT_BASE_DELAY),
+            max_connections,
+            min_connections,
+            acquire_timeout: vars
+                .parse_optional("DB_ACQUIRE_TIMEOUT", "expected seconds")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_DB_ACQUIRE_TIMEOUT),
+        };
+
+        let request_body_limit_kb: usize = vars.parse_or(
+            "REQUEST_BODY_LIMIT_KB",
+            DEFAULT_REQUEST_BODY_LIMIT_KB,
+            "expected a size in KB",
+        )?;
+        if request_body_limit_kb == 0 {
+            return Err(vars.invalid("REQUEST_BODY_LIMIT_KB", "must be greater than zero"));
+        }
+        let request_body_limit = request_body_limit_kb
+            .checked_mul(1024)
+            .ok_or_else(|| vars.invalid("REQUEST_BODY_LIMIT_KB", "is too large"))?;
+
+        let server = ServerConfig {
+            bind_address: format!(
+                "{}:{}",
+                vars.required("BACKEND_HOST")?,
+                vars.parse::<u16>("BACKEND_PORT", "expected a port number")?
+            ),
+            api_key: vars.optional("API_KEY").map(str::to_string),
+            admin_api_key: vars.optional("ADMIN_API_KEY").map(str::to_string),
+            cors_allowed_origins: vars.parse_or(
+                "CORS_ALLOWED_ORIGINS",
+                CorsOrigins::None,
+                "expected `*` or comma-separated origins like https://lab.example.com",
+            )?,
+            request_body_limit,
+            request_timeout: vars
+                .parse_optional("REQUEST_TIMEOUT", "expected seconds")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_REQUEST_TIMEOUT),
+        };
+
+        let image_upload_max_mb: u64 = vars.parse_or(
+            "IMAGE_UPLOAD_MAX_MB",
+            DEFAULT_IMAGE_UPLOAD_MAX_MB,
+            "expected a size in MB",
+        )?;
+        if image_upload_max_mb == 0 {
+            return Err(vars.invalid("IMAGE_UPLOAD_MAX_MB", "must be greater than zero"));
+        }
+        let image_upload_max_bytes = image_upload_max_mb
+            .checked_mul(1024 * 1024)
+            .ok_or_else(|| vars.invalid("IMAGE_UPLOAD_MAX_MB", "is too large"))?;
+
+        let overlay_dir = PathBuf::from(vars.required("OVERLAY_DIR")?);
+        let qemu = QemuHostConfig {
+            image_dir: PathBuf::from(vars.required("IMAGE_DIR")?),
+            console_dir: vars
+                .optional("CONSOLE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| overlay_dir.clone()),
+            overlay_dir,
+            iso_dir: vars.optional("ISO_DIR").map(PathBuf::from),
+            capture_dir: vars.optional("CAPTURE_DIR").map(PathBuf::from),
+            shutdown_timeout: vars
+                .parse_optional("QEMU_SHUTDOWN_TIMEOUT", "expected seconds")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT),
+            vnc_bind_host: vars
+                .optional("QEMU_VNC_BIND_HOST")
+                .unwrap_or(DEFAULT_VNC_BIND_HOST)
+                .to_string(),
+            max_memory_mb: vars.parse_or(
+                "QEMU_MAX_MEMORY_MB",
+                DEFAULT_MAX_MEMORY_MB,
+                "expected a size in MB",
+            )?,
+            max_cpu_cores: vars.parse_or(
+                "QEMU_MAX_CPU_CORES",
+                DEFAULT_MAX_CPU_CORES,
+                "expected a core count",
+            )?,
+            max_total_memory_mb: vars
+                .parse_optional("MAX_TOTAL_MEMORY_MB", "expected a size in MB")?,
+            max_total_cores: vars.parse_optional("MAX_TOTAL_CORES", "expected a core count")?,
+            bridge: vars.optional("QEMU_BRIDGE").map(str::to_string),
+            spice_port_base: vars.parse_or(
+                "QEMU_SPICE_PORT_BASE",
+                DEFAULT_SPICE_PORT_BASE,
+                "expected a port number",
+            )?,
+            kvm_mode: vars.parse_or(
+                "QEMU_KVM_MODE",
+                KvmMode::default(),
+                "expected \"strict\" or \"lenient\"",
+            )?,
+            ovmf_code_path: vars.optional("OVMF_CODE_PATH").map(PathBuf::from),
+            ovmf_vars_path: vars.optional("OVMF_VARS_PATH").map(PathBuf::from),
+            image_upload_max_bytes,
+        };
+
+        let guacamole = GuacamoleConfig {
+            url: format!(
+                "http{}://{}:{}/guacamole",
+                if vars.required("GUAC_HTTPS")? == "1" {
+                    "s"
+                } else {
+                    ""
+                },
+                vars.required("GUAC_HOST")?,
+                vars.parse::<u16>("GUAC_PORT", "expected a port number")?,
+            ),
+            tunnel_path: vars
+                .required("GUAC_TUNNEL_PATH")?
+                .trim_matches('/')
+                .to_string(),
+            api_path: vars
+                .required("GUAC_API_PATH")?
+                .trim_matches('/')
+                .to_string(),
+            connection_prefix: vars.required("GUAC_CONNECTION_PREFIX")?.to_string(),
+            user: vars.required("GUAC_USER")?.to_string(),
+            pass: vars.required("GUAC_PASS")?.to_string(),
+            request_timeout: vars
+                .parse_optional("GUAC_REQUEST_TIMEOUT", "expected seconds")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_GUAC_REQUEST_TIMEOUT),
+            recording_dir: vars.optional("GUAC_RECORDING_DIR").map(str::to_string),
+        };
+
+        // Stopping a node waits up to the shutdown timeout before killing it
+        if server.request_timeout <= qemu.shutdown_timeout {
+            return Err(vars.invalid(
+                "REQUEST_TIMEOUT",
+                "must be longer than QEMU_SHUTDOWN_TIMEOUT",
+            ));
+        }
+
+        Ok(Self {
+            database,
+            server,
+            qemu,
+            guacamole,
+        })
+    }
+}
+
+/// Lookups into the collected variables, treating empty values as unset
+struct Vars<'a>(&'a HashMap<String, String>);
+
+impl<'a> Vars<'a> {
+    fn optional(&self, name: &str) -> Option<&'a str> {
+        self.0
+            .get(name)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn required(&self, name: &'static str) -> Result<&'a str, InvalidValue> {
+        self.optional(name)
+            .ok_or_else(|| self.invalid(name, "must be set"))
+    }
+
+    fn parse<T: FromStr>(
+        &self,
+        name: &'static str,
+        reason: &'static str,
+    ) -> Result<T, InvalidValue> {
+        self.required(name)?
+            .parse()
+            .map_err(|_| self.invalid(name, reason))
+    }
+
+    fn parse_optional<T: FromStr>(
+        &self,
+        name: &'static str,
+        reason: &'static str,
+    ) -> Result<Option<T>, InvalidValue> {
+        self.optional(name)
+            .map(|value| value.parse().map_err(|_| self.invalid(name, reason)))
+            .transpose()
+    }
+
+    fn parse_or<T: FromStr>(
+        &self,
+        name: &'static str,
+        default: T,
+        reason: &'static str,
+    ) -> Result<T, InvalidValue> {
+        Ok(self.parse_optional(name, reason)?.unwrap_or(default))
+    }
+
+    fn invalid(&self, name: &'static str, reason: &'static str) -> InvalidValue {
+        InvalidValue {
+            name,
+            value: self.0.get(name).cloned().unwrap_or_default(),
+            reason,
+        }
+    }
+}
+
+/// Structured alternative to the `.env` file, loaded with `--config`.
+///
+/// Every setting is optional so a file can hold only part of the
+/// configuration; the rest comes from real environment variables.
+///
+/// ```toml
+/// [database]
+/// user = "network_lab"
+/// password = "change_me"
+/// host = "localhost"
+/// port = 5432
+/// name = "network_lab"
+///
+/// [server]
+/// host = "0.0.0.0"
+/// port = 8000
+///
+/// [qemu]
+/// image_dir = "./data/images"
+/// overlay_dir = "./data/overlays"
+///
+/// [guacamole]
+/// host = "localhost"
+/// port = 8080
+/// user = "guacadmin"
+/// pass = "guacadmin"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub database: DatabaseSection,
+    pub server: ServerSection,
+    pub qemu: QemuSection,
+    pub guacamole: GuacamoleSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSection {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub name: Option<String>,
+    /// Times to try connecting at startup before giving up
+    pub connect_attempts: Option<u32>,
+    /// Milliseconds to wait after the first failed attempt, doubled each retry
+    pub connect_base_delay_ms: Option<u64>,
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    /// Seconds to wait for a free pooled connection
+    pub acquire_timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// Key required by mutating requests
+    pub api_key: Option<String>,
+    /// Key required by admin-only routes such as the QMP passthrough
+    pub admin_api_key: Option<String>,
+    /// `*` or comma-separated origins browser frontends may call the API from
+    pub cors_allowed_origins: Option<String>,
+    /// Largest request body accepted outside of image uploads, in KB
+    pub request_body_limit_kb: Option<usize>,
+    /// Seconds a request may take before it is abandoned
+    pub request_timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QemuSection {
+    pub image_dir: Option<String>,
+    pub overlay_dir: Option<String>,
+    /// Directory for serial console logs, defaulting to `overlay_dir`
+    pub console_dir: Option<String>,
+    /// Directory ISO images can be inserted into nodes' CD-ROM drives from
+    pub iso_dir: Option<String>,
+    /// Directory packet captures of nodes' TAP devices are written to
+    pub capture_dir: Option<String>,
+    /// Seconds to wait for a guest to power off before killing it
+    pub shutdown_timeout: Option<u64>,
+    pub vnc_bind_host: Option<String>,
+    /// Largest memory size a node may request, in MB
+    pub max_memory_mb: Option<u32>,
+    /// Largest CPU core count a node may request
+    pub max_cpu_cores: Option<u32>,
+    /// Memory all running nodes may use together, in MB
+    pub max_total_memory_mb: Option<u64>,
+    /// CPU cores all running nodes may use together
+    pub max_total_cores: Option<u64>,
+    /// Linux bridge that node NICs are attached to
+    pub bridge: Option<String>,
+    /// First port handed out to nodes with a SPICE display
+    pub spice_port_base: Option<u16>,
+    /// `strict` to refuse starting nodes without KVM, `lenient` to fall
+    /// back to TCG emulation
+    pub kvm_mode: Option<String>,
+    /// OVMF firmware code for UEFI nodes
+    pub ovmf_code_path: Option<String>,
+    /// OVMF variable store template copied for each UEFI node
+    pub ovmf_vars_path: Option<String>,
+    /// Largest image file accepted by uploads, in MB
+    pub image_upload_max_mb: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuacamoleSection {
+    pub https: Option<bool>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub tunnel_path: Option<String>,
+    pub api_path: Option<String>,
+    pub connection_prefix: Option<String>,
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    /// Seconds before a request to the Guacamole API is abandoned
+    pub request_timeout: Option<u64>,
+    /// Directory on the guacd host that session recordings are written to
+    pub recording_dir: Option<String>,
+}
+
+impl FileConfig {
+    /// Read and parse a TOML config file
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Flatten the settings into the same variable names used by the `.env` file
+    pub fn into_env(self) -> HashMap<String, String> {
+        let entries = [
+            ("POSTGRES_USER", self.database.user),
+            ("POSTGRES_PASSWORD", self.database.password),
+            ("POSTGRES_HOST", self.database.host),
+            ("POSTGRES_PORT", self.database.port.map(|v| v.to_string())),
+            ("BACKEND_DB", self.database.name),
+            (
+                "DB_CONNECT_ATTEMPTS",
+                self.database.connect_attempts.map(|v| v.to_string()),
+            ),
+            (
+                "DB_CONNECT_BASE_DELAY_MS",
+                self.database.connect_base_delay_ms.map(|v| v.to_string()),
+            ),
+            (
+                "DB_MAX_CONNECTIONS",
+                self.database.max_connections.map(|v| v.to_string()),
+            ),
+            (
+                "DB_MIN_CONNECTIONS",
+                self.database.min_connections.map(|v| v.to_string()),
+            ),
+            (
+                "DB_ACQUIRE_TIMEOUT",
+                self.database.acquire_timeout.map(|v| v.to_string()),
+            ),
+            ("BACKEND_HOST", self.server.host),
+            ("BACKEND_PORT", self.server.port.map(|v| v.to_string())),
+            ("API_KEY", self.server.api_key),
+            ("ADMIN_API_KEY", self.server.admin_api_key),
+            ("CORS_ALLOWED_ORIGINS", self.server.cors_allowed_origins),
+            (
+                "REQUEST_BODY_LIMIT_KB",
+                self.server.request_body_limit_kb.map(|v| v.to_string()),
+            ),
+            (
+                "REQUEST_TIMEOUT",
+                self.server.request_timeout.map(|v| v.to_string()),
+            ),
+            ("IMAGE_DIR", self.qemu.image_dir),
+            ("OVERLAY_DIR", self.qemu.overlay_dir),
+            ("CONSOLE_DIR", self.qemu.console_dir),
+            ("ISO_DIR", self.qemu.iso_dir),
+            ("CAPTURE_DIR", self.qemu.capture_dir),
+            (
+                "QEMU_SHUTDOWN_TIMEOUT",
+                self.qemu.shutdown_timeout.map(|v| v.to_string()),
+            ),
+            ("QEMU_VNC_BIND_HOST", self.qemu.vnc_bind_host),
+            (
+                "QEMU_MAX_MEMORY_MB",
+                self.qemu.max_memory_mb.map(|v| v.to_string()),
+            ),
+            (
+                "QEMU_MAX_CPU_CORES",
+                self.qemu.max_cpu_cores.map(|v| v.to_string()),
+            ),
+            (
+                "MAX_TOTAL_MEMORY_MB",
+                self.qemu.max_total_memory_mb.map(|v| v.to_string()),
+            ),
+            (
+                "MAX_TOTAL_CORES",
+                self.qemu.max_total_cores.map(|v| v.to_string()),
+            ),
+            ("QEMU_BRIDGE", self.qemu.bridge),
+            (
+                "QEMU_SPICE_PORT_BASE",
+                self.qemu.spice_port_base.map(|v| v.to_string()),
+            ),
+            ("QEMU_KVM_MODE", self.qemu.kvm_mode),
+            ("OVMF_CODE_PATH", self.qemu.ovmf_code_path),
+            ("OVMF_VARS_PATH", self.qemu.ovmf_vars_path),
+            (
+                "IMAGE_UPLOAD_MAX_MB",
+                self.qemu.image_upload_max_mb.map(|v| v.to_string()),
+            ),
+            (
+                "GUAC_HTTPS",
+                self.guacamole
+                    .https
+                    .map(|v| if v { "1" } else { "0" }.to_string()),
+            ),
+            ("GUAC_HOST", self.guacamole.host),
+            ("GUAC_PORT", self.guacamole.port.map(|v| v.to_string())),
+            ("GUAC_TUNNEL_PATH", self.guacamole.tunnel_path),
+            ("GUAC_API_PATH", self.guacamole.api_path),
+            ("GUAC_CONNECTION_PREFIX", self.guacamole.connection_prefix),
+            ("GUAC_USER", self.guacamole.user),
+            ("GUAC_PASS", self.guacamole.pass),
+            (
+                "GUAC_REQUEST_TIMEOUT",
+                self.guacamole.request_timeout.map(|v| v.to_string()),
+            ),
+            ("GUAC_RECORDING_DIR", self.guacamole.recording_dir),
+        ];
+
+        entries
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name.to_string(), value)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The variables `from_env` requires, with every optional one unset
+    fn env_with(overrides: &[(&str, &str)]) -> HashMap<String, String> {
+        [
+            ("POSTGRES_USER", "lab"),
+            ("POSTGRES_PASSWORD", "secret"),
+            ("POSTGRES_HOST", "db"),
+            ("POSTGRES_PORT", "5432"),
+            ("BACKEND_DB", "network_lab"),
+            ("BACKEND_HOST", "0.0.0.0"),
+            ("BACKEND_PORT", "8000"),
+            ("IMAGE_DIR", "/srv/images"),
+            ("OVERLAY_DIR", "/srv/overlays"),
+            ("GUAC_HTTPS", "0"),
+            ("GUAC_HOST", "guacamole"),
+            ("GUAC_PORT", "8080"),
+            ("GUAC_TUNNEL_PATH", "/websocket-tunnel/"),
+            ("GUAC_API_PATH", "api"),
+            ("GUAC_CONNECTION_PREFIX", "lab"),
+            ("GUAC_USER", "guacadmin"),
+            ("GUAC_PASS", "guacadmin"),
+        ]
+        .iter()
+        .chain(overrides)
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn from_env_fills_in_defaults() {
+        // Blank values count as unset
+        let config = Config::from_env(&env_with(&[("DB_MAX_CONNECTIONS", " ")])).unwrap();
+
+        assert_eq!(
+            config.database.url,
+            "postgres://lab:secret@db:5432/network_lab"
+        );
+        assert_eq!(config.database.max_connections, DEFAULT_DB_MAX_CONNECTIONS);
+        assert_eq!(config.database.min_connections, DEFAULT_DB_MIN_CONNECTIONS);
+        assert_eq!(config.server.bind_address, "0.0.0.0:8000");
+        assert!(matches!(
+            config.server.cors_allowed_origins,
+            CorsOrigins::None
+        ));
+        assert_eq!(
+            config.server.request_body_limit,
+            DEFAULT_REQUEST_BODY_LIMIT_KB * 1024
+        );
+        assert_eq!(config.server.request_timeout, DEFAULT_REQUEST_TIMEOUT);
+        assert_eq!(config.qemu.console_dir, config.qemu.overlay_dir);
+        assert_eq!(config.qemu.shutdown_timeout, DEFAULT_SHUTDOWN_TIMEOUT);
+        assert_eq!(
+            config.qemu.image_upload_max_bytes,
+            DEFAULT_IMAGE_UPLOAD_MAX_MB * 1024 * 1024
+        );
+        assert_eq!(config.guacamole.url, "http://guacamole:8080/guacamole");
+        assert_eq!(config.guacamole.tunnel_path, "websocket-tunnel");
+    }
+
+    #[test]
+    fn from_env_rejects_invalid_values() {
+        let cases = [
+            ("POSTGRES_USER", ""),
+            ("BACKEND_PORT", "http"),
+            ("BACKEND_PORT", "65536"),
+            ("DB_MAX_CONNECTIONS", "0"),
+            ("DB_MAX_CONNECTIONS", "-1"),
+            ("DB_CONNECT_ATTEMPTS", "0"),
+            ("DB_ACQUIRE_TIMEOUT", "30s"),
+            ("REQUEST_BODY_LIMIT_KB", "0"),
+            ("REQUEST_BODY_LIMIT_KB", "18446744073709551615"),
+            ("IMAGE_UPLOAD_MAX_MB", "0"),
+            ("IMAGE_UPLOAD_MAX_MB", "18446744073709551615"),
+            ("QEMU_KVM_MODE", "sometimes"),
+            ("CORS_ALLOWED_ORIGINS", "lab.example.com"),
+            // Not longer than the default QEMU_SHUTDOWN_TIMEOUT
+            ("REQUEST_TIMEOUT", "30"),
+        ];
+
+        for (name, value) in cases {
+            let err = Config::from_env(&env_with(&[(name, value)])).unwrap_err();
+            assert_eq!(err.name, name, "{} = {:?}", name, value);
+        }
+    }
+
+    #[test]
+    fn from_env_rejects_more_min_than_max_connections() {
+        let err = Config::from_env(&env_with(&[
+            ("DB_MAX_CONNECTIONS", "2"),
+            ("DB_MIN_CONNECTIONS", "3"),
+        ]))
+        .unwrap_err();
+        assert_eq!(err.name, "DB_MIN_CONNECTIONS");
+
+        let config = Config::from_env(&env_with(&[
+            ("DB_MAX_CONNECTIONS", "3"),
+            ("DB_MIN_CONNECTIONS", "3"),
+        ]))
+        .unwrap();
+        assert_eq!(config.database.min_connections, 3);
+    }
+
+    #[test]
+    fn cors_origins_accept_any_or_a_list() {
+        assert!(matches!(" * ".parse(), Ok(CorsOrigins::Any)));
+
+        let cases: [(&str, &[&str]); 3] = [
+            ("https://lab.example.com", &["https://lab.example.com"]),
+            (
+                "http://localhost:5173, https://lab.example.com:8443",
+                &["http://localhost:5173", "https://lab.example.com:8443"],
+            ),
+            ("https://lab.example.com,,", &["https://lab.example.com"]),
+        ];
+        for (value, expected) in cases {
+            match value.parse() {
+                Ok(CorsOrigins::List(origins)) => assert_eq!(origins, expected, "{:?}", value),
+                other => panic!("{:?} gave {:?}", value, other),
+            }
+        }
+    }
+
+    #[test]
+    fn cors_origins_reject_anything_but_origins() {
+        for value in [
+            "lab.example.com",
+            "ftp://lab.example.com",
+            "https://",
+            "https://lab.example.com/",
+            "https://lab.example.com/app",
+            "https://lab.example.com, *",
+        ] {
+            assert!(value.parse::<CorsOrigins>().is_err(), "{:?}", value);
+        }
+    }
+}
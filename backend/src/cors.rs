@@ -0,0 +1,30 @@
+use axum::http::{HeaderName, Method, header};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::auth::API_KEY_HEADER;
+use crate::config::CorsOrigins;
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// CORS policy for browser frontends served from another origin.
+///
+/// Preflight requests are answered by the layer itself, before routing and
+/// authentication, so they need no API key.
+pub fn layer(origins: &CorsOrigins) -> CorsLayer {
+    let allow_origin = match origins {
+        // No Access-Control-Allow-Origin is sent, so browsers keep to same-origin
+        CorsOrigins::None => return CorsLayer::new(),
+        CorsOrigins::Any => AllowOrigin::any(),
+        CorsOrigins::List(origins) => AllowOrigin::list(origins.iter().cloned()),
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(API_KEY_HEADER),
+            REQUEST_ID_HEADER.clone(),
+        ])
+        .expose_headers([REQUEST_ID_HEADER.clone()])
+}
@@ -0,0 +1,137 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use reqwest::{Client, StatusCode, Url};
+use thiserror::Error;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tracing::{info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest a download may stall between two chunks; there is no limit on
+/// the whole transfer since images can be many gigabytes
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// Log progress every time this many more bytes have been written
+const PROGRESS_INTERVAL: u64 = 256 * 1024 * 1024;
+
+/// HTTP client for downloads, separate from Guacamole's which has a whole-request timeout
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Download timed out")]
+    Timeout,
+    #[error("Download failed: {0}")]
+    Request(reqwest::Error),
+    #[error("Server answered with status {0}")]
+    Status(StatusCode),
+    #[error("Failed to write download: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout
+        } else {
+            Self::Request(err)
+        }
+    }
+}
+
+/// A file that is deleted when dropped unless kept.
+///
+/// Axum drops a handler's future when the client disconnects, so tying
+/// cleanup to `Drop` also covers cancelled requests, not just errors.
+#[derive(Debug)]
+pub struct PartialFile {
+    path: Option<PathBuf>,
+}
+
+impl PartialFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    /// Keep the file on disk and stop tracking it
+    pub fn keep(mut self) -> PathBuf {
+        self.path.take().unwrap_or_default()
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take()
+            && let Err(e) = std::fs::remove_file(&path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove partial file {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Parse a URL to download from, accepting only http and https
+pub fn parse_url(url: &str) -> Result<Url, DownloadError> {
+    let parsed = Url::parse(url).map_err(|e| DownloadError::InvalidUrl(e.to_string()))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(DownloadError::InvalidUrl(format!(
+            "unsupported scheme `{}`, expected http or https",
+            scheme
+        ))),
+    }
+}
+
+/// Stream `url` into a new file at `dest`, returning the number of bytes written.
+///
+/// The body is written chunk by chunk rather than buffered. `dest` must not
+/// exist yet; on failure it is left for the caller's `PartialFile` to remove.
+pub async fn download_to(url: &Url, dest: &Path) -> Result<u64, DownloadError> {
+    let mut response = http_client().get(url.clone()).send().await?;
+    if !response.status().is_success() {
+        return Err(DownloadError::Status(response.status()));
+    }
+    let expected = response.content_length();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)
+        .await?;
+
+    let mut written = 0u64;
+    let mut next_report = PROGRESS_INTERVAL;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+        if written >= next_report {
+            match expected {
+                Some(total) => info!("Downloaded {} of {} bytes from {}", written, total, url),
+                None => info!("Downloaded {} bytes from {}", written, url),
+            }
+            next_report += PROGRESS_INTERVAL;
+        }
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+
+    Ok(written)
+}
+
+fn http_client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(|| {
+        Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .build()
+            .unwrap_or_else(|err| {
+                warn!("Failed to build download HTTP client, using defaults: {err}");
+                Client::new()
+            })
+    })
+}
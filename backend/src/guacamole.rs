@@ -1,22 +1,45 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use utoipa::ToSchema;
 
+use crate::config::GuacamoleConfig;
 use crate::qemu::{self, QemuError, QemuInstance};
 
 #[derive(Debug, thiserror::Error)]
 pub enum GuacamoleError {
     #[error("HTTP request failed: {0}")]
-    Request(#[from] reqwest::Error),
+    Request(reqwest::Error),
+    #[error("Request to Guacamole timed out")]
+    Timeout,
     #[error("Authentication failed")]
     AuthFailed,
     #[error("Failed to create connection: {0}")]
     ConnectionFailed(String),
     #[error("QEMU error: {0}")]
     Qemu(#[from] QemuError),
+    // `new` enables VNC itself, so a missing port surfaces as `Qemu`
+    #[allow(dead_code)]
     #[error("VNC is not enabled on the QEMU instance")]
     VncNotEnabled,
+    #[error("Guacamole rejected the session token")]
+    TokenRejected,
+}
+
+impl From<reqwest::Error> for GuacamoleError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout
+        } else {
+            Self::Request(err)
+        }
+    }
 }
 
 /// Represents a Guacamole connection with all URLs needed for UI integration
@@ -30,10 +53,43 @@ pub struct GuacamoleConnection {
     pub client_url: String,
     pub websocket_url: String,
     pub tunnel_url: String,
-    pub vnc_port: u16,
+    /// Guacamole protocol of the connection (`vnc` or `ssh`)
+    pub protocol: String,
+    /// Identifier of the connection group holding this connection (`ROOT` for the top level)
+    pub parent_identifier: String,
+    pub port: u16,
+    /// Parameters the connection was registered with, sent again on update
+    #[serde(skip)]
+    parameters: ConnectionParameters,
 }
 
-#[derive(Debug, Deserialize)]
+/// Identifier Guacamole gives the top-level connection group
+const ROOT_GROUP: &str = "ROOT";
+
+/// Guacamole expires sessions after 60 minutes of inactivity by default;
+/// stop reusing a token well before that
+const TOKEN_IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Attempts made for idempotent requests before giving up
+const MAX_REQUEST_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled (plus jitter) for each later one
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// HTTP client shared by all Guacamole operations so connections and TLS sessions are reused
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Session tokens keyed by API URL and username, shared by all connection operations
+static TOKEN_CACHE: LazyLock<Mutex<HashMap<(String, String), CachedToken>>> =
+    LazyLock::new(Default::default);
+
+struct CachedToken {
+    auth_response: AuthResponse,
+    last_used: Instant,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 struct AuthResponse {
     #[serde(rename = "authToken")]
     auth_token: String,
@@ -51,13 +107,192 @@ struct CreateConnectionRequest {
     attributes: ConnectionAttributes,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 struct ConnectionParameters {
     hostname: String,
     port: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    #[serde(rename = "private-key", skip_serializing_if = "Option::is_none")]
+    private_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passphrase: Option<String>,
+    #[serde(rename = "color-depth", skip_serializing_if = "Option::is_none")]
+    color_depth: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    autoretry: Option<String>,
+    #[serde(rename = "swap-red-blue", skip_serializing_if = "Option::is_none")]
+    swap_red_blue: Option<String>,
+    #[serde(rename = "recording-path", skip_serializing_if = "Option::is_none")]
+    recording_path: Option<String>,
+    #[serde(rename = "recording-name", skip_serializing_if = "Option::is_none")]
+    recording_name: Option<String>,
+    #[serde(
+        rename = "create-recording-path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    create_recording_path: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+impl ConnectionParameters {
+    fn new(hostname: &str, port: u16) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            port: port.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Apply VNC display tuning; unset options keep Guacamole's defaults
+    fn with_vnc_display(self, display: &VncDisplayOptions) -> Self {
+        Self {
+            color_depth: display.color_depth.map(|depth| u8::from(depth).to_string()),
+            cursor: display.cursor.map(|cursor| cursor.as_str().to_string()),
+            autoretry: display.autoretry.map(|retries| retries.to_string()),
+            swap_red_blue: display.swap_red_blue.map(|swap| swap.to_string()),
+            ..self
+        }
+    }
+
+    /// Have guacd record sessions of the connection, if `recording` is set
+    fn with_recording(self, recording: Option<&SessionRecording>) -> Self {
+        match recording {
+            Some(recording) => Self {
+                recording_path: Some(recording.path.clone()),
+                recording_name: Some(recording.name.clone()),
+                create_recording_path: Some("true".into()),
+                ..self
+            },
+            None => self,
+        }
+    }
+}
+
+/// Where a new connection is filed and whether its sessions are recorded
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionOptions {
+    /// Connection group to place the connection in, created if missing; the
+    /// connection goes at the top level when unset
+    pub group: Option<String>,
+    pub recording: Option<SessionRecording>,
+    /// Connection the caller owns, such as the one a node records, to update
+    /// in place if it still exists; no other connection is ever reused
+    pub reuse: Option<String>,
+}
+
+/// Session recording written by guacd, which needs write access to `path`
+/// on its own filesystem; the backend never touches the files
+#[derive(Debug, Clone)]
+pub struct SessionRecording {
+    /// Directory the recording is written to, created by guacd if missing
+    pub path: String,
+    /// File name, which may use Guacamole tokens such as `${GUAC_DATE}`
+    pub name: String,
+}
+
+impl SessionRecording {
+    /// Record into `GUAC_RECORDING_DIR`, or `None` when it isn't set.
+    ///
+    /// Without a `name`, recordings are named after the connection and the
+    /// time the session started so later sessions don't overwrite earlier ones.
+    pub fn in_configured_dir(
+        config: &GuacamoleConfig,
+        connection_name: &str,
+        name: Option<&str>,
+    ) -> Option<Self> {
+        let path = config.recording_dir.as_deref()?;
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => format!(
+                "{}-${{GUAC_DATE}}-${{GUAC_TIME}}",
+                sanitize_identifier(connection_name)
+            ),
+        };
+        Some(Self {
+            path: path.to_string(),
+            name,
+        })
+    }
+}
+
+/// Optional display tuning for VNC connections
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct VncDisplayOptions {
+    /// Colour depth in bits per pixel; lower depths save bandwidth
+    #[schema(value_type = Option<u8>)]
+    pub color_depth: Option<ColorDepth>,
+    /// Whether the mouse cursor is drawn locally or by the VNC server
+    pub cursor: Option<VncCursor>,
+    /// Times Guacamole retries a failed VNC connection
+    pub autoretry: Option<u32>,
+    /// Swap the red and blue channels for servers that send them reversed
+    pub swap_red_blue: Option<bool>,
+}
+
+/// Colour depths supported by Guacamole's VNC client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum ColorDepth {
+    Bits8 = 8,
+    Bits16 = 16,
+    Bits24 = 24,
+    Bits32 = 32,
+}
+
+impl From<ColorDepth> for u8 {
+    fn from(depth: ColorDepth) -> Self {
+        depth as u8
+    }
+}
+
+impl TryFrom<u8> for ColorDepth {
+    type Error = String;
+
+    fn try_from(bits: u8) -> Result<Self, Self::Error> {
+        match bits {
+            8 => Ok(Self::Bits8),
+            16 => Ok(Self::Bits16),
+            24 => Ok(Self::Bits24),
+            32 => Ok(Self::Bits32),
+            _ => Err(format!(
+                "unsupported color depth {}, expected 8, 16, 24 or 32",
+                bits
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VncCursor {
+    Local,
+    Remote,
+}
+
+impl VncCursor {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Remote => "remote",
+        }
+    }
+}
+
+/// Credentials Guacamole uses to log into an SSH server.
+/// Any field left as `None` is prompted for interactively by Guacamole.
+#[derive(Debug, Clone, Default)]
+pub struct SshCredentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
 struct ConnectionAttributes {
     #[serde(rename = "max-connections")]
     max_connections: String,
@@ -65,9 +300,99 @@ struct ConnectionAttributes {
     max_connections_per_user: String,
 }
 
+/// A connection as Guacamole stores it, sent back whole on update since
+/// Guacamole replaces whatever is left out
+#[derive(Debug, Serialize, Deserialize)]
+struct ConnectionDefinition {
+    name: String,
+    #[serde(rename = "parentIdentifier")]
+    parent_identifier: String,
+    protocol: String,
+    /// Not returned with the connection itself; fetched separately
+    #[serde(default)]
+    parameters: HashMap<String, String>,
+    #[serde(default)]
+    attributes: HashMap<String, Option<String>>,
+}
+
 #[derive(Debug, Deserialize)]
 struct CreateConnectionResponse {
     identifier: String,
+    /// Set when an existing connection was found instead of a new one created
+    #[serde(skip)]
+    reused: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateConnectionGroupRequest {
+    name: String,
+    #[serde(rename = "parentIdentifier")]
+    parent_identifier: String,
+    /// `ORGANIZATIONAL` groups only organize connections; `BALANCING` ones
+    /// would spread users across their members
+    #[serde(rename = "type")]
+    group_type: String,
+    attributes: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateSharingProfileRequest {
+    name: String,
+    #[serde(rename = "primaryConnectionIdentifier")]
+    primary_connection_identifier: String,
+    parameters: HashMap<String, String>,
+    attributes: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SharingProfileSummary {
+    identifier: String,
+    name: String,
+    #[serde(rename = "primaryConnectionIdentifier")]
+    primary_connection_identifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActiveConnectionSummary {
+    identifier: String,
+    #[serde(rename = "connectionIdentifier")]
+    connection_identifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SharingCredentials {
+    values: HashMap<String, String>,
+}
+
+/// A sharing profile letting others join a connection's sessions
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GuacamoleShare {
+    pub sharing_profile_id: String,
+    pub connection_id: String,
+    pub read_only: bool,
+    /// Link joining the connection's current session. Share links belong to
+    /// a live session, so this is only set while someone is connected and
+    /// stops working once they disconnect.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConnectionGroupSummary {
+    identifier: String,
+    name: String,
+    #[serde(rename = "parentIdentifier")]
+    parent_identifier: String,
+}
+
+/// A connection as known to Guacamole, independent of any node
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct GuacamoleConnectionSummary {
+    pub identifier: String,
+    pub name: String,
+    pub protocol: String,
+    /// Identifier of the connection group holding this connection (`ROOT` for the top level)
+    #[serde(rename(deserialize = "parentIdentifier"))]
+    pub parent_identifier: String,
 }
 
 impl GuacamoleConnection {
@@ -79,68 +404,49 @@ impl GuacamoleConnection {
     /// 3. Register the VNC connection with Guacamole
     ///
     /// # Arguments
-    /// * `env` - Environment variables containing Guacamole configuration
+    /// * `config` - Guacamole settings
     /// * `connection_name` - Name for the Guacamole connection
     /// * `instance` - Mutable reference to the QEMU instance to bind
     /// * `vnc_display` - Optional VNC display number to use (if VNC needs to be enabled)
+    /// * `display` - Colour depth, cursor and retry tuning for the connection
+    /// * `password` - VNC password to require; a random one is used when unset
+    /// * `options` - Connection group and session recording settings
     ///
     /// # Returns
     /// A `GuacamoleConnection` with all URLs needed for UI integration
     pub async fn new(
-        env: &HashMap<String, String>,
+        config: &GuacamoleConfig,
         connection_name: &str,
         instance: &mut QemuInstance,
         vnc_display: Option<u16>,
+        display: VncDisplayOptions,
+        password: Option<String>,
+        options: ConnectionOptions,
     ) -> Result<Self, GuacamoleError> {
         if instance.vnc_port.is_none() {
             let display = vnc_display.unwrap_or(0);
-            qemu::enable_vnc(instance, display).await?;
+            qemu::enable_vnc(instance, display, password).await?;
+        } else if let Some(password) = password {
+            qemu::set_vnc_password(instance, password).await?;
         }
 
         // Get VNC connection info from the QEMU instance
         let (vnc_host, vnc_port) = qemu::get_vnc_info(instance)?;
 
-        // Load env and build URL/identifier data
-        let env_cfg = Self::build_env_config(env, connection_name);
-
-        let client = Client::new();
-
-        // Authenticate with Guacamole
-        let auth_response = Self::authenticate(
-            &client,
-            &env_cfg.api_url,
-            &env_cfg.username,
-            &env_cfg.password,
-        )
-        .await?;
-
-        // Create VNC connection in Guacamole
-        let create_response = Self::create_connection(
-            &client,
-            &env_cfg.api_url,
-            &auth_response,
+        let parameters = ConnectionParameters {
+            password: instance.vnc_password.clone(),
+            ..ConnectionParameters::new(&vnc_host, vnc_port).with_vnc_display(&display)
+        };
+        Self::register(
+            config,
             connection_name,
+            "vnc",
             &vnc_host,
             vnc_port,
+            parameters,
+            options,
         )
-        .await?;
-
-        let client_url = format!(
-            "{}/#/client/{}",
-            env_cfg.base_http_url, env_cfg.client_identifier
-        );
-
-        Ok(Self {
-            connection_name: connection_name.to_string(),
-            connection_key: env_cfg.connection_key,
-            connection_id: create_response.identifier,
-            client_identifier: env_cfg.client_identifier,
-            api_url: env_cfg.api_url,
-            client_url,
-            websocket_url: env_cfg.websocket_url,
-            tunnel_url: env_cfg.tunnel_url,
-            vnc_port,
-        })
+        .await
     }
 
     /// Create a Guacamole connection from explicit VNC host and port.
@@ -148,42 +454,386 @@ impl GuacamoleConnection {
     /// Use this when you already have VNC running and just need to register it with Guacamole.
     ///
     /// # Arguments
-    /// * `env` - Environment variables containing Guacamole configuration
+    /// * `config` - Guacamole settings
     /// * `connection_name` - Name for the Guacamole connection
     /// * `vnc_host` - The VNC server hostname/IP
     /// * `vnc_port` - The VNC server port
+    /// * `display` - Colour depth, cursor and retry tuning for the connection
+    /// * `password` - Password of the VNC server, if it requires one
+    /// * `options` - Connection group and session recording settings
     ///
     /// # Returns
     /// A `GuacamoleConnection` with all URLs needed for UI integration
     pub async fn from_vnc(
-        env: &HashMap<String, String>,
+        config: &GuacamoleConfig,
         connection_name: &str,
         vnc_host: &str,
         vnc_port: u16,
+        display: VncDisplayOptions,
+        password: Option<String>,
+        options: ConnectionOptions,
     ) -> Result<Self, GuacamoleError> {
-        // Load env and build URL/identifier data
-        let env_cfg = Self::build_env_config(env, connection_name);
-
-        let client = Client::new();
-
-        // Authenticate with Guacamole
-        let auth_response = Self::authenticate(
-            &client,
-            &env_cfg.api_url,
-            &env_cfg.username,
-            &env_cfg.password,
-        )
-        .await?;
-
-        // Create VNC connection in Guacamole
-        let create_response = Self::create_connection(
-            &client,
-            &env_cfg.api_url,
-            &auth_response,
+        let parameters = ConnectionParameters {
+            password,
+            ..ConnectionParameters::new(vnc_host, vnc_port).with_vnc_display(&display)
+        };
+        Self::register(
+            config,
             connection_name,
+            "vnc",
             vnc_host,
             vnc_port,
+            parameters,
+            options,
+        )
+        .await
+    }
+
+    /// Create a Guacamole SSH connection to the given host and port.
+    ///
+    /// Use this for headless nodes that are reached through a terminal rather than VNC.
+    ///
+    /// # Arguments
+    /// * `config` - Guacamole settings
+    /// * `connection_name` - Name for the Guacamole connection
+    /// * `ssh_host` - The SSH server hostname/IP
+    /// * `ssh_port` - The SSH server port
+    /// * `credentials` - Credentials Guacamole should log in with
+    /// * `options` - Connection group and session recording settings
+    ///
+    /// # Returns
+    /// A `GuacamoleConnection` with all URLs needed for UI integration
+    pub async fn from_ssh(
+        config: &GuacamoleConfig,
+        connection_name: &str,
+        ssh_host: &str,
+        ssh_port: u16,
+        credentials: SshCredentials,
+        options: ConnectionOptions,
+    ) -> Result<Self, GuacamoleError> {
+        let parameters = ConnectionParameters {
+            username: credentials.username,
+            password: credentials.password,
+            private_key: credentials.private_key,
+            passphrase: credentials.passphrase,
+            ..ConnectionParameters::new(ssh_host, ssh_port)
+        };
+        Self::register(
+            config,
+            connection_name,
+            "ssh",
+            ssh_host,
+            ssh_port,
+            parameters,
+            options,
         )
+        .await
+    }
+
+    /// List every connection Guacamole currently knows about.
+    ///
+    /// Useful for spotting connections whose backing node no longer exists.
+    pub async fn list_connections(
+        config: &GuacamoleConfig,
+    ) -> Result<Vec<GuacamoleConnectionSummary>, GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, "");
+
+        let client = http_client(config);
+
+        let api_url = env_cfg.api_url.as_str();
+        Self::with_token(client, &env_cfg, |auth_response| async move {
+            Self::fetch_connections(client, api_url, &auth_response).await
+        })
+        .await
+    }
+
+    /// Get the identifier of the top-level connection group named `name`,
+    /// creating the group if it doesn't exist yet.
+    ///
+    /// Groups keep the connections of a lab or student together instead of
+    /// in one flat list.
+    pub async fn create_connection_group(
+        config: &GuacamoleConfig,
+        name: &str,
+    ) -> Result<String, GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, name);
+
+        let client = http_client(config);
+
+        let api_url = env_cfg.api_url.as_str();
+        Self::with_token(client, &env_cfg, |auth_response| async move {
+            if let Some(identifier) =
+                Self::find_connection_group(client, api_url, &auth_response, name).await?
+            {
+                return Ok(identifier);
+            }
+
+            let create_request = CreateConnectionGroupRequest {
+                name: name.to_string(),
+                parent_identifier: ROOT_GROUP.into(),
+                group_type: "ORGANIZATIONAL".into(),
+                attributes: HashMap::new(),
+            };
+            let response = client
+                .post(format!(
+                    "{}/session/data/{}/connectionGroups",
+                    api_url, auth_response.data_source
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+                .json(&create_request)
+                .send()
+                .await?;
+            let created: CreateConnectionResponse = check_response(response)?.json().await?;
+            debug!(
+                "Created Guacamole connection group {} ({})",
+                name, created.identifier
+            );
+            Ok(created.identifier)
+        })
+        .await
+    }
+
+    /// Point this connection at a new host and port, keeping its identifier.
+    ///
+    /// Existing client URLs stay valid, which makes this preferable to
+    /// recreating the connection when a node comes back on a different VNC port.
+    /// Guacamole replaces all parameters on update, so the others the
+    /// connection was registered with (password, display tuning, recording,
+    /// SSH credentials) are sent along unchanged.
+    pub async fn update(
+        &mut self,
+        config: &GuacamoleConfig,
+        new_host: &str,
+        new_port: u16,
+    ) -> Result<(), GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, &self.connection_name);
+
+        let client = http_client(config);
+
+        let update_request = CreateConnectionRequest {
+            name: self.connection_name.clone(),
+            parent_identifier: self.parent_identifier.clone(),
+            protocol: self.protocol.clone(),
+            parameters: ConnectionParameters {
+                hostname: new_host.to_string(),
+                port: new_port.to_string(),
+                ..self.parameters.clone()
+            },
+            attributes: ConnectionAttributes::default(),
+        };
+
+        let (api_url, connection_id) = (self.api_url.as_str(), self.connection_id.as_str());
+        Self::with_token(client, &env_cfg, |auth_response| {
+            let update_request = &update_request;
+            async move {
+                let response = client
+                    .put(format!(
+                        "{}/session/data/{}/connections/{}",
+                        api_url, auth_response.data_source, connection_id
+                    ))
+                    .header("Guacamole-Token", &auth_response.auth_token)
+                    .json(update_request)
+                    .send()
+                    .await?;
+                check_response(response)?;
+                Ok(())
+            }
+        })
+        .await?;
+
+        self.parameters = update_request.parameters;
+        self.port = new_port;
+        Ok(())
+    }
+
+    /// Check that the Guacamole web application answers at its configured URL
+    pub async fn ping(config: &GuacamoleConfig) -> Result<(), GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, "");
+
+        let client = http_client(config);
+        send_with_retry(|| client.get(&env_cfg.base_http_url))
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Delete this connection from Guacamole
+    pub async fn delete(&self, config: &GuacamoleConfig) -> Result<(), GuacamoleError> {
+        Self::delete_by_id(config, &self.connection_id).await
+    }
+
+    /// Create a sharing profile for this connection and, if it has an active
+    /// session, a link others can use to join it.
+    ///
+    /// Read-only shares let viewers watch without sending input.
+    #[allow(dead_code)] // Routes only know the connection id, see `create_share_by_id`
+    pub async fn create_share(
+        &self,
+        config: &GuacamoleConfig,
+        read_only: bool,
+    ) -> Result<GuacamoleShare, GuacamoleError> {
+        Self::create_share_by_id(config, &self.connection_id, read_only).await
+    }
+
+    /// Create a sharing profile knowing only the connection's identifier.
+    ///
+    /// A profile with the same access level is reused rather than duplicated.
+    pub async fn create_share_by_id(
+        config: &GuacamoleConfig,
+        connection_id: &str,
+        read_only: bool,
+    ) -> Result<GuacamoleShare, GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, connection_id);
+
+        let client = http_client(config);
+
+        let name = if read_only {
+            "read-only share"
+        } else {
+            "share"
+        };
+        let (api_url, base_http_url) = (env_cfg.api_url.as_str(), env_cfg.base_http_url.as_str());
+        Self::with_token(client, &env_cfg, |auth_response| async move {
+            let existing = Self::fetch_sharing_profiles(client, api_url, &auth_response)
+                .await?
+                .into_iter()
+                .find(|profile| {
+                    profile.primary_connection_identifier == connection_id && profile.name == name
+                });
+            let sharing_profile_id = match existing {
+                Some(profile) => profile.identifier,
+                None => {
+                    let mut parameters = HashMap::new();
+                    if read_only {
+                        parameters.insert("read-only".to_string(), "true".to_string());
+                    }
+                    let create_request = CreateSharingProfileRequest {
+                        name: name.to_string(),
+                        primary_connection_identifier: connection_id.to_string(),
+                        parameters,
+                        attributes: HashMap::new(),
+                    };
+                    let response = client
+                        .post(format!(
+                            "{}/session/data/{}/sharingProfiles",
+                            api_url, auth_response.data_source
+                        ))
+                        .header("Guacamole-Token", &auth_response.auth_token)
+                        .json(&create_request)
+                        .send()
+                        .await?;
+                    let created: CreateConnectionResponse =
+                        check_response(response)?.json().await?;
+                    created.identifier
+                }
+            };
+
+            let url = Self::share_key(
+                client,
+                api_url,
+                &auth_response,
+                connection_id,
+                &sharing_profile_id,
+            )
+            .await?
+            .map(|key| format!("{}/#/?key={}", base_http_url, key));
+
+            Ok(GuacamoleShare {
+                sharing_profile_id,
+                connection_id: connection_id.to_string(),
+                read_only,
+                url,
+            })
+        })
+        .await
+    }
+
+    /// Delete a connection from Guacamole knowing only its identifier.
+    ///
+    /// Useful when only the `guacamole_connection_id` stored on a node is
+    /// available, e.g. after a restart of the backend. The API URL and
+    /// credentials come from `config`, the same as when the connection was created.
+    pub async fn delete_by_id(
+        config: &GuacamoleConfig,
+        connection_id: &str,
+    ) -> Result<(), GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, connection_id);
+
+        let client = http_client(config);
+
+        Self::with_token(client, &env_cfg, |auth_response| {
+            Self::delete_connection(client, &env_cfg.api_url, auth_response, connection_id)
+        })
+        .await
+    }
+
+    /// Give the connection with the given identifier a new display name.
+    ///
+    /// Needs only the identifier: the connection's parameters, group and
+    /// attributes are read back from Guacamole and kept as they are.
+    pub async fn rename_by_id(
+        config: &GuacamoleConfig,
+        connection_id: &str,
+        new_name: &str,
+    ) -> Result<(), GuacamoleError> {
+        let env_cfg = Self::build_env_config(config, new_name);
+
+        let client = http_client(config);
+
+        Self::with_token(client, &env_cfg, |auth_response| {
+            Self::update_connection(
+                client,
+                &env_cfg.api_url,
+                auth_response,
+                connection_id,
+                new_name,
+            )
+        })
+        .await
+    }
+
+    // Private helpers to reduce duplication between the constructors.
+
+    /// Register a connection to `host`:`port` and assemble its URLs
+    ///
+    /// Files it in `options.group` and adds `options.recording` to
+    /// `parameters`.
+    async fn register(
+        config: &GuacamoleConfig,
+        connection_name: &str,
+        protocol: &str,
+        host: &str,
+        port: u16,
+        parameters: ConnectionParameters,
+        options: ConnectionOptions,
+    ) -> Result<Self, GuacamoleError> {
+        // Build URL/identifier data
+        let env_cfg = Self::build_env_config(config, connection_name);
+
+        let client = http_client(config);
+
+        // Create the connection in Guacamole, authenticating as needed
+        let parameters = parameters.with_recording(options.recording.as_ref());
+        let parent_identifier = match options.group.as_deref() {
+            Some(group) => Self::create_connection_group(config, group).await?,
+            None => ROOT_GROUP.to_string(),
+        };
+        let create_request = CreateConnectionRequest {
+            name: connection_name.to_string(),
+            parent_identifier,
+            protocol: protocol.to_string(),
+            parameters,
+            attributes: ConnectionAttributes::default(),
+        };
+        let create_response = Self::with_token(client, &env_cfg, |auth_response| {
+            Self::create_connection(
+                client,
+                &env_cfg.api_url,
+                auth_response,
+                &create_request,
+                options.reuse.as_deref(),
+            )
+        })
         .await?;
 
         let client_url = format!(
@@ -191,7 +841,7 @@ impl GuacamoleConnection {
             env_cfg.base_http_url, env_cfg.client_identifier
         );
 
-        Ok(Self {
+        let mut connection = Self {
             connection_name: connection_name.to_string(),
             connection_key: env_cfg.connection_key,
             connection_id: create_response.identifier,
@@ -200,80 +850,34 @@ impl GuacamoleConnection {
             client_url,
             websocket_url: env_cfg.websocket_url,
             tunnel_url: env_cfg.tunnel_url,
-            vnc_port,
-        })
-    }
-
-    /// Delete this connection from Guacamole
-    pub async fn delete(&self, env: &HashMap<String, String>) -> Result<(), GuacamoleError> {
-        let username = env.get("GUAC_USER").unwrap();
-        let password = env.get("GUAC_PASS").unwrap();
-
-        let client = Client::new();
-
-        let auth_response: AuthResponse = client
-            .post(format!("{}/tokens", self.api_url))
-            .form(&[("username", username), ("password", password)])
-            .send()
-            .await?
-            .error_for_status()
-            .map_err(|_| GuacamoleError::AuthFailed)?
-            .json()
-            .await?;
-
-        client
-            .delete(format!(
-                "{}/session/data/{}/connections/{}",
-                self.api_url, auth_response.data_source, self.connection_id
-            ))
-            .header("Guacamole-Token", &auth_response.auth_token)
-            .send()
-            .await?
-            .error_for_status()
-            .map_err(|e| GuacamoleError::ConnectionFailed(e.to_string()))?;
-
-        Ok(())
+            protocol: create_request.protocol,
+            parent_identifier: create_request.parent_identifier,
+            port,
+            parameters: create_request.parameters,
+        };
+        // A reused connection still holds whatever an earlier run registered
+        if create_response.reused {
+            connection.update(config, host, port).await?;
+        }
+        Ok(connection)
     }
 
-    // Private helpers to reduce duplication between `new` and `from_vnc`.
-
-    fn build_env_config(env: &HashMap<String, String>, connection_name: &str) -> EnvConfig {
-        let base_http_url = env
-            .get("GUAC_URL")
-            .unwrap()
-            .trim()
-            .trim_end_matches('/')
-            .to_string();
-
-        // local-only values used to compute URLs; not kept on the returned struct
-        let tunnel_path = env
-            .get("GUAC_TUNNEL_PATH")
-            .unwrap()
-            .trim()
-            .trim_matches('/')
-            .to_string();
-        let api_path = env
-            .get("GUAC_API_PATH")
-            .unwrap()
-            .trim()
-            .trim_matches('/')
-            .to_string();
+    fn build_env_config(config: &GuacamoleConfig, connection_name: &str) -> EnvConfig {
+        let base_http_url = config.url.clone();
 
         // prefix is only used to derive the client identifier
-        let connection_prefix = sanitize_identifier(env.get("GUAC_CONNECTION_PREFIX").unwrap());
-        let username = env.get("GUAC_USER").unwrap().to_string();
-        let password = env.get("GUAC_PASS").unwrap().to_string();
+        let connection_prefix = sanitize_identifier(&config.connection_prefix);
 
         let connection_key = sanitize_identifier(connection_name);
         let client_identifier = format!("{}-{}", connection_prefix, connection_key);
-        let api_url = format!("{}/{}", base_http_url, api_path);
-        let tunnel_url = format!("{}/{}", base_http_url, tunnel_path);
-        let websocket_url = compute_websocket_url(&base_http_url, &tunnel_path);
+        let api_url = format!("{}/{}", base_http_url, config.api_path);
+        let tunnel_url = format!("{}/{}", base_http_url, config.tunnel_path);
+        let websocket_url = compute_websocket_url(&base_http_url, &config.tunnel_path);
 
         EnvConfig {
             base_http_url,
-            username,
-            password,
+            username: config.user.clone(),
+            password: config.pass.clone(),
             connection_key,
             client_identifier,
             api_url,
@@ -282,62 +886,329 @@ impl GuacamoleConnection {
         }
     }
 
+    /// Get a session token for the admin account, reusing a cached one while it is fresh
     async fn authenticate(
         client: &Client,
         api_url: &str,
         username: &str,
         password: &str,
     ) -> Result<AuthResponse, GuacamoleError> {
-        let auth_response: AuthResponse = client
-            .post(format!("{}/tokens", api_url))
-            .form(&[("username", username), ("password", password)])
-            .send()
-            .await?
-            .error_for_status()
-            .map_err(|_| GuacamoleError::AuthFailed)?
-            .json()
-            .await?;
+        let key = (api_url.to_string(), username.to_string());
+
+        if let Some(cached) = TOKEN_CACHE.lock().unwrap().get_mut(&key)
+            && cached.last_used.elapsed() < TOKEN_IDLE_TTL
+        {
+            cached.last_used = Instant::now();
+            return Ok(cached.auth_response.clone());
+        }
+
+        // Issuing a token has no side effects worth guarding, so it is retried
+        // like a GET
+        let auth_response: AuthResponse = send_with_retry(|| {
+            client
+                .post(format!("{}/tokens", api_url))
+                .form(&[("username", username), ("password", password)])
+        })
+        .await?
+        .error_for_status()
+        .map_err(|_| GuacamoleError::AuthFailed)?
+        .json()
+        .await?;
+
+        TOKEN_CACHE.lock().unwrap().insert(
+            key,
+            CachedToken {
+                auth_response: auth_response.clone(),
+                last_used: Instant::now(),
+            },
+        );
         Ok(auth_response)
     }
 
+    /// Run `op` with a session token, re-authenticating and retrying once if
+    /// Guacamole rejects a cached token that expired on its side
+    async fn with_token<T, F, Fut>(
+        client: &Client,
+        env_cfg: &EnvConfig,
+        op: F,
+    ) -> Result<T, GuacamoleError>
+    where
+        F: Fn(AuthResponse) -> Fut,
+        Fut: Future<Output = Result<T, GuacamoleError>>,
+    {
+        let auth_response = Self::authenticate(
+            client,
+            &env_cfg.api_url,
+            &env_cfg.username,
+            &env_cfg.password,
+        )
+        .await?;
+
+        match op(auth_response).await {
+            Err(GuacamoleError::TokenRejected) => {
+                debug!("Guacamole rejected the cached token, re-authenticating");
+                TOKEN_CACHE
+                    .lock()
+                    .unwrap()
+                    .remove(&(env_cfg.api_url.clone(), env_cfg.username.clone()));
+
+                let auth_response = Self::authenticate(
+                    client,
+                    &env_cfg.api_url,
+                    &env_cfg.username,
+                    &env_cfg.password,
+                )
+                .await?;
+                op(auth_response).await
+            }
+            result => result,
+        }
+    }
+
     async fn create_connection(
         client: &Client,
         api_url: &str,
-        auth_response: &AuthResponse,
-        connection_name: &str,
-        vnc_host: &str,
-        vnc_port: u16,
+        auth_response: AuthResponse,
+        create_request: &CreateConnectionRequest,
+        reuse: Option<&str>,
     ) -> Result<CreateConnectionResponse, GuacamoleError> {
-        let create_request = CreateConnectionRequest {
-            name: connection_name.to_string(),
-            parent_identifier: "ROOT".into(),
-            protocol: "vnc".into(),
-            parameters: ConnectionParameters {
-                hostname: vnc_host.to_string(),
-                port: vnc_port.to_string(),
-            },
-            attributes: ConnectionAttributes {
-                max_connections: "".to_string(),
-                max_connections_per_user: "".to_string(),
-            },
-        };
+        // Update the caller's own connection instead of piling up duplicates.
+        // Never match by name: that would hand over whichever connection the
+        // caller chose to name.
+        if let Some(identifier) = reuse
+            && Self::connection_exists(
+                client,
+                api_url,
+                &auth_response,
+                identifier,
+                &create_request.protocol,
+            )
+            .await?
+        {
+            debug!(
+                "Reusing existing Guacamole connection {} for {}",
+                identifier, create_request.name
+            );
+            return Ok(CreateConnectionResponse {
+                identifier: identifier.to_string(),
+                reused: true,
+            });
+        }
 
-        let create_response: CreateConnectionResponse = client
+        let response = client
             .post(format!(
                 "{}/session/data/{}/connections",
                 api_url, auth_response.data_source
             ))
             .header("Guacamole-Token", &auth_response.auth_token)
-            .json(&create_request)
+            .json(create_request)
             .send()
-            .await?
-            .error_for_status()
-            .map_err(|e| GuacamoleError::ConnectionFailed(e.to_string()))?
-            .json()
             .await?;
+        let create_response: CreateConnectionResponse = check_response(response)?.json().await?;
 
         Ok(create_response)
     }
+
+    /// Whether a connection with the given identifier and protocol exists
+    async fn connection_exists(
+        client: &Client,
+        api_url: &str,
+        auth_response: &AuthResponse,
+        identifier: &str,
+        protocol: &str,
+    ) -> Result<bool, GuacamoleError> {
+        let connections = Self::fetch_connections(client, api_url, auth_response).await?;
+
+        Ok(connections
+            .iter()
+            .any(|summary| summary.identifier == identifier && summary.protocol == protocol))
+    }
+
+    /// Look up the identifier of a top-level connection group with the given name
+    async fn find_connection_group(
+        client: &Client,
+        api_url: &str,
+        auth_response: &AuthResponse,
+        name: &str,
+    ) -> Result<Option<String>, GuacamoleError> {
+        let response = send_with_retry(|| {
+            client
+                .get(format!(
+                    "{}/session/data/{}/connectionGroups",
+                    api_url, auth_response.data_source
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+
+        // Keyed by identifier like the connection list
+        let groups: HashMap<String, ConnectionGroupSummary> =
+            check_response(response)?.json().await?;
+        Ok(groups
+            .into_values()
+            .find(|group| group.name == name && group.parent_identifier == ROOT_GROUP)
+            .map(|group| group.identifier))
+    }
+
+    async fn fetch_connections(
+        client: &Client,
+        api_url: &str,
+        auth_response: &AuthResponse,
+    ) -> Result<Vec<GuacamoleConnectionSummary>, GuacamoleError> {
+        let response = send_with_retry(|| {
+            client
+                .get(format!(
+                    "{}/session/data/{}/connections",
+                    api_url, auth_response.data_source
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+
+        // Guacamole returns an object keyed by identifier
+        let connections: HashMap<String, GuacamoleConnectionSummary> =
+            check_response(response)?.json().await?;
+        Ok(connections.into_values().collect())
+    }
+
+    async fn fetch_sharing_profiles(
+        client: &Client,
+        api_url: &str,
+        auth_response: &AuthResponse,
+    ) -> Result<Vec<SharingProfileSummary>, GuacamoleError> {
+        let response = send_with_retry(|| {
+            client
+                .get(format!(
+                    "{}/session/data/{}/sharingProfiles",
+                    api_url, auth_response.data_source
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+
+        let profiles: HashMap<String, SharingProfileSummary> =
+            check_response(response)?.json().await?;
+        Ok(profiles.into_values().collect())
+    }
+
+    /// Get a share key for the connection's first active session, if it has one
+    async fn share_key(
+        client: &Client,
+        api_url: &str,
+        auth_response: &AuthResponse,
+        connection_id: &str,
+        sharing_profile_id: &str,
+    ) -> Result<Option<String>, GuacamoleError> {
+        let response = send_with_retry(|| {
+            client
+                .get(format!(
+                    "{}/session/data/{}/activeConnections",
+                    api_url, auth_response.data_source
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+        let active: HashMap<String, ActiveConnectionSummary> =
+            check_response(response)?.json().await?;
+        let Some(session) = active
+            .into_values()
+            .find(|session| session.connection_identifier == connection_id)
+        else {
+            return Ok(None);
+        };
+
+        let response = send_with_retry(|| {
+            client
+                .get(format!(
+                    "{}/session/data/{}/activeConnections/{}/sharingCredentials/{}",
+                    api_url, auth_response.data_source, session.identifier, sharing_profile_id
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+        let credentials: SharingCredentials = check_response(response)?.json().await?;
+        Ok(credentials.values.get("key").cloned())
+    }
+
+    async fn delete_connection(
+        client: &Client,
+        api_url: &str,
+        auth_response: AuthResponse,
+        connection_id: &str,
+    ) -> Result<(), GuacamoleError> {
+        // Guacamole's database backends cascade this, but other backends may
+        // leave profiles pointing at a connection that no longer exists
+        let profiles = Self::fetch_sharing_profiles(client, api_url, &auth_response).await?;
+        for profile in profiles
+            .iter()
+            .filter(|profile| profile.primary_connection_identifier == connection_id)
+        {
+            let response = client
+                .delete(format!(
+                    "{}/session/data/{}/sharingProfiles/{}",
+                    api_url, auth_response.data_source, profile.identifier
+                ))
+                .header("Guacamole-Token", &auth_response.auth_token)
+                .send()
+                .await?;
+            check_response(response)?;
+        }
+
+        let response = client
+            .delete(format!(
+                "{}/session/data/{}/connections/{}",
+                api_url, auth_response.data_source, connection_id
+            ))
+            .header("Guacamole-Token", &auth_response.auth_token)
+            .send()
+            .await?;
+        // Already gone is as good as deleted, so retried deletes succeed
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_response(response)?;
+        Ok(())
+    }
+
+    /// Rename a connection, sending back its current definition otherwise unchanged
+    async fn update_connection(
+        client: &Client,
+        api_url: &str,
+        auth_response: AuthResponse,
+        connection_id: &str,
+        new_name: &str,
+    ) -> Result<(), GuacamoleError> {
+        let connection_url = format!(
+            "{}/session/data/{}/connections/{}",
+            api_url, auth_response.data_source, connection_id
+        );
+
+        let response = send_with_retry(|| {
+            client
+                .get(&connection_url)
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+        let mut definition: ConnectionDefinition = check_response(response)?.json().await?;
+
+        let response = send_with_retry(|| {
+            client
+                .get(format!("{}/parameters", connection_url))
+                .header("Guacamole-Token", &auth_response.auth_token)
+        })
+        .await?;
+        definition.parameters = check_response(response)?.json().await?;
+        definition.name = new_name.to_string();
+
+        let response = client
+            .put(&connection_url)
+            .header("Guacamole-Token", &auth_response.auth_token)
+            .json(&definition)
+            .send()
+            .await?;
+        check_response(response)?;
+        Ok(())
+    }
 }
 
 /// Small struct returned by `build_env_config` to carry computed values.
@@ -352,6 +1223,74 @@ struct EnvConfig {
     websocket_url: String,
 }
 
+/// Get the shared HTTP client, building it from `config` on first use
+fn http_client(config: &GuacamoleConfig) -> &'static Client {
+    HTTP_CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(config.request_timeout)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .unwrap_or_else(|err| {
+                warn!("Failed to build Guacamole HTTP client, using defaults: {err}");
+                Client::new()
+            })
+    })
+}
+
+/// Send an idempotent request, retrying connection failures, timeouts and
+/// 5xx responses with jittered exponential backoff. Other 4xx/2xx responses
+/// are returned as-is for the caller to interpret.
+async fn send_with_retry<F>(build: F) -> Result<Response, GuacamoleError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        let error = match build().send().await {
+            Ok(response) if !response.status().is_server_error() => return Ok(response),
+            Ok(response) if attempt >= MAX_REQUEST_ATTEMPTS => return Ok(response),
+            Ok(response) => format!("status {}", response.status()),
+            Err(err)
+                if attempt < MAX_REQUEST_ATTEMPTS && (err.is_connect() || err.is_timeout()) =>
+            {
+                err.to_string()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let wait = delay + jitter(delay);
+        warn!(
+            "Guacamole request failed ({error}), attempt {attempt}/{MAX_REQUEST_ATTEMPTS}; retrying in {}ms",
+            wait.as_millis()
+        );
+        tokio::time::sleep(wait).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Random-ish extra delay of up to half of `delay`, so clients that failed
+/// together don't retry in lockstep
+fn jitter(delay: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    let max = (delay / 2).as_nanos().max(1) as u64;
+    Duration::from_nanos(u64::from(nanos) % max)
+}
+
+/// Map a rejected session token to `TokenRejected` and any other failure status to `ConnectionFailed`
+fn check_response(response: Response) -> Result<Response, GuacamoleError> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(GuacamoleError::TokenRejected),
+        _ => response
+            .error_for_status()
+            .map_err(|e| GuacamoleError::ConnectionFailed(e.to_string())),
+    }
+}
+
 fn compute_websocket_url(base_http_url: &str, tunnel_path: &str) -> String {
     let (scheme, remainder) = if let Some(rest) = base_http_url.strip_prefix("https://") {
         ("wss://", rest)
@@ -368,6 +1307,12 @@ fn compute_websocket_url(base_http_url: &str, tunnel_path: &str) -> String {
     )
 }
 
+/// Lowercase `input` and collapse every run of non-alphanumeric characters
+/// into a single hyphen.
+///
+/// Inputs with nothing usable (e.g. `""`, `"---"` or an emoji) fall back to
+/// `id-` followed by a stable hash of the original, so the identifier is never
+/// empty and distinct inputs stay distinct.
 fn sanitize_identifier(input: &str) -> String {
     let intermediate: String = input
         .chars()
@@ -393,5 +1338,56 @@ fn sanitize_identifier(input: &str) -> String {
             prev_hyphen = false;
         }
     }
-    result.trim_matches('-').to_string()
+    let result = result.trim_matches('-');
+    if result.is_empty() {
+        return format!("id-{:016x}", fnv1a_hash(input.as_bytes()));
+    }
+    result.to_string()
+}
+
+/// 64-bit FNV-1a, used over `DefaultHasher` because its output must not
+/// change between Rust releases
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_identifier_falls_back_to_a_hash() {
+        for input in ["", "---", "😀"] {
+            let sanitized = sanitize_identifier(input);
+            assert!(sanitized.starts_with("id-"), "{input:?} gave {sanitized}");
+            assert!(sanitized.len() > "id-".len());
+            assert_eq!(
+                sanitized,
+                sanitize_identifier(input),
+                "not stable for {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sanitize_identifier_fallback_is_pinned() {
+        // FNV-1a of no bytes is its offset basis; this must not change between builds
+        assert_eq!(sanitize_identifier(""), "id-cbf29ce484222325");
+    }
+
+    #[test]
+    fn sanitize_identifier_fallback_differs_per_input() {
+        assert_ne!(sanitize_identifier("---"), sanitize_identifier("😀"));
+        assert_ne!(sanitize_identifier(""), sanitize_identifier("---"));
+    }
+
+    #[test]
+    fn sanitize_identifier_collapses_separators() {
+        assert_eq!(sanitize_identifier("  Lab Node--01 "), "lab-node-01");
+    }
 }
@@ -1,21 +1,45 @@
+mod audit;
+mod auth;
+mod config;
+mod cors;
+mod download;
 mod guacamole;
+mod metrics;
 mod models;
+mod openapi;
 mod qemu;
+mod reaper;
+mod request_id;
 mod routes;
+mod validate;
 
-use std::{collections::HashMap, env, sync::Arc};
+use std::{collections::HashMap, env, path::Path, sync::Arc, time::Duration};
 
-use sqlx::migrate::Migrator;
+use clap::{Parser, ValueEnum};
+use sqlx::{PgPool, migrate::Migrator, postgres::PgPoolOptions};
 use thiserror::Error;
-use tracing::{debug, error, info, instrument, trace};
+use tokio::{signal, sync::broadcast, task::JoinSet};
+use tracing::{debug, error, info, instrument, trace, warn};
 use tracing_subscriber::filter::LevelFilter;
 
-use models::AppState;
+use config::{Config, ConfigFileError, DatabaseConfig, FileConfig, InvalidValue};
+use models::{AppState, InstanceRegistry, NodeStatus};
+use qemu::QemuError;
 use routes::create_router;
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
-const ENV_SPECS: &'static [&'static str; 17] = &[
+/// Extra time on top of the guest shutdown timeout for killing stragglers
+/// and recording their state before the process exits regardless
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Node events buffered per subscriber before the slowest starts missing them
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Upper bound on the wait between two connection attempts
+const MAX_DB_CONNECT_DELAY: Duration = Duration::from_secs(30);
+
+const ENV_SPECS: &[&str; 17] = &[
     "POSTGRES_USER",
     "POSTGRES_PASSWORD",
     "POSTGRES_HOST",
@@ -35,13 +59,53 @@ const ENV_SPECS: &'static [&'static str; 17] = &[
     "GUAC_PASS",
 ];
 
+/// Variables that are loaded when present but fall back to defaults otherwise
+const OPTIONAL_ENV_SPECS: &[&str] = &[
+    "QEMU_SHUTDOWN_TIMEOUT",
+    "QEMU_VNC_BIND_HOST",
+    "QEMU_MAX_MEMORY_MB",
+    "QEMU_MAX_CPU_CORES",
+    "QEMU_BRIDGE",
+    "QEMU_SPICE_PORT_BASE",
+    "QEMU_KVM_MODE",
+    "CONSOLE_DIR",
+    "ISO_DIR",
+    "CAPTURE_DIR",
+    "OVMF_CODE_PATH",
+    "OVMF_VARS_PATH",
+    "IMAGE_UPLOAD_MAX_MB",
+    "GUAC_REQUEST_TIMEOUT",
+    "GUAC_RECORDING_DIR",
+    "API_KEY",
+    "ADMIN_API_KEY",
+    "CORS_ALLOWED_ORIGINS",
+    "REQUEST_BODY_LIMIT_KB",
+    "REQUEST_TIMEOUT",
+    "MAX_TOTAL_MEMORY_MB",
+    "MAX_TOTAL_CORES",
+    "DB_CONNECT_ATTEMPTS",
+    "DB_CONNECT_BASE_DELAY_MS",
+    "DB_MAX_CONNECTIONS",
+    "DB_MIN_CONNECTIONS",
+    "DB_ACQUIRE_TIMEOUT",
+];
+
 #[derive(Debug, Error)]
 enum SetupError {
     #[error("Failed to load environment file {file}: {source}")]
     EnvLoadError { file: String, source: dotenv::Error },
 
-    #[error("Expected variable `{0}` not found")]
-    EnvVarNotFound(String),
+    #[error("Failed to load config file {file}: {source}")]
+    ConfigLoadError {
+        file: String,
+        source: ConfigFileError,
+    },
+
+    #[error("Missing variables in {file}: {}", missing.join(", "))]
+    EnvVarsMissing { file: String, missing: Vec<String> },
+
+    #[error(transparent)]
+    InvalidValue(#[from] InvalidValue),
 }
 
 fn read_env(name: &str) -> Option<String> {
@@ -62,6 +126,7 @@ fn read_env(name: &str) -> Option<String> {
 fn load_env(
     file: &str,
     specs: &'static [&'static str],
+    optional_specs: &'static [&'static str],
 ) -> Result<HashMap<String, String>, SetupError> {
     debug!("Loading environment variables from file: {}", file);
     dotenv::from_filename(file).map_err(|err| SetupError::EnvLoadError {
@@ -69,108 +134,338 @@ fn load_env(
         source: err,
     })?;
 
+    collect_env(file, specs, optional_specs, HashMap::new())
+}
+
+/// Load variables from a TOML config file; real environment variables take precedence
+fn load_config(
+    file: &str,
+    specs: &'static [&'static str],
+    optional_specs: &'static [&'static str],
+) -> Result<HashMap<String, String>, SetupError> {
+    debug!("Loading configuration from file: {}", file);
+    let config = FileConfig::load(Path::new(file)).map_err(|err| SetupError::ConfigLoadError {
+        file: file.into(),
+        source: err,
+    })?;
+
+    collect_env(file, specs, optional_specs, config.into_env())
+}
+
+/// Gather `specs` from the environment, falling back to `defaults`
+fn collect_env(
+    source: &str,
+    specs: &'static [&'static str],
+    optional_specs: &'static [&'static str],
+    mut defaults: HashMap<String, String>,
+) -> Result<HashMap<String, String>, SetupError> {
+    // Collect every missing variable so they can all be fixed in one go
     let mut variables = HashMap::new();
+    let mut missing = Vec::new();
     for spec in specs {
-        if let Some(val) = read_env(spec) {
+        if let Some(val) = read_env(spec).or_else(|| defaults.remove(*spec)) {
             variables.insert(spec.to_string(), val);
         } else {
-            Err(SetupError::EnvVarNotFound(spec.to_string()))?;
+            missing.push(spec.to_string());
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(SetupError::EnvVarsMissing {
+            file: source.into(),
+            missing,
+        });
+    }
+
+    for spec in optional_specs {
+        if let Some(val) = read_env(spec).or_else(|| defaults.remove(*spec)) {
+            variables.insert(spec.to_string(), val);
         }
     }
 
     Ok(variables)
 }
 
-fn build_postgres_url(
-    user: &str,
-    password: &str,
-    host: &str,
-    port: &str,
-    database: &str,
-) -> String {
-    format!(
-        "postgres://{}:{}@{}:{}/{}",
-        user, password, host, port, database
+/// Build the pool settings from `DB_MAX_CONNECTIONS`, `DB_MIN_CONNECTIONS`
+/// and `DB_ACQUIRE_TIMEOUT`
+fn pool_options(database: &DatabaseConfig) -> PgPoolOptions {
+    info!(
+        "Database pool: max_connections={}, min_connections={}, acquire_timeout={}s",
+        database.max_connections,
+        database.min_connections,
+        database.acquire_timeout.as_secs()
+    );
+
+    PgPoolOptions::new()
+        .max_connections(database.max_connections)
+        .min_connections(database.min_connections)
+        .acquire_timeout(database.acquire_timeout)
+}
+
+/// Connect to the database, retrying with exponential backoff.
+///
+/// Postgres is often still starting when the backend comes up (e.g. under
+/// docker compose), so failures are retried up to `attempts` times with the
+/// delay doubling from `base_delay` up to `MAX_DB_CONNECT_DELAY`.
+async fn connect_with_retry(
+    options: PgPoolOptions,
+    database_url: &str,
+    attempts: u32,
+    base_delay: Duration,
+) -> Result<PgPool, sqlx::Error> {
+    let mut delay = base_delay;
+    let mut attempt = 1;
+    loop {
+        match options.clone().connect(database_url).await {
+            Ok(pool) => return Ok(pool),
+            Err(err) if attempt < attempts => {
+                warn!(
+                    "Database connection attempt {attempt}/{attempts} failed: {err}; retrying in {}ms",
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_DB_CONNECT_DELAY);
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Mark nodes left `Running` by a previous run as stopped.
+///
+/// QEMU processes are children of the backend and none of their handles
+/// survive a restart, so any node still recorded as running is stale.
+async fn reconcile_node_status(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE nodes SET status = $1, vnc_port = NULL, paused = FALSE, spice_port = NULL WHERE status = $2",
     )
+    .bind(NodeStatus::Stopped)
+    .bind(NodeStatus::Running)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
 }
 
-fn parse_log_level(args: &mut env::Args) -> LevelFilter {
-    while let Some(arg) = args.next() {
-        if arg == "--log-level" {
-            return if let Some(level) = args.next() {
-                match level.to_lowercase().as_str() {
-                    "debug" => LevelFilter::DEBUG,
-                    "info" => LevelFilter::INFO,
-                    "warn" | "warning" => LevelFilter::WARN,
-                    "trace" => LevelFilter::TRACE,
-                    "error" => LevelFilter::ERROR,
-                    _ => LevelFilter::INFO,
+/// Resolve once SIGINT (Ctrl+C) or SIGTERM is received
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(err) => {
+                error!("Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, stopping the server");
+}
+
+/// Power off every tracked VM and mark it stopped.
+///
+/// Instances are stopped concurrently; each gets the configured guest
+/// shutdown timeout before it is killed, and the whole pass is abandoned
+/// after `SHUTDOWN_GRACE` more so a stuck VM cannot hold up the exit.
+async fn stop_all_instances(state: &AppState) {
+    let instances = state.instances.drain().await;
+    if instances.is_empty() {
+        return;
+    }
+
+    info!("Stopping {} running node(s)", instances.len());
+    let timeout = state.config.qemu.shutdown_timeout;
+
+    let mut tasks = JoinSet::new();
+    for (node_id, instance) in instances {
+        tasks.spawn(async move {
+            let mut guard = instance.lock().await;
+            let result = match qemu::stop_node(&mut guard, timeout).await {
+                Ok(()) | Err(QemuError::NodeNotRunning) => Ok(()),
+                Err(err) => {
+                    warn!("Graceful stop of node {node_id} failed ({err}), killing it");
+                    qemu::kill_node(&mut guard).await
                 }
-            } else {
-                LevelFilter::INFO
             };
+            (node_id, result)
+        });
+    }
+
+    let stop_all = async {
+        while let Some(joined) = tasks.join_next().await {
+            let (node_id, result) = match joined {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    error!("Node stop task failed: {err}");
+                    continue;
+                }
+            };
+
+            if let Err(err) = result {
+                error!("Failed to stop node {node_id}: {err}");
+                continue;
+            }
+
+            if let Err(err) = sqlx::query(
+                "UPDATE nodes SET status = $1, vnc_port = NULL, paused = FALSE, spice_port = NULL WHERE id = $2",
+            )
+            .bind(NodeStatus::Stopped)
+            .bind(node_id)
+            .execute(&state.db)
+            .await
+            {
+                error!("Failed to mark node {node_id} stopped: {err}");
+            } else {
+                info!("Node {node_id} stopped");
+            }
+        }
+    };
+
+    if tokio::time::timeout(timeout + SHUTDOWN_GRACE, stop_all)
+        .await
+        .is_err()
+    {
+        warn!(
+            "Shutdown window elapsed with {} node(s) still stopping",
+            tasks.len()
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    #[value(alias = "warning")]
+    Warn,
+    Error,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => LevelFilter::TRACE,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Error => LevelFilter::ERROR,
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Backend for managing QEMU lab nodes and their Guacamole consoles
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Maximum level of log messages to emit
+    #[arg(long, value_enum, default_value = "info", ignore_case = true)]
+    log_level: LogLevel,
+
+    /// Format of emitted log lines
+    #[arg(
+        long,
+        value_enum,
+        default_value = "pretty",
+        env = "LOG_FORMAT",
+        ignore_case = true
+    )]
+    log_format: LogFormat,
+
+    /// Load settings from a TOML file instead of `.env`
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
 
-    LevelFilter::INFO
+    /// Address to listen on, overriding BACKEND_HOST and BACKEND_PORT
+    #[arg(long, value_name = "HOST:PORT")]
+    bind: Option<String>,
 }
 
 #[tokio::main]
 #[instrument]
 async fn main() {
-    let log_level = parse_log_level(&mut env::args());
-    tracing_subscriber::fmt().with_max_level(log_level).init();
+    let cli = Cli::parse();
+    let subscriber = tracing_subscriber::fmt().with_max_level(LevelFilter::from(cli.log_level));
+    match cli.log_format {
+        LogFormat::Pretty => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
 
-    let mut env = match load_env(".env", ENV_SPECS) {
-        Ok(env) => env,
+    let metrics = match metrics::install_recorder() {
+        Ok(handle) => handle,
+        Err(err) => {
+            error!("Failed to install metrics recorder: {err}");
+            return;
+        }
+    };
+
+    let loaded = match &cli.config {
+        Some(path) => load_config(path, ENV_SPECS, OPTIONAL_ENV_SPECS),
+        None => load_env(".env", ENV_SPECS, OPTIONAL_ENV_SPECS),
+    };
+
+    let config = match loaded.and_then(|env| Config::from_env(&env).map_err(SetupError::from)) {
+        Ok(config) => config,
         Err(err) => {
             error!("{err}");
             return;
         }
     };
 
-    let database_url = build_postgres_url(
-        env.get("POSTGRES_USER").unwrap(),
-        env.get("POSTGRES_PASSWORD").unwrap(),
-        env.get("POSTGRES_HOST").unwrap(),
-        env.get("POSTGRES_PORT").unwrap(),
-        env.get("BACKEND_DB").unwrap(),
-    );
-    env.insert("DATABASE_URL".into(), database_url.clone());
-
-    env.insert(
-        "GUAC_URL".into(),
-        format!(
-            "http{}://{}:{}/guacamole/",
-            if env.get("GUAC_HTTPS").unwrap() == "1" {
-                "s"
-            } else {
-                ""
-            },
-            env.get("GUAC_HOST").unwrap(),
-            env.get("GUAC_PORT").unwrap(),
-        ),
-    );
+    if !qemu::kvm_available() {
+        warn!(
+            "/dev/kvm is not accessible, nodes will run with TCG emulation or fail to start if QEMU_KVM_MODE is strict"
+        );
+    }
 
     debug!("Loaded environment variables.");
 
+    let database = &config.database;
     debug!(
         "Connecting to the database at {}:{}",
-        env.get("POSTGRES_HOST").unwrap(),
-        env.get("POSTGRES_PORT").unwrap()
+        database.host, database.port
     );
 
-    let pool = match sqlx::postgres::PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
+    let attempts = database.connect_attempts;
+    let pool = match connect_with_retry(
+        pool_options(database),
+        &database.url,
+        attempts,
+        database.connect_base_delay,
+    )
+    .await
     {
         Ok(pool) => {
             info!("Successfully connected to the database.");
             pool
         }
         Err(err) => {
-            error!("Failed to connect to the database: {}", err);
+            error!(
+                "Failed to connect to the database after {attempts} attempt(s): {}",
+                err
+            );
             return;
         }
     };
@@ -181,13 +476,20 @@ async fn main() {
     }
 
     debug!("Migrations applied successfully.");
+
+    match reconcile_node_status(&pool).await {
+        Ok(0) => {}
+        Ok(count) => warn!("Marked {count} node(s) left running by a previous run as stopped"),
+        Err(err) => {
+            error!("Failed to reconcile node status: {}", err);
+            return;
+        }
+    }
     info!("Database setup complete.");
 
-    let address = format!(
-        "{}:{}",
-        env.get("BACKEND_HOST").unwrap(),
-        env.get("BACKEND_PORT").unwrap()
-    );
+    let address = cli
+        .bind
+        .unwrap_or_else(|| config.server.bind_address.clone());
 
     let listener = match tokio::net::TcpListener::bind(&address).await {
         Ok(listener) => {
@@ -200,12 +502,26 @@ async fn main() {
         }
     };
 
-    let app = create_router(AppState {
+    let state = AppState {
         db: pool,
-        env: Arc::new(env),
-    });
+        config: Arc::new(config),
+        instances: InstanceRegistry::default(),
+        metrics,
+        events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+    };
+    if !auth::api_key_configured(&state) {
+        warn!("API_KEY is not set, mutating requests are not authenticated");
+    }
+    tokio::spawn(reaper::run(state.clone()));
+    let app = create_router(state.clone());
 
-    if let Err(err) = axum::serve(listener, app).await {
+    if let Err(err) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+    {
         error!("Server error: {err}");
     }
+
+    stop_all_instances(&state).await;
+    info!("Shutdown complete");
 }
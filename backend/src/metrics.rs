@@ -0,0 +1,72 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+
+use crate::models::AppState;
+
+pub const NODES_STARTED: &str = "network_lab_nodes_started_total";
+pub const NODES_STOPPED: &str = "network_lab_nodes_stopped_total";
+const NODES_RUNNING: &str = "network_lab_nodes_running";
+const GUACAMOLE_CONNECTIONS_CREATED: &str = "network_lab_guacamole_connections_created_total";
+const GUACAMOLE_CONNECTION_FAILURES: &str = "network_lab_guacamole_connection_failures_total";
+const HTTP_REQUESTS: &str = "network_lab_http_requests_total";
+const HTTP_REQUEST_DURATION: &str = "network_lab_http_request_duration_seconds";
+
+/// Latency buckets in seconds; node start and stop requests can take tens of seconds
+const HTTP_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Install the global Prometheus recorder used by the `metrics` macros
+pub fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets(HTTP_DURATION_BUCKETS)?
+        .install_recorder()
+}
+
+/// Count a Guacamole connection creation attempt by protocol and outcome
+pub fn record_connection_result<T, E>(protocol: &'static str, result: &Result<T, E>) {
+    let name = match result {
+        Ok(_) => GUACAMOLE_CONNECTIONS_CREATED,
+        Err(_) => GUACAMOLE_CONNECTION_FAILURES,
+    };
+    counter!(name, "protocol" => protocol).increment(1);
+}
+
+/// Middleware recording the count and latency of requests per route and status
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    // Label by route template rather than the raw path so node ids don't
+    // create a new series per node
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION, &labels).record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// GET /metrics - Prometheus scrape endpoint
+pub async fn render(State(state): State<AppState>) -> impl IntoResponse {
+    gauge!(NODES_RUNNING).set(state.instances.len().await as f64);
+    state.metrics.run_upkeep();
+    state.metrics.render()
+}
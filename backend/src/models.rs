@@ -1,15 +1,30 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex as StdMutex},
+    time::SystemTime,
 };
 
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use metrics_exporter_prometheus::PrometheusHandle;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use serde_json::Value;
+use sqlx::{FromRow, PgPool, Row, postgres::PgRow, types::Json as SqlJson};
 use thiserror::Error;
+use tokio::sync::{Mutex, MutexGuard, broadcast};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
+use crate::config::Config;
+use crate::guacamole::VncDisplayOptions;
+use crate::qemu::QemuInstance;
+
 #[derive(Debug, Error)]
 pub enum ImagePathError {
     #[error("Invalid path: {0}")]
@@ -29,7 +44,7 @@ pub enum ImagePathError {
 ///     └── ubuntu-with-nginx.qcow2 (parent_id: ubuntu-base)
 ///             └── [Node overlays created at runtime]
 /// ```
-#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
 pub struct Image {
     pub id: Uuid,
     pub name: String,
@@ -39,12 +54,14 @@ pub struct Image {
     pub parent_id: Option<Uuid>,
     /// Description of what this image contains
     pub description: Option<String>,
+    /// Hex SHA-256 of the image file, recorded when it was registered
+    pub sha256: Option<String>,
 }
 
 impl Image {
     /// Get the full filesystem path for this image
     pub fn get_full_path(&self, app_state: &AppState) -> Result<PathBuf, ImagePathError> {
-        validate_and_resolve_path(app_state.env.get("IMAGE_DIR").unwrap(), &self.path)
+        validate_and_resolve_path(&app_state.config.qemu.image_dir, &self.path)
     }
 
     /// Check if this is a base image (has no parent)
@@ -53,16 +70,65 @@ impl Image {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, sqlx::Type, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::Type, PartialEq, Eq, ToSchema)]
 #[sqlx(type_name = "text", rename_all = "PascalCase")]
 pub enum NodeStatus {
     Running,
     Stopped,
 }
 
+/// How a node's network interface is connected
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, sqlx::Type, PartialEq, Eq, ToSchema)]
+#[sqlx(type_name = "text", rename_all = "PascalCase")]
+pub enum NetworkMode {
+    /// No network interface at all
+    Isolated,
+    /// QEMU user-mode (SLIRP) NAT: outbound access and forwarded ports,
+    /// without root or any host setup
+    User,
+    /// TAP device on the `QEMU_BRIDGE` bridge; needs `CAP_NET_ADMIN`
+    Bridge,
+}
+
+/// Firmware a node boots with
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, Copy, sqlx::Type, PartialEq, Eq, ToSchema,
+)]
+#[sqlx(type_name = "text", rename_all = "PascalCase")]
+pub enum Firmware {
+    /// QEMU's built-in SeaBIOS
+    #[default]
+    Bios,
+    /// OVMF, from `OVMF_CODE_PATH` with a per-node copy of `OVMF_VARS_PATH`
+    Uefi,
+}
+
+/// A host TCP port forwarded to a port inside the guest
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub struct PortForward {
+    pub host_port: u16,
+    pub guest_port: u16,
+}
+
+/// Traffic shaping applied with netem to a bridged node's TAP device.
+///
+/// The device's egress is the guest's ingress, so this shapes traffic
+/// delivered to the guest.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+pub struct NetemParams {
+    /// Added delay in milliseconds
+    pub delay_ms: Option<u32>,
+    /// Random variation of the delay in milliseconds; needs `delay_ms`
+    pub jitter_ms: Option<u32>,
+    /// Percentage of packets dropped, from 0 to 100
+    pub loss_pct: Option<f32>,
+    /// Bandwidth limit in kbit/s
+    pub rate_kbit: Option<u32>,
+}
+
 /// Represents a virtual machine instance.
 /// Each node is based on an Image and has its own runtime overlay for instance-specific changes.
-#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Node {
     pub id: Uuid,
     pub name: String,
@@ -76,6 +142,96 @@ pub struct Node {
     pub vnc_port: Option<u16>,
     /// Guacamole connection ID if connected
     pub guacamole_connection_id: Option<String>,
+    /// Whether the guest is frozen; a paused node is still `Running`
+    pub paused: bool,
+    /// Guest memory in MB, or the QEMU default when unset
+    pub memory_mb: Option<u32>,
+    /// Number of guest CPU cores, or the QEMU default when unset
+    pub cpu_cores: Option<u32>,
+    /// Network connection, or the server default when unset
+    pub network_mode: Option<NetworkMode>,
+    /// Port forwards for `User` networking
+    pub port_forwards: Vec<PortForward>,
+    pub firmware: Firmware,
+    /// Whether the node serves a SPICE display alongside VNC
+    pub spice: bool,
+    /// SPICE port while the node is running with SPICE enabled
+    pub spice_port: Option<u16>,
+    /// Shaping of the node's bridged NIC
+    pub netem: Option<NetemParams>,
+}
+
+// Implemented by hand because Postgres has no unsigned types to decode
+// `vnc_port`, `spice_port`, `memory_mb` and `cpu_cores` from
+impl<'r> FromRow<'r, PgRow> for Node {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            status: row.try_get("status")?,
+            image_id: row.try_get("image_id")?,
+            instance_overlay_path: row.try_get("instance_overlay_path")?,
+            vnc_port: try_get_unsigned(row, "vnc_port")?,
+            guacamole_connection_id: row.try_get("guacamole_connection_id")?,
+            paused: row.try_get("paused")?,
+            memory_mb: try_get_unsigned(row, "memory_mb")?,
+            cpu_cores: try_get_unsigned(row, "cpu_cores")?,
+            network_mode: row.try_get("network_mode")?,
+            port_forwards: row
+                .try_get::<SqlJson<Vec<PortForward>>, _>("port_forwards")?
+                .0,
+            firmware: row.try_get("firmware")?,
+            spice: row.try_get("spice")?,
+            spice_port: try_get_unsigned(row, "spice_port")?,
+            netem: row
+                .try_get::<Option<SqlJson<NetemParams>>, _>("netem")?
+                .map(|netem| netem.0),
+        })
+    }
+}
+
+/// A point-to-point layer 2 link between two nodes.
+///
+/// Each end gets a dedicated NIC backed by a TAP device on a bridge of its
+/// own, so the link's frames never mix with other traffic and each end's
+/// device can be shaped. Setting up the devices needs `CAP_NET_ADMIN`.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Link {
+    pub id: Uuid,
+    pub node_a: Uuid,
+    pub node_b: Uuid,
+    /// One-way latency in milliseconds, added in each direction
+    pub latency_ms: Option<u32>,
+    /// Bandwidth limit in kbit/s, applied to each direction
+    pub bandwidth_kbps: Option<u32>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Link {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            node_a: row.try_get("node_a")?,
+            node_b: row.try_get("node_b")?,
+            latency_ms: try_get_unsigned(row, "latency_ms")?,
+            bandwidth_kbps: try_get_unsigned(row, "bandwidth_kbps")?,
+        })
+    }
+}
+
+/// Decode a nullable INTEGER column into a narrower or unsigned type
+fn try_get_unsigned<T>(row: &PgRow, column: &str) -> Result<Option<T>, sqlx::Error>
+where
+    T: TryFrom<i32>,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    let value: Option<i32> = row.try_get(column)?;
+    value
+        .map(T::try_from)
+        .transpose()
+        .map_err(|e| sqlx::Error::ColumnDecode {
+            index: column.into(),
+            source: Box::new(e),
+        })
 }
 
 impl Node {
@@ -85,25 +241,95 @@ impl Node {
         app_state: &AppState,
     ) -> Result<PathBuf, ImagePathError> {
         validate_and_resolve_path(
-            app_state.env.get("OVERLAY_DIR").unwrap(),
+            &app_state.config.qemu.overlay_dir,
             &self.instance_overlay_path,
         )
     }
+
+    /// Get the full filesystem path for this node's QMP monitor socket
+    pub fn get_monitor_socket_path(&self, app_state: &AppState) -> Result<PathBuf, ImagePathError> {
+        validate_and_resolve_path(
+            &app_state.config.qemu.overlay_dir,
+            &format!("{}.qmp", self.id),
+        )
+    }
+
+    /// Get the full filesystem path for this node's guest agent socket
+    pub fn get_guest_agent_socket_path(
+        &self,
+        app_state: &AppState,
+    ) -> Result<PathBuf, ImagePathError> {
+        validate_and_resolve_path(
+            &app_state.config.qemu.overlay_dir,
+            &format!("{}.qga", self.id),
+        )
+    }
+
+    /// Get the full filesystem path for this node's writable UEFI variable store
+    pub fn get_uefi_vars_path(&self, app_state: &AppState) -> Result<PathBuf, ImagePathError> {
+        validate_and_resolve_path(
+            &app_state.config.qemu.overlay_dir,
+            &format!("{}.vars.fd", self.id),
+        )
+    }
+
+    /// Get the full filesystem path for this node's serial console log
+    ///
+    /// Logs live in `CONSOLE_DIR`, or next to the overlays when it is unset.
+    pub fn get_console_log_path(&self, app_state: &AppState) -> Result<PathBuf, ImagePathError> {
+        validate_and_resolve_path(
+            &app_state.config.qemu.console_dir,
+            &format!("{}.log", self.id),
+        )
+    }
+}
+
+/// Path of a node's packet capture in `CAPTURE_DIR`, or `None` when no
+/// `CAPTURE_DIR` is configured
+pub fn capture_path(
+    app_state: &AppState,
+    node_id: Uuid,
+) -> Option<Result<PathBuf, ImagePathError>> {
+    let capture_dir = app_state.config.qemu.capture_dir.as_ref()?;
+    Some(validate_and_resolve_path(
+        capture_dir,
+        &format!("{}.pcap", node_id),
+    ))
+}
+
+/// Resolve an ISO image path relative to `ISO_DIR`, refusing anything
+/// outside it. Returns `None` when no `ISO_DIR` is configured.
+pub fn resolve_iso_path(
+    app_state: &AppState,
+    relative_path: &str,
+) -> Option<Result<PathBuf, ImagePathError>> {
+    let iso_dir = app_state.config.qemu.iso_dir.as_ref()?;
+    Some(validate_and_resolve_path(iso_dir, relative_path))
 }
 
 fn validate_and_resolve_path(
-    base_dir: &str,
+    base_dir: &Path,
     relative_path: &str,
 ) -> Result<PathBuf, ImagePathError> {
-    let base_dir = Path::new(base_dir).canonicalize()?;
+    let base_dir = base_dir.canonicalize()?;
     let full_path = base_dir.join(relative_path);
 
-    // For new files that don't exist yet, we validate the parent directory
-    let path_to_check =
-        if full_path.exists() {
-            full_path.canonicalize()?
-        } else {
-            // Canonicalize the parent and append the filename
+    // `exists()` follows symlinks, so a dangling symlink would look like a new
+    // file and creating it would write wherever it points. Check the link
+    // itself instead: anything present on disk, symlink or not, must resolve.
+    let path_to_check = match full_path.symlink_metadata() {
+        Ok(metadata) => full_path.canonicalize().map_err(|e| {
+            if metadata.file_type().is_symlink() {
+                ImagePathError::PathTraversal(format!(
+                    "{} is a symlink that cannot be resolved",
+                    relative_path
+                ))
+            } else {
+                e.into()
+            }
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            // For new files that don't exist yet, we validate the parent directory
             let parent = full_path
                 .parent()
                 .ok_or_else(|| {
@@ -113,7 +339,9 @@ fn validate_and_resolve_path(
             parent.join(full_path.file_name().ok_or_else(|| {
                 io::Error::new(io::ErrorKind::InvalidInput, "Path has no filename")
             })?)
-        };
+        }
+        Err(e) => return Err(e.into()),
+    };
 
     // Ensure the resolved path is within the base directory (to prevent directory traversal attacks)
     if !path_to_check.starts_with(&base_dir) {
@@ -126,17 +354,443 @@ fn validate_and_resolve_path(
     Ok(path_to_check)
 }
 
+/// A QEMU instance shared between request handlers
+pub type SharedInstance = Arc<Mutex<QemuInstance>>;
+
+/// Registry of the QEMU processes started by this backend, keyed by node id.
+///
+/// Locking discipline: the registry lock is only held for the duration of a
+/// single lookup, insert or removal and never across an `.await` on QEMU.
+/// Operations that talk to a VM (starting, stopping, monitor commands) clone
+/// the instance handle out of the registry and lock that instead, so a slow
+/// shutdown of one node never blocks requests for another.
+#[derive(Clone, Default)]
+pub struct InstanceRegistry {
+    instances: Arc<Mutex<HashMap<Uuid, SharedInstance>>>,
+    /// Memory and cores claimed by nodes that are running or starting
+    reservations: Arc<Mutex<HashMap<Uuid, ResourceUsage>>>,
+    /// Held while a VNC display is picked so two requests never both see it free
+    vnc_allocation: Arc<Mutex<()>>,
+    /// VNC displays handed out but not yet recorded as a node's `vnc_port`
+    vnc_claims: Arc<StdMutex<HashSet<u16>>>,
+    /// Held while a SPICE port is picked, like `vnc_allocation`
+    spice_allocation: Arc<Mutex<()>>,
+    /// SPICE ports handed out but not yet recorded as a node's `spice_port`
+    spice_claims: Arc<StdMutex<HashSet<u16>>>,
+    /// Nodes a request is starting, from its checks until the instance is tracked
+    starting: Arc<StdMutex<HashSet<Uuid>>>,
+    /// Image files whose checksum matched, as they were when hashed
+    verified_images: Arc<StdMutex<HashMap<Uuid, VerifiedFile>>>,
+}
+
+/// An image file's size and modification time when it matched `sha256`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedFile {
+    pub len: u64,
+    pub modified: SystemTime,
+    pub sha256: String,
+}
+
+/// Why a node's resources couldn't be reserved
+#[derive(Debug)]
+pub enum ReservationError {
+    /// The node already holds a reservation, so it is running or starting
+    AlreadyReserved,
+    /// Starting the node would exceed the budget; carries the other nodes' usage
+    OverBudget(ResourceUsage),
+}
+
+/// Marks a node as being started; dropping it lets another request start it
+#[derive(Debug)]
+pub struct StartClaim {
+    node_id: Uuid,
+    starting: Arc<StdMutex<HashSet<Uuid>>>,
+}
+
+impl Drop for StartClaim {
+    fn drop(&mut self) {
+        self.starting.lock().unwrap().remove(&self.node_id);
+    }
+}
+
+/// A VNC display reserved for one node.
+///
+/// Allocation looks at the displays recorded on running nodes, so keep the
+/// claim until the display is stored as the node's `vnc_port`; dropping it
+/// makes the display available again.
+#[derive(Debug)]
+pub struct VncDisplayClaim {
+    display: u16,
+    claims: Arc<StdMutex<HashSet<u16>>>,
+}
+
+impl VncDisplayClaim {
+    pub fn display(&self) -> u16 {
+        self.display
+    }
+}
+
+impl Drop for VncDisplayClaim {
+    fn drop(&mut self) {
+        self.claims.lock().unwrap().remove(&self.display);
+    }
+}
+
+/// A SPICE port reserved for one node; keep it until the port is stored as
+/// the node's `spice_port`
+#[derive(Debug)]
+pub struct SpicePortClaim {
+    port: u16,
+    claims: Arc<StdMutex<HashSet<u16>>>,
+}
+
+impl SpicePortClaim {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for SpicePortClaim {
+    fn drop(&mut self) {
+        self.claims.lock().unwrap().remove(&self.port);
+    }
+}
+
+/// Memory and CPU cores used by one or more VMs
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ResourceUsage {
+    pub memory_mb: u64,
+    pub cpu_cores: u64,
+}
+
+/// Host-wide limits on what running nodes may use together; `None` is unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceBudget {
+    pub memory_mb: Option<u64>,
+    pub cpu_cores: Option<u64>,
+}
+
+impl InstanceRegistry {
+    /// Get a handle to the running instance of a node
+    pub async fn get(&self, node_id: Uuid) -> Option<SharedInstance> {
+        self.instances.lock().await.get(&node_id).cloned()
+    }
+
+    /// Check whether a node has a running instance
+    pub async fn contains(&self, node_id: Uuid) -> bool {
+        self.instances.lock().await.contains_key(&node_id)
+    }
+
+    /// Track an instance, returning the one it replaced if any
+    pub async fn insert(&self, node_id: Uuid, instance: SharedInstance) -> Option<SharedInstance> {
+        self.instances.lock().await.insert(node_id, instance)
+    }
+
+    /// Stop tracking a node's instance and hand it back to the caller
+    pub async fn remove(&self, node_id: Uuid) -> Option<SharedInstance> {
+        self.instances.lock().await.remove(&node_id)
+    }
+
+    /// Check whether `instance` is still the one tracked for a node
+    pub async fn tracks(&self, node_id: Uuid, instance: &SharedInstance) -> bool {
+        self.instances
+            .lock()
+            .await
+            .get(&node_id)
+            .is_some_and(|tracked| Arc::ptr_eq(tracked, instance))
+    }
+
+    /// Stop tracking a node's instance only if it is still `instance`, so a
+    /// handle that was already removed or replaced is left alone
+    pub async fn remove_if_same(&self, node_id: Uuid, instance: &SharedInstance) -> bool {
+        let mut instances = self.instances.lock().await;
+        match instances.get(&node_id) {
+            Some(tracked) if Arc::ptr_eq(tracked, instance) => {
+                instances.remove(&node_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Handles to every tracked instance, which stay tracked
+    pub async fn entries(&self) -> Vec<(Uuid, SharedInstance)> {
+        self.instances
+            .lock()
+            .await
+            .iter()
+            .map(|(node_id, instance)| (*node_id, instance.clone()))
+            .collect()
+    }
+
+    /// Number of tracked instances
+    pub async fn len(&self) -> usize {
+        self.instances.lock().await.len()
+    }
+
+    /// Stop tracking every instance and hand them all back to the caller
+    pub async fn drain(&self) -> Vec<(Uuid, SharedInstance)> {
+        self.reservations.lock().await.clear();
+        self.instances.lock().await.drain().collect()
+    }
+
+    /// Claim the start of a node, or `None` if another request is starting it.
+    ///
+    /// Take the claim before checking that the node is stopped and keep it
+    /// until its instance is tracked, so two starts can't both pass the check.
+    pub fn claim_start(&self, node_id: Uuid) -> Option<StartClaim> {
+        if !self.starting.lock().unwrap().insert(node_id) {
+            return None;
+        }
+        Some(StartClaim {
+            node_id,
+            starting: self.starting.clone(),
+        })
+    }
+
+    /// Claim resources for a node about to start if they fit in `budget`.
+    ///
+    /// Checking and claiming happen under one lock so concurrent starts can't
+    /// both squeeze into the last free capacity. A node that already holds a
+    /// reservation is refused rather than having it replaced.
+    pub async fn reserve(
+        &self,
+        node_id: Uuid,
+        usage: ResourceUsage,
+        budget: ResourceBudget,
+    ) -> Result<(), ReservationError> {
+        let mut reservations = self.reservations.lock().await;
+        if reservations.contains_key(&node_id) {
+            return Err(ReservationError::AlreadyReserved);
+        }
+        Self::check_budget(&reservations, node_id, usage, budget)
+            .map_err(ReservationError::OverBudget)?;
+        reservations.insert(node_id, usage);
+        Ok(())
+    }
+
+    /// Change the resources held by a running node if the new amount fits in
+    /// `budget`. On rejection the usage of the other nodes is returned.
+    pub async fn resize(
+        &self,
+        node_id: Uuid,
+        usage: ResourceUsage,
+        budget: ResourceBudget,
+    ) -> Result<(), ResourceUsage> {
+        let mut reservations = self.reservations.lock().await;
+        Self::check_budget(&reservations, node_id, usage, budget)?;
+        reservations.insert(node_id, usage);
+        Ok(())
+    }
+
+    /// Check that `usage` fits in `budget` next to every other node's reservation
+    fn check_budget(
+        reservations: &HashMap<Uuid, ResourceUsage>,
+        node_id: Uuid,
+        usage: ResourceUsage,
+        budget: ResourceBudget,
+    ) -> Result<(), ResourceUsage> {
+        let in_use = reservations.iter().filter(|(id, _)| **id != node_id).fold(
+            ResourceUsage::default(),
+            |total, (_, usage)| ResourceUsage {
+                memory_mb: total.memory_mb + usage.memory_mb,
+                cpu_cores: total.cpu_cores + usage.cpu_cores,
+            },
+        );
+
+        let fits = budget
+            .memory_mb
+            .is_none_or(|max| in_use.memory_mb + usage.memory_mb <= max)
+            && budget
+                .cpu_cores
+                .is_none_or(|max| in_use.cpu_cores + usage.cpu_cores <= max);
+        if !fits {
+            return Err(in_use);
+        }
+        Ok(())
+    }
+
+    /// Return a node's resources once it has stopped or failed to start
+    pub async fn release(&self, node_id: Uuid) {
+        self.reservations.lock().await.remove(&node_id);
+    }
+
+    /// Serialize VNC display allocation. Hold the guard from reading the
+    /// displays in use until the chosen one is claimed.
+    pub async fn lock_vnc_allocation(&self) -> MutexGuard<'_, ()> {
+        self.vnc_allocation.lock().await
+    }
+
+    /// Displays claimed by allocations that haven't been recorded yet
+    pub fn claimed_vnc_displays(&self) -> HashSet<u16> {
+        self.vnc_claims.lock().unwrap().clone()
+    }
+
+    /// Reserve a display until the returned claim is dropped
+    pub fn claim_vnc_display(&self, display: u16) -> VncDisplayClaim {
+        self.vnc_claims.lock().unwrap().insert(display);
+        VncDisplayClaim {
+            display,
+            claims: self.vnc_claims.clone(),
+        }
+    }
+
+    /// Serialize SPICE port allocation, as `lock_vnc_allocation` does for VNC
+    pub async fn lock_spice_allocation(&self) -> MutexGuard<'_, ()> {
+        self.spice_allocation.lock().await
+    }
+
+    /// SPICE ports claimed by starts that haven't been recorded yet
+    pub fn claimed_spice_ports(&self) -> HashSet<u16> {
+        self.spice_claims.lock().unwrap().clone()
+    }
+
+    /// Reserve a SPICE port until the returned claim is dropped
+    pub fn claim_spice_port(&self, port: u16) -> SpicePortClaim {
+        self.spice_claims.lock().unwrap().insert(port);
+        SpicePortClaim {
+            port,
+            claims: self.spice_claims.clone(),
+        }
+    }
+
+    /// Whether an image's file matched its checksum last time and hasn't
+    /// changed size or modification time since
+    pub fn is_verified(&self, image_id: Uuid, file: &VerifiedFile) -> bool {
+        self.verified_images.lock().unwrap().get(&image_id) == Some(file)
+    }
+
+    /// Remember that an image's file, as described by `file`, matched its checksum
+    pub fn mark_verified(&self, image_id: Uuid, file: VerifiedFile) {
+        self.verified_images.lock().unwrap().insert(image_id, file);
+    }
+}
+
+/// A change in a node's lifecycle, published on `AppState::events`.
+///
+/// Handlers publish after the change is persisted, so subscribers can react
+/// to it (WebSocket clients, metrics, auditing) without the handlers knowing
+/// about them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NodeEvent {
+    Created {
+        node_id: Uuid,
+    },
+    Started {
+        node_id: Uuid,
+    },
+    Stopped {
+        node_id: Uuid,
+    },
+    Wiped {
+        node_id: Uuid,
+    },
+    Deleted {
+        node_id: Uuid,
+    },
+    Renamed {
+        node_id: Uuid,
+        name: String,
+    },
+    /// QEMU exited without being stopped through the API. `crashed` is unset
+    /// when it exited cleanly, e.g. because the guest powered itself off.
+    Exited {
+        node_id: Uuid,
+        crashed: bool,
+        exit_status: String,
+    },
+    VncEnabled {
+        node_id: Uuid,
+        vnc_port: u16,
+    },
+    VncDisabled {
+        node_id: Uuid,
+    },
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
-    pub env: Arc<HashMap<String, String>>,
+    pub config: Arc<Config>,
+    pub instances: InstanceRegistry,
+    pub metrics: PrometheusHandle,
+    pub events: broadcast::Sender<NodeEvent>,
+}
+
+impl AppState {
+    /// Publish a node event to every subscriber; it is dropped if there are none
+    pub fn publish(&self, event: NodeEvent) {
+        let _ = self.events.send(event);
+    }
 }
 
-#[derive(Debug, Serialize)]
+/// Failure reported in the `error` field of an `ApiResponse`.
+///
+/// Serializes as `{"code": "not_found", "message": "..."}` so clients can
+/// branch on `code` without parsing the message; the variant also decides
+/// the HTTP status.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "code", content = "message", rename_all = "snake_case")]
+pub enum ApiError {
+    /// 400: the request itself is malformed or out of range
+    Validation(String),
+    /// 401: no credentials or the wrong ones
+    Unauthorized(String),
+    /// 403: the operation is disabled for everyone
+    Forbidden(String),
+    NotFound(String),
+    /// 408: the request took longer than the server allows
+    RequestTimeout(String),
+    /// 409: the request conflicts with the current state, e.g. a running node
+    Conflict(String),
+    /// 413: the request body is over a configured size limit
+    PayloadTooLarge(String),
+    Internal(String),
+    /// 502: Guacamole or another dependency failed
+    Upstream(String),
+    /// 503: a dependency this server relies on is unreachable
+    Unavailable(String),
+    /// 504: a dependency didn't answer in time
+    UpstreamTimeout(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        ApiResponse::<NoData>::error(self).into_response()
+    }
+}
+
+/// `data` of responses that carry none; serializes as `null`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NoData;
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
-    pub error: Option<String>,
+    pub error: Option<ApiError>,
+    /// HTTP status sent with the body; not part of the JSON
+    #[serde(skip)]
+    pub status: StatusCode,
 }
 
 impl<T: Serialize> ApiResponse<T> {
@@ -145,63 +799,543 @@ impl<T: Serialize> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            status: StatusCode::OK,
         }
     }
 
-    pub fn error(message: String) -> ApiResponse<()> {
+    /// Error response, sent with the status matching the error's kind
+    pub fn error(error: ApiError) -> ApiResponse<NoData> {
         ApiResponse {
             success: false,
             data: None,
-            error: Some(message),
+            status: error.status(),
+            error: Some(error),
         }
     }
+
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl<T: Serialize> IntoResponse for ApiResponse<T> {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
 }
 
 // ============================================================================
 // Request/Response DTOs
 // ============================================================================
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateImageRequest {
     pub name: String,
     pub path: String,
     pub parent_id: Option<Uuid>,
     pub description: Option<String>,
+    /// Expected hex SHA-256; registration fails if the file doesn't match
+    pub sha256: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateNodeRequest {
     pub name: String,
     /// ID of the image to base this node on
     pub image_id: Uuid,
+    /// Guest memory in MB (defaults to 1024)
+    pub memory_mb: Option<u32>,
+    /// Number of guest CPU cores (defaults to 1)
+    pub cpu_cores: Option<u32>,
+    /// Defaults to `User` when port forwards are given, otherwise to `Bridge`
+    /// if `QEMU_BRIDGE` is set and `Isolated` if not
+    pub network_mode: Option<NetworkMode>,
+    #[serde(default)]
+    pub port_forwards: Vec<PortForward>,
+    #[serde(default)]
+    pub firmware: Firmware,
+    /// Also serve a SPICE display for external clients; VNC stays available
+    #[serde(default)]
+    pub spice: bool,
+    /// Delay, loss and rate limit for `Bridge` networking
+    pub netem: Option<NetemParams>,
+}
+
+/// Create `count` identical nodes named `{name_prefix}-01`, `{name_prefix}-02`, ...
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BulkCreateNodesRequest {
+    pub count: u32,
+    pub name_prefix: String,
+    pub image_id: Uuid,
+    pub memory_mb: Option<u32>,
+    pub cpu_cores: Option<u32>,
+    /// Port forwards aren't accepted since every node would claim the same host ports
+    pub network_mode: Option<NetworkMode>,
+    #[serde(default)]
+    pub firmware: Firmware,
+    #[serde(default)]
+    pub spice: bool,
+    pub netem: Option<NetemParams>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateLinkRequest {
+    pub node_a: Uuid,
+    pub node_b: Uuid,
+    pub latency_ms: Option<u32>,
+    pub bandwidth_kbps: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListNodesQuery {
+    /// Page size, capped by the server
+    pub limit: Option<u32>,
+    /// Number of nodes to skip
+    #[serde(default)]
+    pub offset: u32,
+    /// Only return nodes with this status
+    pub status: Option<NodeStatus>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AuditLogQuery {
+    /// Only entries at or after this time (RFC 3339)
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries before this time (RFC 3339)
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RestartQuery {
+    /// Respawn the QEMU process instead of resetting the guest
+    #[serde(default)]
+    pub hard: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CloneNodeRequest {
+    /// Name of the new node
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RenameNodeRequest {
+    pub name: String,
+    /// Also rename the node's Guacamole connection, if it has one
+    #[serde(default)]
+    pub rename_connection: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateSnapshotRequest {
+    pub name: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CpuCountResponse {
+    /// CPUs plugged into the running VM
+    pub cpu_cores: u32,
+    /// CPUs the VM can grow to without a restart
+    pub max_cpus: u32,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CommitNodeRequest {
+    /// Name of the new image
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportImageRequest {
+    /// http or https URL of a qcow2 image
+    pub url: String,
+    pub name: String,
+    /// Image the downloaded qcow2 is an overlay of, if any
+    pub parent_id: Option<Uuid>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportedImage {
+    pub image: Image,
+    /// Bytes downloaded into `IMAGE_DIR`
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AttachCdromRequest {
+    /// ISO image path relative to `ISO_DIR`
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetMemoryRequest {
+    /// Memory the running guest should have, in MB
+    pub memory_mb: u32,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MonitorCommandRequest {
+    /// QMP command to execute, e.g. `query-block`
+    pub command: String,
+    pub arguments: Option<Value>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateVncConnectionRequest {
     pub connection_name: Option<String>,
+    /// Node to bind the connection to, if any
+    pub node_id: Option<Uuid>,
     pub vnc_host: String,
     pub vnc_port: u16,
+    /// VNC password; defaults to the password of the bound node's VNC server
+    pub password: Option<String>,
+    /// Display tuning; every option defaults to Guacamole's own default
+    #[serde(default)]
+    pub display: VncDisplayOptions,
+    /// Guacamole connection group to file the connection under, e.g. a lab
+    /// or student name; created if missing
+    pub group: Option<String>,
+    /// Record sessions under `GUAC_RECORDING_DIR`
+    #[serde(default)]
+    pub record: bool,
+    /// File name for the recording; defaults to the connection name plus
+    /// the session's date and time
+    pub recording_name: Option<String>,
+}
+
+/// Body of `POST /node/{id}/vnc`; the whole body may be omitted
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct EnableNodeVncRequest {
+    /// Defaults to the node's name
+    pub connection_name: Option<String>,
+    /// VNC password; a random one is generated when unset
+    pub password: Option<String>,
+    pub display: VncDisplayOptions,
+    /// Guacamole connection group to file the connection under; created if missing
+    pub group: Option<String>,
+    /// Record sessions under `GUAC_RECORDING_DIR`
+    pub record: bool,
+    /// File name for the recording; defaults to the connection name plus
+    /// the session's date and time
+    pub recording_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateSshConnectionRequest {
+    pub connection_name: Option<String>,
+    pub ssh_host: String,
+    /// Defaults to 22
+    pub ssh_port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+    /// Guacamole connection group to file the connection under; created if missing
+    pub group: Option<String>,
+    /// Record sessions under `GUAC_RECORDING_DIR`
+    #[serde(default)]
+    pub record: bool,
+    /// File name for the recording; defaults to the connection name plus
+    /// the session's date and time
+    pub recording_name: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct CreateVncConnectionResponse {
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ShareConnectionRequest {
+    /// Whether viewers are kept from sending input; defaults to true
+    pub read_only: Option<bool>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateConnectionResponse {
     pub connection_name: String,
     pub connection_id: String,
+    /// Identifier of the connection group holding the connection (`ROOT` for the top level)
+    pub parent_identifier: String,
     pub client_url: String,
     pub websocket_url: String,
     pub tunnel_url: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ImageWithAncestors {
     pub image: Image,
     /// Full chain of ancestor images from immediate parent to root base image
     pub ancestors: Vec<Image>,
 }
 
-#[derive(Debug, Serialize)]
+impl ImageWithAncestors {
+    /// Build from an image chain ordered from base to leaf, as returned by `qemu::get_image_chain`
+    pub fn from_chain(mut chain: Vec<Image>) -> Option<Self> {
+        let image = chain.pop()?;
+        chain.reverse();
+        Some(Self {
+            image,
+            ancestors: chain,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct NodeWithImage {
     pub node: Node,
     /// The image this node is based on, with its full ancestry chain
     pub image: ImageWithAncestors,
 }
+
+/// One page of `GET /node`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NodeList {
+    pub nodes: Vec<NodeWithImage>,
+    /// Nodes matching the filter across all pages
+    pub total: i64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImageVerification {
+    pub image_id: Uuid,
+    /// Checksum recorded at registration, if any
+    pub expected: Option<String>,
+    pub actual: String,
+    /// False only when a recorded checksum differs from the file
+    pub matches: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NodeStatusResponse {
+    pub node_id: Uuid,
+    /// Status recorded in the database
+    pub status: NodeStatus,
+    /// Live guest run state reported by QEMU (e.g. `running`, `paused`,
+    /// `shutdown`), or `stopped` when there is no process
+    pub run_state: String,
+    pub vnc_port: Option<u16>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SpiceInfoResponse {
+    pub node_id: Uuid,
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: &'static str,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DependencyStatus {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl<E: std::fmt::Display> From<Result<(), E>> for DependencyStatus {
+    fn from(result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => Self {
+                ok: true,
+                error: None,
+            },
+            Err(e) => Self {
+                ok: false,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub database: DependencyStatus,
+    pub guacamole: DependencyStatus,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A base directory with an empty `sub` directory, next to a directory
+    /// outside it holding `secret.qcow2`
+    struct Fixture {
+        root: TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = tempfile::tempdir().unwrap();
+            std::fs::create_dir_all(root.path().join("base/sub")).unwrap();
+            std::fs::create_dir(root.path().join("outside")).unwrap();
+            std::fs::write(root.path().join("outside/secret.qcow2"), b"").unwrap();
+            Self { root }
+        }
+
+        fn base(&self) -> PathBuf {
+            self.root.path().join("base")
+        }
+
+        fn outside(&self) -> PathBuf {
+            self.root.path().join("outside")
+        }
+    }
+
+    fn is_traversal(result: Result<PathBuf, ImagePathError>) -> bool {
+        matches!(result, Err(ImagePathError::PathTraversal(_)))
+    }
+
+    #[test]
+    fn rejects_a_symlink_to_a_file_outside_the_base() {
+        let fixture = Fixture::new();
+        symlink(
+            fixture.outside().join("secret.qcow2"),
+            fixture.base().join("link.qcow2"),
+        )
+        .unwrap();
+
+        assert!(is_traversal(validate_and_resolve_path(
+            &fixture.base(),
+            "link.qcow2"
+        )));
+    }
+
+    #[test]
+    fn rejects_a_new_file_under_a_symlinked_directory_outside_the_base() {
+        let fixture = Fixture::new();
+        symlink(fixture.outside(), fixture.base().join("escape")).unwrap();
+
+        assert!(is_traversal(validate_and_resolve_path(
+            &fixture.base(),
+            "escape/new.qcow2"
+        )));
+    }
+
+    #[test]
+    fn rejects_a_dangling_symlink() {
+        let fixture = Fixture::new();
+        symlink(
+            fixture.outside().join("missing.qcow2"),
+            fixture.base().join("dangling.qcow2"),
+        )
+        .unwrap();
+
+        assert!(is_traversal(validate_and_resolve_path(
+            &fixture.base(),
+            "dangling.qcow2"
+        )));
+    }
+
+    #[test]
+    fn rejects_parent_traversal() {
+        let fixture = Fixture::new();
+
+        assert!(is_traversal(validate_and_resolve_path(
+            &fixture.base(),
+            "../outside/secret.qcow2"
+        )));
+        assert!(is_traversal(validate_and_resolve_path(
+            &fixture.base(),
+            "sub/../../new.qcow2"
+        )));
+    }
+
+    #[test]
+    fn accepts_a_new_file_in_a_subdirectory() {
+        let fixture = Fixture::new();
+
+        let resolved = validate_and_resolve_path(&fixture.base(), "sub/new.qcow2").unwrap();
+        assert_eq!(
+            resolved,
+            fixture.base().canonicalize().unwrap().join("sub/new.qcow2")
+        );
+    }
+
+    #[test]
+    fn a_node_can_only_be_started_once_at_a_time() {
+        let registry = InstanceRegistry::default();
+        let node_id = Uuid::now_v7();
+
+        let claim = registry.claim_start(node_id).unwrap();
+        assert!(registry.claim_start(node_id).is_none());
+        assert!(registry.claim_start(Uuid::now_v7()).is_some());
+
+        drop(claim);
+        assert!(registry.claim_start(node_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn reserve_refuses_a_node_that_already_holds_resources() {
+        let registry = InstanceRegistry::default();
+        let node_id = Uuid::now_v7();
+        let usage = ResourceUsage {
+            memory_mb: 512,
+            cpu_cores: 1,
+        };
+        let budget = ResourceBudget {
+            memory_mb: Some(1024),
+            cpu_cores: None,
+        };
+
+        registry.reserve(node_id, usage, budget).await.unwrap();
+        assert!(matches!(
+            registry.reserve(node_id, usage, budget).await,
+            Err(ReservationError::AlreadyReserved)
+        ));
+
+        // Resizing replaces the node's own claim instead of adding to it
+        let grown = ResourceUsage {
+            memory_mb: 1024,
+            ..usage
+        };
+        registry.resize(node_id, grown, budget).await.unwrap();
+        assert!(matches!(
+            registry.reserve(Uuid::now_v7(), usage, budget).await,
+            Err(ReservationError::OverBudget(in_use)) if in_use.memory_mb == 1024
+        ));
+
+        registry.release(node_id).await;
+        registry.reserve(node_id, usage, budget).await.unwrap();
+    }
+
+    #[test]
+    fn a_verified_image_is_rehashed_once_its_file_changes() {
+        let registry = InstanceRegistry::default();
+        let image_id = Uuid::now_v7();
+        let file = VerifiedFile {
+            len: 4096,
+            modified: SystemTime::UNIX_EPOCH,
+            sha256: "ab".repeat(32),
+        };
+        assert!(!registry.is_verified(image_id, &file));
+
+        registry.mark_verified(image_id, file.clone());
+        assert!(registry.is_verified(image_id, &file));
+        assert!(!registry.is_verified(Uuid::now_v7(), &file));
+        for changed in [
+            VerifiedFile {
+                len: 8192,
+                ..file.clone()
+            },
+            VerifiedFile {
+                modified: SystemTime::now(),
+                ..file.clone()
+            },
+            VerifiedFile {
+                sha256: "cd".repeat(32),
+                ..file.clone()
+            },
+        ] {
+            assert!(!registry.is_verified(image_id, &changed));
+        }
+    }
+}
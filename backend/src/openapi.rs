@@ -0,0 +1,136 @@
+use axum::{Json, response::Html};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        OpenApi as OpenApiSpec,
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+
+use crate::auth::API_KEY_HEADER;
+use crate::routes;
+
+/// Swagger UI version loaded from the CDN by `swagger_ui`
+const SWAGGER_UI_VERSION: &str = "5.17.14";
+
+/// OpenAPI description of the routes in `routes.rs`, built from the
+/// `#[utoipa::path]` annotations on the handlers and the `ToSchema` derives
+/// on the models they use.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "network-lab backend"),
+    paths(
+        routes::create_node,
+        routes::create_nodes_bulk,
+        routes::list_nodes,
+        routes::get_node,
+        routes::rename_node,
+        routes::get_node_status,
+        routes::get_node_disk_usage,
+        routes::get_node_blocks,
+        routes::get_spice_info,
+        routes::get_console_log,
+        routes::run_node,
+        routes::restart_node,
+        routes::stop_node,
+        routes::delete_node,
+        routes::clone_node,
+        routes::commit_node,
+        routes::wipe_node,
+        routes::pause_node,
+        routes::resume_node,
+        routes::set_node_memory,
+        routes::add_node_cpu,
+        routes::attach_cdrom,
+        routes::detach_cdrom,
+        routes::start_capture,
+        routes::stop_capture,
+        routes::get_capture,
+        routes::create_snapshot,
+        routes::list_snapshots,
+        routes::restore_snapshot,
+        routes::run_monitor_command,
+        routes::create_link,
+        routes::list_links,
+        routes::get_link,
+        routes::delete_link,
+        routes::create_image,
+        routes::import_image,
+        routes::upload_image,
+        routes::verify_image,
+        routes::list_images,
+        routes::get_image,
+        routes::delete_image,
+        routes::enable_node_vnc,
+        routes::disable_node_vnc,
+        routes::create_vnc_connection,
+        routes::node_events,
+        routes::create_ssh_connection,
+        routes::list_audit_log,
+        routes::share_connection,
+        routes::list_connections,
+        routes::health,
+        routes::ready,
+    ),
+    tags(
+        (name = "nodes", description = "Virtual machines and their lifecycle"),
+        (name = "snapshots", description = "Internal snapshots of running nodes"),
+        (name = "capture", description = "Packet captures of bridged nodes"),
+        (name = "links", description = "Point-to-point links between nodes"),
+        (name = "images", description = "Base and layered disk images"),
+        (name = "connections", description = "Guacamole VNC and SSH connections"),
+        (name = "audit", description = "Log of state-changing operations"),
+        (name = "health", description = "Liveness and readiness probes"),
+    ),
+    modifiers(&ApiKeySecurity)
+)]
+pub struct ApiDoc;
+
+/// Declare the two ways of sending the API key. Only mutating requests need
+/// it, so it is documented rather than required on every operation.
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut OpenApiSpec) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+    }
+}
+
+/// GET /api-docs/openapi.json - OpenAPI description of this API
+pub async fn openapi_json() -> Json<OpenApiSpec> {
+    Json(ApiDoc::openapi())
+}
+
+/// GET /api-docs - Swagger UI for the OpenAPI description
+///
+/// The UI's assets are loaded from a CDN by the browser, so the server
+/// doesn't have to bundle them.
+pub async fn swagger_ui() -> Html<String> {
+    Html(format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>network-lab API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@{version}/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@{version}/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({{ url: "/api-docs/openapi.json", dom_id: "#swagger-ui" }});
+  </script>
+</body>
+</html>
+"##,
+        version = SWAGGER_UI_VERSION
+    ))
+}
@@ -1,15 +1,84 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    str::FromStr,
+    time::Duration,
+};
 
+use ::metrics::counter;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
-use tokio::process::Child;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        UnixStream,
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    process::{Child, Command},
+    sync::Mutex,
+    time::{Instant, sleep, timeout},
+};
+use tracing::{debug, info, trace, warn};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::models::{AppState, Image, Node};
+use crate::metrics;
+use crate::models::{
+    AppState, Firmware, Image, Link, NetemParams, NetworkMode, Node, NodeStatus, PortForward,
+    ResourceBudget, ResourceUsage, SpicePortClaim, VerifiedFile, VncDisplayClaim,
+};
+
+const QEMU_BINARY: &str = "qemu-system-x86_64";
+const QEMU_IMG_BINARY: &str = "qemu-img";
+const IP_BINARY: &str = "ip";
+const TC_BINARY: &str = "tc";
+const TCPDUMP_BINARY: &str = "tcpdump";
+/// How long tcpdump gets to fail on startup, e.g. for lack of permissions,
+/// before a capture is reported as started
+const CAPTURE_STARTUP_GRACE: Duration = Duration::from_millis(300);
+/// Where the kernel lists network interfaces, and a bridge's ports under `brif`
+const SYS_CLASS_NET: &str = "/sys/class/net";
+const VNC_BASE_PORT: u16 = 5900;
+/// Displays handed out to nodes; display 0 is left for the host's own server
+const FIRST_VNC_DISPLAY: u16 = 1;
+const LAST_VNC_DISPLAY: u16 = 99;
+/// Number of ports, starting at the SPICE port base, handed out to nodes
+const SPICE_PORT_RANGE: u16 = 100;
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MIN_MEMORY_MB: u32 = 64;
+const MONITOR_TIMEOUT: Duration = Duration::from_secs(5);
+/// A guest without a running agent never answers, so give up on it quickly
+const GUEST_AGENT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Name of the virtio-serial port qemu-guest-agent looks for
+const GUEST_AGENT_PORT_NAME: &str = "org.qemu.guest_agent.0";
+const MAX_IMAGE_CHAIN_DEPTH: i32 = 64;
+/// Saving or loading VM state copies guest RAM, which can take a while
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(120);
+const MAX_SNAPSHOT_NAME_LEN: usize = 64;
+/// Drive id of the instance overlay, as reported by `query-block`
+const DISK_DRIVE_ID: &str = "disk0";
+const BALLOON_DEVICE_ID: &str = "balloon0";
+const CDROM_DEVICE_ID: &str = "cdrom0";
+const BYTES_PER_MB: u64 = 1024 * 1024;
+/// How long reading an image's backing chain may take before it is assumed to loop
+const BACKING_CHAIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Read size used when checksumming image files
+const CHECKSUM_BUFFER_SIZE: usize = 1024 * 1024;
+const KVM_DEVICE: &str = "/dev/kvm";
+/// VNC authentication only uses the first 8 characters of a password
+const MAX_VNC_PASSWORD_LEN: usize = 8;
+/// Characters used for generated VNC passwords
+const VNC_PASSWORD_CHARSET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
 
 #[derive(Debug, Error)]
 pub enum QemuError {
     #[error("Failed to spawn QEMU process: {0}")]
-    SpawnFailed(#[from] std::io::Error),
+    SpawnFailed(#[from] io::Error),
 
     #[error("Node is not running")]
     NodeNotRunning,
@@ -26,6 +95,15 @@ pub enum QemuError {
     #[error("Failed to allocate VNC port")]
     VncPortAllocationFailed,
 
+    #[error("Invalid VNC password: {0}")]
+    InvalidVncPassword(String),
+
+    #[error("SPICE is not enabled for this node")]
+    SpiceNotEnabled,
+
+    #[error("Failed to allocate SPICE port")]
+    SpicePortAllocationFailed,
+
     #[error("Invalid node configuration: {0}")]
     InvalidConfiguration(String),
 
@@ -40,6 +118,31 @@ pub enum QemuError {
 
     #[error("Failed to resolve image path: {0}")]
     ImagePathError(String),
+
+    #[error("Overlay already exists: {0}")]
+    OverlayAlreadyExists(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Invalid snapshot name: {0}")]
+    InvalidSnapshotName(String),
+
+    #[error("Snapshot not found: {0}")]
+    SnapshotNotFound(String),
+
+    #[error("Failed to set up networking: {0}")]
+    NetworkSetup(String),
+
+    #[error("UEFI firmware unavailable: {0}")]
+    FirmwareNotFound(String),
+
+    #[error("Image {image_id} does not match its checksum: expected {expected}, found {actual}")]
+    ChecksumMismatch {
+        image_id: Uuid,
+        expected: String,
+        actual: String,
+    },
 }
 
 /// Configuration options for starting a QEMU VM
@@ -49,33 +152,470 @@ pub struct QemuConfig {
     pub memory_mb: u64,
     /// Number of CPU cores
     pub cpu_cores: u32,
+    /// CPUs the VM can grow to through hotplug
+    pub max_cpus: u32,
     /// Enable KVM acceleration
     pub enable_kvm: bool,
-    /// VNC display number (if enabled)
+    /// Add a virtio-balloon device so memory can be adjusted at runtime
+    pub balloon: bool,
+    /// Add an empty CD-ROM drive that ISOs can be inserted into at runtime
+    pub cdrom: bool,
+    /// Add a virtio-serial channel for qemu-guest-agent
+    pub guest_agent: bool,
+    /// VNC display number (if enabled). The server requires a password, so
+    /// clients can't connect until one is set with `set_vnc_password`
     pub vnc_display: Option<u16>,
+    /// Port of an additional SPICE display, which has no authentication
+    pub spice_port: Option<u16>,
+    /// How the VM's NIC is connected; the VM has no NIC when unset
+    pub network: Option<NetworkConfig>,
+    /// Point-to-point links, each realized as an extra NIC on the link's bridge
+    pub links: Vec<LinkEndpoint>,
+    /// File the guest's serial console output is written to
+    pub serial_log: Option<PathBuf>,
+    /// Boot with OVMF instead of SeaBIOS
+    pub uefi: Option<UefiFirmware>,
     /// Additional QEMU arguments
     pub extra_args: Vec<String>,
 }
 
+/// What to do when a VM asks for KVM but the host can't provide it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KvmMode {
+    /// Refuse to start the VM
+    Strict,
+    /// Start the VM with TCG software emulation instead
+    #[default]
+    Lenient,
+}
+
+impl FromStr for KvmMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Ok(Self::Strict),
+            "lenient" => Ok(Self::Lenient),
+            _ => Err(format!("unknown KVM mode {value:?}")),
+        }
+    }
+}
+
+/// Network backend of a VM's virtio NIC
+#[derive(Debug, Clone)]
+pub enum NetworkConfig {
+    /// Connect to an existing Linux bridge through a per-node TAP device.
+    /// Creating the device needs `CAP_NET_ADMIN`.
+    Tap {
+        /// Name of the bridge the TAP device is added to
+        bridge: String,
+        /// MAC address of the guest NIC; derived from the node id when unset
+        mac: Option<String>,
+        /// Shaping applied to the TAP device once it is created
+        netem: Option<NetemParams>,
+    },
+    /// QEMU's user-mode (SLIRP) stack: NAT-style outbound connectivity
+    /// through the host without root, reachable inbound only through the
+    /// given TCP port forwards
+    User { forwards: Vec<PortForward> },
+}
+
+/// OVMF firmware images for a UEFI boot
+#[derive(Debug, Clone)]
+pub struct UefiFirmware {
+    /// Read-only firmware code, shared by all nodes
+    pub code: PathBuf,
+    /// Pristine variable store copied for each node
+    pub vars_template: PathBuf,
+    /// The node's own writable variable store
+    pub vars: PathBuf,
+}
+
+/// This VM's end of a link to another VM
+#[derive(Debug, Clone)]
+pub struct LinkEndpoint {
+    pub link_id: Uuid,
+    /// Bridge joining the two ends, created by whichever end starts first
+    pub bridge: String,
+    /// TAP device of this VM's end, added to `bridge`
+    pub tap_device: String,
+    /// Shaping of frames delivered to this end, from the link's latency and
+    /// bandwidth
+    pub netem: Option<NetemParams>,
+}
+
 impl Default for QemuConfig {
     fn default() -> Self {
         Self {
             memory_mb: 1024,
             cpu_cores: 1,
+            max_cpus: 1,
             enable_kvm: true,
+            balloon: true,
+            cdrom: true,
+            guest_agent: true,
             vnc_display: None,
+            spice_port: None,
+            network: None,
+            links: Vec::new(),
+            serial_log: None,
+            uefi: None,
             extra_args: Vec::new(),
         }
     }
 }
 
+impl QemuConfig {
+    /// Default configuration with the node's own memory, CPU and network
+    /// settings applied
+    pub fn for_node(node: &Node, app_state: &AppState) -> Result<Self, QemuError> {
+        let defaults = Self::default();
+        let network = match network_mode(node.network_mode, &node.port_forwards, app_state) {
+            NetworkMode::Isolated => None,
+            NetworkMode::User => Some(NetworkConfig::User {
+                forwards: node.port_forwards.clone(),
+            }),
+            NetworkMode::Bridge => Some(NetworkConfig::Tap {
+                bridge: bridge_name(app_state).ok_or_else(|| {
+                    QemuError::InvalidConfiguration(
+                        "bridge networking requires QEMU_BRIDGE to be set".into(),
+                    )
+                })?,
+                mac: None,
+                netem: node.netem,
+            }),
+        };
+
+        let serial_log = node
+            .get_console_log_path(app_state)
+            .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+
+        let uefi = match node.firmware {
+            Firmware::Bios => None,
+            Firmware::Uefi => {
+                let (code, vars_template) = ovmf_paths(app_state)?;
+                let vars = node
+                    .get_uefi_vars_path(app_state)
+                    .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+                Some(UefiFirmware {
+                    code,
+                    vars_template,
+                    vars,
+                })
+            }
+        };
+
+        let cpu_cores = node.cpu_cores.unwrap_or(defaults.cpu_cores);
+        // Reserve hotplug headroom up to the per-node core limit
+        let max_cpus = cpu_cores.max(app_state.config.qemu.max_cpu_cores);
+
+        Ok(Self {
+            memory_mb: node.memory_mb.map(u64::from).unwrap_or(defaults.memory_mb),
+            cpu_cores,
+            max_cpus,
+            network,
+            serial_log: Some(serial_log),
+            uefi,
+            ..defaults
+        })
+    }
+
+    /// Add a NIC for each of `links` that `node_id` is an end of
+    pub fn with_links(mut self, node_id: Uuid, links: &[Link]) -> Self {
+        self.links.extend(links.iter().filter_map(|link| {
+            let end = if node_id == link.node_a {
+                'a'
+            } else if node_id == link.node_b {
+                'b'
+            } else {
+                return None;
+            };
+            let netem = (link.latency_ms.is_some() || link.bandwidth_kbps.is_some()).then_some(
+                NetemParams {
+                    delay_ms: link.latency_ms,
+                    jitter_ms: None,
+                    loss_pct: None,
+                    rate_kbit: link.bandwidth_kbps,
+                },
+            );
+            Some(LinkEndpoint {
+                link_id: link.id,
+                bridge: link_bridge_name(link.id),
+                tap_device: link_tap_name(link.id, end),
+                netem,
+            })
+        }));
+        self
+    }
+
+    /// Serve a SPICE display on `port` in addition to VNC
+    pub fn with_spice(mut self, port: u16) -> Self {
+        self.spice_port = Some(port);
+        self
+    }
+
+    /// Memory and cores the VM will use
+    pub fn resource_usage(&self) -> ResourceUsage {
+        ResourceUsage {
+            memory_mb: self.memory_mb,
+            cpu_cores: u64::from(self.cpu_cores),
+        }
+    }
+}
+
+/// Locate the OVMF code and variable store template from `OVMF_CODE_PATH`
+/// and `OVMF_VARS_PATH`, failing if either is unset or missing
+fn ovmf_paths(app_state: &AppState) -> Result<(PathBuf, PathBuf), QemuError> {
+    let config = &app_state.config.qemu;
+    let resolve = |name: &str, path: &Option<PathBuf>| {
+        let path = path
+            .clone()
+            .ok_or_else(|| QemuError::FirmwareNotFound(format!("{} is not set", name)))?;
+        if !path.is_file() {
+            return Err(QemuError::FirmwareNotFound(format!(
+                "{} ({}) does not exist",
+                name,
+                path.display()
+            )));
+        }
+        Ok(path)
+    };
+
+    Ok((
+        resolve("OVMF_CODE_PATH", &config.ovmf_code_path)?,
+        resolve("OVMF_VARS_PATH", &config.ovmf_vars_path)?,
+    ))
+}
+
+/// Check that a node requesting `firmware` could be booted
+///
+/// # Arguments
+/// * `firmware` - Requested firmware
+/// * `app_state` - Application state whose config holds the OVMF paths
+pub fn validate_firmware(firmware: Firmware, app_state: &AppState) -> Result<(), QemuError> {
+    match firmware {
+        Firmware::Bios => Ok(()),
+        Firmware::Uefi => ovmf_paths(app_state).map(|_| ()),
+    }
+}
+
+/// Delete a node's UEFI variable store so the next boot starts from the template
+///
+/// # Arguments
+/// * `node` - The node whose variable store to delete
+/// * `app_state` - Application state whose config holds the overlay directory
+pub async fn delete_uefi_vars(node: &Node, app_state: &AppState) -> Result<(), QemuError> {
+    let vars = node
+        .get_uefi_vars_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    match tokio::fs::remove_file(&vars).await {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Resolve a node's network mode, applying the defaults for an unset one
+fn network_mode(
+    mode: Option<NetworkMode>,
+    forwards: &[PortForward],
+    app_state: &AppState,
+) -> NetworkMode {
+    match mode {
+        Some(mode) => mode,
+        None if !forwards.is_empty() => NetworkMode::User,
+        None if bridge_name(app_state).is_some() => NetworkMode::Bridge,
+        None => NetworkMode::Isolated,
+    }
+}
+
+fn bridge_name(app_state: &AppState) -> Option<String> {
+    app_state.config.qemu.bridge.clone()
+}
+
+/// Check a requested network setup before it is stored on a node
+///
+/// # Arguments
+/// * `mode` - Requested network mode, if any
+/// * `forwards` - Requested port forwards
+/// * `app_state` - Application state whose config holds the host bridge
+pub fn validate_network(
+    mode: Option<NetworkMode>,
+    forwards: &[PortForward],
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    let mode = network_mode(mode, forwards, app_state);
+
+    if !forwards.is_empty() && mode != NetworkMode::User {
+        return Err(QemuError::InvalidConfiguration(
+            "port_forwards require the User network mode".into(),
+        ));
+    }
+    if mode == NetworkMode::Bridge && bridge_name(app_state).is_none() {
+        return Err(QemuError::InvalidConfiguration(
+            "bridge networking requires QEMU_BRIDGE to be set".into(),
+        ));
+    }
+
+    let mut host_ports = HashSet::new();
+    for forward in forwards {
+        if forward.host_port == 0 || forward.guest_port == 0 {
+            return Err(QemuError::InvalidConfiguration(
+                "forwarded ports must be non-zero".into(),
+            ));
+        }
+        if !host_ports.insert(forward.host_port) {
+            return Err(QemuError::InvalidConfiguration(format!(
+                "host port {} is forwarded more than once",
+                forward.host_port
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check requested netem shaping before it is stored on a node
+///
+/// Shaping is applied to the node's TAP device, so it needs `Bridge` networking.
+pub fn validate_netem(
+    netem: Option<&NetemParams>,
+    mode: Option<NetworkMode>,
+    forwards: &[PortForward],
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    let Some(netem) = netem else {
+        return Ok(());
+    };
+
+    if network_mode(mode, forwards, app_state) != NetworkMode::Bridge {
+        return Err(QemuError::InvalidConfiguration(
+            "netem requires the Bridge network mode".into(),
+        ));
+    }
+    if netem.delay_ms.is_none() && netem.loss_pct.is_none() && netem.rate_kbit.is_none() {
+        return Err(QemuError::InvalidConfiguration(
+            "netem needs at least one of delay_ms, loss_pct or rate_kbit".into(),
+        ));
+    }
+    if netem.jitter_ms.is_some() && netem.delay_ms.is_none() {
+        return Err(QemuError::InvalidConfiguration(
+            "jitter_ms requires delay_ms".into(),
+        ));
+    }
+    if let Some(loss) = netem.loss_pct
+        && !(0.0..=100.0).contains(&loss)
+    {
+        return Err(QemuError::InvalidConfiguration(
+            "loss_pct must be between 0 and 100".into(),
+        ));
+    }
+    if netem.rate_kbit == Some(0) {
+        return Err(QemuError::InvalidConfiguration(
+            "rate_kbit must be greater than zero".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check requested node sizing against the limits set by `QEMU_MAX_MEMORY_MB`
+/// and `QEMU_MAX_CPU_CORES`
+///
+/// # Arguments
+/// * `memory_mb` - Requested guest memory, if any
+/// * `cpu_cores` - Requested CPU core count, if any
+/// * `app_state` - Application state whose config holds the sizing limits
+pub fn validate_resources(
+    memory_mb: Option<u32>,
+    cpu_cores: Option<u32>,
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    let max_memory_mb = app_state.config.qemu.max_memory_mb;
+    if let Some(memory_mb) = memory_mb
+        && !(MIN_MEMORY_MB..=max_memory_mb).contains(&memory_mb)
+    {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "memory_mb must be between {} and {}",
+            MIN_MEMORY_MB, max_memory_mb
+        )));
+    }
+
+    let max_cpu_cores = app_state.config.qemu.max_cpu_cores;
+    if let Some(cpu_cores) = cpu_cores
+        && !(1..=max_cpu_cores).contains(&cpu_cores)
+    {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "cpu_cores must be between 1 and {}",
+            max_cpu_cores
+        )));
+    }
+
+    Ok(())
+}
+
+/// Host-wide limits from `MAX_TOTAL_MEMORY_MB` and `MAX_TOTAL_CORES`
+pub fn resource_budget(app_state: &AppState) -> ResourceBudget {
+    ResourceBudget {
+        memory_mb: app_state.config.qemu.max_total_memory_mb,
+        cpu_cores: app_state.config.qemu.max_total_cores,
+    }
+}
+
+/// An internal snapshot stored in a node's overlay
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub name: String,
+    /// Size of the saved RAM and device state in bytes
+    #[serde(rename(deserialize = "vm-state-size"))]
+    pub vm_state_size: u64,
+    /// Creation time as a Unix timestamp
+    #[serde(rename(deserialize = "date-sec"))]
+    pub date_sec: i64,
+    /// Guest uptime when the snapshot was taken, in seconds
+    #[serde(rename(deserialize = "vm-clock-sec"))]
+    pub vm_clock_sec: i64,
+}
+
 /// Represents a running QEMU instance
 #[derive(Debug)]
 pub struct QemuInstance {
     pub node_id: Uuid,
     pub process: Child,
+    /// Memory the VM was started with, in MB; the balloon can't go above it
+    pub memory_mb: u64,
+    /// CPUs currently plugged into the VM
+    pub cpu_cores: u32,
+    /// CPUs the VM's topology has room for
+    pub max_cpus: u32,
+    /// Whether the VM has a CD-ROM drive
+    pub cdrom_drive: bool,
+    /// ISO image currently inserted in the CD-ROM drive
+    pub cdrom: Option<PathBuf>,
     pub vnc_port: Option<u16>,
+    /// Address the VNC server binds to, as reachable by Guacamole
+    pub vnc_host: String,
+    /// Password the VNC server currently requires
+    pub vnc_password: Option<String>,
+    /// Port of the SPICE server, bound to the same address as VNC
+    pub spice_port: Option<u16>,
     pub monitor_socket: Option<PathBuf>,
+    /// Socket of the qemu-guest-agent channel, if the VM has one
+    pub guest_agent_socket: Option<PathBuf>,
+    /// TAP device created for the VM's NIC, removed when it stops
+    pub tap_device: Option<String>,
+    /// The VM's ends of its links, whose TAP devices are removed when it stops
+    pub links: Vec<LinkEndpoint>,
+    /// Packet capture running on `tap_device`
+    pub capture: Option<PacketCapture>,
+}
+
+/// A tcpdump process writing a node's traffic to a pcap file
+#[derive(Debug)]
+pub struct PacketCapture {
+    process: Child,
+    pub path: PathBuf,
 }
 
 /// Start a QEMU VM for the given node
@@ -84,42 +624,239 @@ pub struct QemuInstance {
 /// * `node` - The node to start
 /// * `image` - The image the node is based on
 /// * `image_chain` - Full chain of ancestor images (for building the disk chain)
-/// * `config` - QEMU configuration options
-/// * `app_state` - Application state containing env and db
+/// * `config` - Per-node QEMU options, see `QemuConfig::for_node`
+/// * `app_state` - Application state whose config holds the image and overlay
+///   directories and the VNC bind address
 ///
 /// # Returns
 /// A `QemuInstance` representing the running VM
 pub async fn start_node(
-    _node: &Node,
-    _image: &Image,
-    _image_chain: &[Image],
-    _config: QemuConfig,
-    _app_state: &AppState,
+    node: &Node,
+    image: &Image,
+    image_chain: &[Image],
+    config: QemuConfig,
+    app_state: &AppState,
 ) -> Result<QemuInstance, QemuError> {
-    // TODO: Implement QEMU VM startup
-    // 1. Resolve the full image chain paths (base -> overlays -> instance overlay)
-    // 2. Ensure instance overlay exists (create if needed, pointing to the image)
-    // 3. Build QEMU command with appropriate arguments
-    // 4. Set up monitor socket for VM control
-    // 5. Spawn the QEMU process
-    // 6. Return the QemuInstance
-    unimplemented!("start_node is not yet implemented")
+    let overlay_path = node
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    let monitor_socket = node
+        .get_monitor_socket_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    let guest_agent_socket = if config.guest_agent {
+        Some(
+            node.get_guest_agent_socket_path(app_state)
+                .map_err(|e| QemuError::ImagePathError(e.to_string()))?,
+        )
+    } else {
+        None
+    };
+
+    validate_image_chain(image_chain, app_state).await?;
+    verify_image_chain(image_chain, app_state).await?;
+
+    let tap_device = match &config.network {
+        Some(NetworkConfig::Tap { bridge, netem, .. }) => {
+            let name = tap_device_name(node.id);
+            create_tap_device(&name, bridge).await?;
+            if let Some(params) = netem
+                && let Err(err) = apply_netem(&name, params).await
+            {
+                let _ = delete_tap_device(&name).await;
+                return Err(err);
+            }
+            Some(name)
+        }
+        _ => None,
+    };
+
+    if let Err(err) = create_link_devices(&config.links).await {
+        if let Some(name) = &tap_device {
+            let _ = delete_tap_device(name).await;
+        }
+        return Err(err);
+    }
+
+    // Only a freshly created overlay is ours to clean up on failure; an
+    // existing one holds the node's disk state and must be left alone.
+    let created_overlay = if overlay_path.exists() {
+        Ok(false)
+    } else {
+        debug!("Creating instance overlay for node {}", node.id);
+        create_instance_overlay(node, image, app_state)
+            .await
+            .map(|()| true)
+    };
+
+    let spawned = match created_overlay {
+        Ok(created) => {
+            let prepared = match &config.serial_log {
+                Some(path) => create_console_log(path).await,
+                None => Ok(()),
+            };
+            let prepared = match (prepared, &config.uefi) {
+                (Ok(()), Some(uefi)) => prepare_uefi_vars(uefi).await,
+                (prepared, _) => prepared,
+            };
+            match prepared {
+                Ok(()) => {
+                    let sockets = [Some(&monitor_socket), guest_agent_socket.as_ref()];
+                    spawn_qemu(node, image_chain, &config, sockets, app_state).await
+                }
+                Err(err) => Err(err),
+            }
+            .map_err(|err| (err, created))
+        }
+        Err(err) => Err((err, false)),
+    };
+
+    let process = match spawned {
+        Ok(process) => process,
+        Err((err, created_overlay)) => {
+            if created_overlay && let Err(cleanup_err) = tokio::fs::remove_file(&overlay_path).await
+            {
+                warn!(
+                    "Failed to clean up overlay {} after failed start: {}",
+                    overlay_path.display(),
+                    cleanup_err
+                );
+            }
+            if let Some(name) = &tap_device
+                && let Err(cleanup_err) = delete_tap_device(name).await
+            {
+                warn!(
+                    "Failed to remove TAP device {} after failed start: {}",
+                    name, cleanup_err
+                );
+            }
+            remove_link_devices(&config.links).await;
+            return Err(err);
+        }
+    };
+
+    info!("Started QEMU for node {} (pid {:?})", node.id, process.id());
+    counter!(metrics::NODES_STARTED).increment(1);
+
+    Ok(QemuInstance {
+        node_id: node.id,
+        process,
+        memory_mb: config.memory_mb,
+        cpu_cores: config.cpu_cores,
+        max_cpus: config.max_cpus.max(config.cpu_cores),
+        cdrom_drive: config.cdrom,
+        cdrom: None,
+        vnc_port: config.vnc_display.map(|display| VNC_BASE_PORT + display),
+        vnc_host: app_state.config.qemu.vnc_bind_host.clone(),
+        vnc_password: None,
+        spice_port: config.spice_port,
+        monitor_socket: Some(monitor_socket),
+        guest_agent_socket,
+        tap_device,
+        links: config.links,
+        capture: None,
+    })
+}
+
+/// Build the argument list and spawn the QEMU process
+async fn spawn_qemu(
+    node: &Node,
+    image_chain: &[Image],
+    config: &QemuConfig,
+    sockets: [Option<&PathBuf>; 2],
+    app_state: &AppState,
+) -> Result<Child, QemuError> {
+    let args = build_qemu_args(node, image_chain, config, app_state)?;
+
+    // A socket left behind by a previous run would make QEMU fail to bind
+    for socket in sockets.into_iter().flatten() {
+        if socket.exists() {
+            tokio::fs::remove_file(socket).await?;
+        }
+    }
+
+    trace!("Spawning {} {:?}", QEMU_BINARY, args);
+
+    let process = Command::new(QEMU_BINARY)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    Ok(process)
 }
 
 /// Stop a running QEMU VM
 ///
+/// Asks qemu-guest-agent to shut the guest down, or requests an ACPI
+/// shutdown if the agent doesn't answer, and waits for the guest to power
+/// off, falling back to `kill_node` once `timeout` has elapsed.
+///
 /// # Arguments
 /// * `instance` - The QEMU instance to stop
+/// * `timeout` - How long to wait for the guest to shut down by itself
 ///
 /// # Returns
 /// Ok(()) if the VM was stopped successfully
-pub async fn stop_node(_instance: &mut QemuInstance) -> Result<(), QemuError> {
-    // TODO: Implement graceful QEMU shutdown
-    // 1. Send shutdown command via monitor socket (ACPI shutdown)
-    // 2. Wait for process to exit with timeout
-    // 3. Force kill if timeout exceeded
-    // 4. Clean up resources
-    unimplemented!("stop_node is not yet implemented")
+pub async fn stop_node(instance: &mut QemuInstance, timeout: Duration) -> Result<(), QemuError> {
+    if !process_alive(instance).await? {
+        return Err(QemuError::NodeNotRunning);
+    }
+
+    let Some(socket_path) = instance.monitor_socket.clone() else {
+        warn!(
+            "Node {} has no monitor socket, killing it instead",
+            instance.node_id
+        );
+        return kill_node(instance).await;
+    };
+
+    match guest_agent_shutdown(instance).await {
+        Ok(()) => debug!(
+            "Asked the guest agent of node {} to shut down",
+            instance.node_id
+        ),
+        Err(err) => {
+            debug!(
+                "Guest agent of node {} unavailable ({}), using ACPI shutdown",
+                instance.node_id, err
+            );
+            if let Err(err) = send_monitor_command(&socket_path, "system_powerdown", None).await {
+                warn!(
+                    "Failed to request shutdown of node {}: {}, killing it instead",
+                    instance.node_id, err
+                );
+                return kill_node(instance).await;
+            }
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if !process_alive(instance).await? {
+            // Reap the child so it doesn't linger as a zombie
+            instance.process.wait().await?;
+            release_resources(instance).await;
+            counter!(metrics::NODES_STOPPED, "mode" => "graceful").increment(1);
+            debug!("Node {} shut down gracefully", instance.node_id);
+            return Ok(());
+        }
+        sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+
+    warn!(
+        "Node {} did not shut down within {:?}, killing it",
+        instance.node_id, timeout
+    );
+    kill_node(instance).await
+}
+
+/// Like `is_running`, but treats a process that exited with an error as stopped
+async fn process_alive(instance: &mut QemuInstance) -> Result<bool, QemuError> {
+    match is_running(instance).await {
+        Ok(running) => Ok(running),
+        Err(QemuError::ProcessExited(_)) => Ok(false),
+        Err(err) => Err(err),
+    }
 }
 
 /// Force kill a QEMU VM without graceful shutdown
@@ -129,12 +866,346 @@ pub async fn stop_node(_instance: &mut QemuInstance) -> Result<(), QemuError> {
 ///
 /// # Returns
 /// Ok(()) if the VM was killed successfully
-pub async fn kill_node(_instance: &mut QemuInstance) -> Result<(), QemuError> {
-    // TODO: Implement forced QEMU termination
-    // 1. Send SIGKILL to the process
-    // 2. Wait for process to exit
-    // 3. Clean up resources
-    unimplemented!("kill_node is not yet implemented")
+pub async fn kill_node(instance: &mut QemuInstance) -> Result<(), QemuError> {
+    // Only signal a process that hasn't been reaped yet, so killing an
+    // already-dead instance is a no-op
+    if instance.process.try_wait()?.is_none() {
+        instance.process.start_kill()?;
+        counter!(metrics::NODES_STOPPED, "mode" => "killed").increment(1);
+    }
+    let status = instance.process.wait().await?;
+    debug!("Node {} terminated with {}", instance.node_id, status);
+
+    release_resources(instance).await;
+    Ok(())
+}
+
+/// Remove the sockets and clear the runtime fields of a terminated instance
+async fn release_resources(instance: &mut QemuInstance) {
+    let sockets = [
+        instance.monitor_socket.take(),
+        instance.guest_agent_socket.take(),
+    ];
+    for socket_path in sockets.into_iter().flatten() {
+        match tokio::fs::remove_file(&socket_path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!("Failed to remove socket {}: {}", socket_path.display(), err),
+        }
+    }
+    if instance.capture.is_some()
+        && let Err(err) = stop_capture(instance).await
+    {
+        warn!(
+            "Failed to stop packet capture of node {}: {}",
+            instance.node_id, err
+        );
+    }
+    if let Some(name) = instance.tap_device.take()
+        && let Err(err) = delete_tap_device(&name).await
+    {
+        warn!("Failed to remove TAP device {}: {}", name, err);
+    }
+    remove_link_devices(&std::mem::take(&mut instance.links)).await;
+    instance.vnc_port = None;
+    instance.vnc_password = None;
+    instance.spice_port = None;
+    instance.cdrom = None;
+}
+
+/// Start capturing a running VM's bridged traffic into `path` with tcpdump
+///
+/// Only nodes with a TAP device can be captured. An earlier capture at
+/// `path` is overwritten.
+pub async fn start_capture(instance: &mut QemuInstance, path: &Path) -> Result<(), QemuError> {
+    let Some(tap_device) = &instance.tap_device else {
+        return Err(QemuError::InvalidConfiguration(
+            "packet capture requires the Bridge network mode".into(),
+        ));
+    };
+    if instance.capture.is_some() {
+        return Err(QemuError::InvalidConfiguration(
+            "a packet capture is already running".into(),
+        ));
+    }
+
+    // -U flushes every packet, so the file is readable while the capture
+    // runs and nothing is lost when it is killed
+    let mut process = Command::new(TCPDUMP_BINARY)
+        .args(["-i", tap_device, "-n", "-U", "-w"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    sleep(CAPTURE_STARTUP_GRACE).await;
+    if let Some(status) = process.try_wait()? {
+        let output = process.wait_with_output().await?;
+        return Err(QemuError::NetworkSetup(format!(
+            "{} exited with {}: {}",
+            TCPDUMP_BINARY,
+            status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    debug!(
+        "Capturing {} of node {} to {}",
+        tap_device,
+        instance.node_id,
+        path.display()
+    );
+    instance.capture = Some(PacketCapture {
+        process,
+        path: path.to_path_buf(),
+    });
+    Ok(())
+}
+
+/// Stop a running VM's packet capture, returning the path it was written to
+pub async fn stop_capture(instance: &mut QemuInstance) -> Result<PathBuf, QemuError> {
+    let Some(mut capture) = instance.capture.take() else {
+        return Err(QemuError::InvalidConfiguration(
+            "no packet capture is running".into(),
+        ));
+    };
+
+    if capture.process.try_wait()?.is_none() {
+        capture.process.start_kill()?;
+    }
+    capture.process.wait().await?;
+    debug!(
+        "Stopped packet capture of node {} to {}",
+        instance.node_id,
+        capture.path.display()
+    );
+    Ok(capture.path)
+}
+
+/// Create an empty console log so it can be read as soon as the node starts;
+/// QEMU truncates it again when it opens it
+async fn create_console_log(path: &Path) -> Result<(), QemuError> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)
+        .await?;
+    Ok(())
+}
+
+/// Give a node its own copy of the OVMF variable store on first UEFI boot
+async fn prepare_uefi_vars(uefi: &UefiFirmware) -> Result<(), QemuError> {
+    if tokio::fs::try_exists(&uefi.vars).await? {
+        return Ok(());
+    }
+    tokio::fs::copy(&uefi.vars_template, &uefi.vars).await?;
+    debug!("Created UEFI variable store {}", uefi.vars.display());
+    Ok(())
+}
+
+/// Name of a node's TAP device, built from the random tail of its UUIDv7 to
+/// fit the 15 character interface name limit
+fn tap_device_name(node_id: Uuid) -> String {
+    let id = node_id.simple().to_string();
+    format!("tap{}", &id[id.len() - 12..])
+}
+
+/// Name of the bridge joining the two ends of a link
+fn link_bridge_name(link_id: Uuid) -> String {
+    let id = link_id.simple().to_string();
+    format!("lk{}", &id[id.len() - 12..])
+}
+
+/// Name of the TAP device of one end of a link, `end` being `a` or `b`
+fn link_tap_name(link_id: Uuid, end: char) -> String {
+    let id = link_id.simple().to_string();
+    format!("l{}{}", end, &id[id.len() - 12..])
+}
+
+/// Serializes creating and removing link bridges, so one end starting while
+/// the other stops never sees its bridge deleted under it
+static LINK_BRIDGES: Mutex<()> = Mutex::const_new(());
+
+/// Add a VM's end of each link to the link's bridge, creating the bridge if
+/// the other end isn't running
+///
+/// Requires `CAP_NET_ADMIN`. On failure nothing created here is left behind.
+async fn create_link_devices(links: &[LinkEndpoint]) -> Result<(), QemuError> {
+    let _bridges = LINK_BRIDGES.lock().await;
+    for (index, link) in links.iter().enumerate() {
+        let created = match ensure_bridge(&link.bridge).await {
+            Ok(()) => create_tap_device(&link.tap_device, &link.bridge).await,
+            Err(err) => Err(err),
+        };
+        // Each end shapes its own egress, so both directions get the delay
+        let created = match (created, &link.netem) {
+            (Ok(()), Some(params)) => {
+                let shaped = apply_netem(&link.tap_device, params).await;
+                if shaped.is_err() {
+                    let _ = delete_tap_device(&link.tap_device).await;
+                }
+                shaped
+            }
+            (created, _) => created,
+        };
+        if let Err(err) = created {
+            remove_link_devices_locked(&links[..index]).await;
+            if let Err(cleanup_err) = remove_idle_bridge(&link.bridge).await {
+                warn!("Failed to remove bridge {}: {}", link.bridge, cleanup_err);
+            }
+            return Err(err);
+        }
+        debug!("Joined link {} through {}", link.link_id, link.tap_device);
+    }
+    Ok(())
+}
+
+/// Remove a VM's link TAP devices, and each link's bridge once no end is on it
+async fn remove_link_devices(links: &[LinkEndpoint]) {
+    let _bridges = LINK_BRIDGES.lock().await;
+    remove_link_devices_locked(links).await;
+}
+
+async fn remove_link_devices_locked(links: &[LinkEndpoint]) {
+    for link in links {
+        if let Err(err) = delete_tap_device(&link.tap_device).await {
+            warn!("Failed to remove TAP device {}: {}", link.tap_device, err);
+        }
+        if let Err(err) = remove_idle_bridge(&link.bridge).await {
+            warn!("Failed to remove bridge {}: {}", link.bridge, err);
+        }
+    }
+}
+
+/// Create a bridge and bring it up unless it already exists
+async fn ensure_bridge(name: &str) -> Result<(), QemuError> {
+    if tokio::fs::try_exists(Path::new(SYS_CLASS_NET).join(name)).await? {
+        return Ok(());
+    }
+    run_ip(&["link", "add", "name", name, "type", "bridge"]).await?;
+    if let Err(err) = run_ip(&["link", "set", "dev", name, "up"]).await {
+        let _ = run_ip(&["link", "delete", "dev", name]).await;
+        return Err(err);
+    }
+    debug!("Created bridge {}", name);
+    Ok(())
+}
+
+/// Delete a bridge that has no ports left; a missing bridge is fine
+async fn remove_idle_bridge(name: &str) -> Result<(), QemuError> {
+    let mut ports =
+        match tokio::fs::read_dir(Path::new(SYS_CLASS_NET).join(name).join("brif")).await {
+            Ok(ports) => ports,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+    if ports.next_entry().await?.is_some() {
+        return Ok(());
+    }
+    run_ip(&["link", "delete", "dev", name]).await?;
+    debug!("Removed bridge {}", name);
+    Ok(())
+}
+
+/// Locally administered MAC address (QEMU's 52:54:00 prefix) derived from the node id
+fn default_mac_address(node_id: Uuid) -> String {
+    let bytes = node_id.as_bytes();
+    format!(
+        "52:54:00:{:02x}:{:02x}:{:02x}",
+        bytes[13], bytes[14], bytes[15]
+    )
+}
+
+/// Create a TAP device and add it to `bridge`
+///
+/// Requires `CAP_NET_ADMIN`. A device left behind by an earlier run is
+/// replaced.
+async fn create_tap_device(name: &str, bridge: &str) -> Result<(), QemuError> {
+    if delete_tap_device(name).await.is_ok() {
+        debug!("Removed stale TAP device {}", name);
+    }
+
+    let steps: [&[&str]; 3] = [
+        &["tuntap", "add", "dev", name, "mode", "tap"],
+        &["link", "set", "dev", name, "master", bridge],
+        &["link", "set", "dev", name, "up"],
+    ];
+    for args in steps {
+        if let Err(err) = run_ip(args).await {
+            let _ = delete_tap_device(name).await;
+            return Err(err);
+        }
+    }
+
+    debug!("Created TAP device {} on bridge {}", name, bridge);
+    Ok(())
+}
+
+/// Deleting the device also drops any netem qdisc applied to it
+async fn delete_tap_device(name: &str) -> Result<(), QemuError> {
+    run_ip(&["link", "delete", "dev", name]).await
+}
+
+/// Replace the root qdisc of `iface` with a netem one shaping its egress
+///
+/// Requires `CAP_NET_ADMIN`. Node TAP devices are deleted when the node
+/// stops, which removes the qdisc with them.
+pub async fn apply_netem(iface: &str, params: &NetemParams) -> Result<(), QemuError> {
+    let mut args: Vec<String> = ["qdisc", "replace", "dev", iface, "root", "netem"]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    if let Some(delay) = params.delay_ms {
+        args.extend(["delay".to_string(), format!("{}ms", delay)]);
+        if let Some(jitter) = params.jitter_ms {
+            args.push(format!("{}ms", jitter));
+        }
+    }
+    if let Some(loss) = params.loss_pct {
+        args.extend(["loss".to_string(), format!("{}%", loss)]);
+    }
+    if let Some(rate) = params.rate_kbit {
+        args.extend(["rate".to_string(), format!("{}kbit", rate)]);
+    }
+
+    let output = Command::new(TC_BINARY)
+        .args(&args)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(QemuError::NetworkSetup(format!(
+            "`{} {}` failed: {}",
+            TC_BINARY,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    debug!("Applied netem {:?} to {}", params, iface);
+    Ok(())
+}
+
+async fn run_ip(args: &[&str]) -> Result<(), QemuError> {
+    let output = Command::new(IP_BINARY)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(QemuError::NetworkSetup(format!(
+            "`{} {}` failed: {}",
+            IP_BINARY,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
 }
 
 /// Enable VNC on a running QEMU VM
@@ -142,31 +1213,163 @@ pub async fn kill_node(_instance: &mut QemuInstance) -> Result<(), QemuError> {
 /// # Arguments
 /// * `instance` - The QEMU instance to enable VNC on
 /// * `display` - The VNC display number (port = 5900 + display)
+/// * `password` - Password clients must supply; a random one is generated when unset
 ///
 /// # Returns
 /// The VNC port number if successful
-pub async fn enable_vnc(_instance: &mut QemuInstance, _display: u16) -> Result<u16, QemuError> {
-    // TODO: Implement VNC enable via QEMU monitor
-    // 1. Connect to monitor socket
-    // 2. Send "change vnc :display" command
-    // 3. Update instance vnc_port
-    // 4. Return the VNC port (5900 + display)
-    unimplemented!("enable_vnc is not yet implemented")
+pub async fn enable_vnc(
+    instance: &mut QemuInstance,
+    display: u16,
+    password: Option<String>,
+) -> Result<u16, QemuError> {
+    if instance.vnc_port.is_some() {
+        return Err(QemuError::VncAlreadyEnabled);
+    }
+
+    let socket_path = monitor_socket(instance)?;
+    let port = VNC_BASE_PORT
+        .checked_add(display)
+        .ok_or(QemuError::VncPortAllocationFailed)?;
+
+    // Set the password before listening so the port is never reachable
+    // without authentication
+    set_vnc_password(instance, password.unwrap_or_else(generate_vnc_password)).await?;
+
+    // The VNC server is created at startup with `-vnc none`; pointing it at an
+    // address makes it start listening
+    send_monitor_command(
+        &socket_path,
+        "display-update",
+        Some(json!({
+            "type": "vnc",
+            "addresses": [{
+                "type": "inet",
+                "host": instance.vnc_host,
+                "port": port.to_string(),
+            }],
+        })),
+    )
+    .await?;
+
+    // A failed bind isn't reported by display-update, so confirm the server is up
+    let info = send_monitor_command(&socket_path, "query-vnc", None).await?;
+    let listening = info.get("enabled").and_then(Value::as_bool) == Some(true)
+        && info.get("service").and_then(Value::as_str) == Some(port.to_string().as_str());
+    if !listening {
+        warn!(
+            "VNC server for node {} is not listening on port {}: {}",
+            instance.node_id, port, info
+        );
+        return Err(QemuError::VncPortAllocationFailed);
+    }
+
+    instance.vnc_port = Some(port);
+    debug!("Enabled VNC for node {} on port {}", instance.node_id, port);
+    Ok(port)
+}
+
+/// Change the password required by the VNC server of a running QEMU VM
+///
+/// Existing VNC clients stay connected; the password applies to new connections.
+pub async fn set_vnc_password(
+    instance: &mut QemuInstance,
+    password: String,
+) -> Result<(), QemuError> {
+    validate_vnc_password(&password)?;
+    let socket_path = monitor_socket(instance)?;
+
+    send_monitor_command(
+        &socket_path,
+        "set_password",
+        Some(json!({
+            "protocol": "vnc",
+            "password": password,
+        })),
+    )
+    .await?;
+
+    instance.vnc_password = Some(password);
+    Ok(())
+}
+
+/// Check that a password can be used for VNC authentication
+pub fn validate_vnc_password(password: &str) -> Result<(), QemuError> {
+    if password.is_empty() || password.chars().count() > MAX_VNC_PASSWORD_LEN {
+        return Err(QemuError::InvalidVncPassword(format!(
+            "must be 1 to {} characters",
+            MAX_VNC_PASSWORD_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Generate a random password of the longest length VNC supports
+fn generate_vnc_password() -> String {
+    let mut bits = Uuid::new_v4().as_u128();
+    let base = VNC_PASSWORD_CHARSET.len() as u128;
+    (0..MAX_VNC_PASSWORD_LEN)
+        .map(|_| {
+            let c = VNC_PASSWORD_CHARSET[(bits % base) as usize];
+            bits /= base;
+            c as char
+        })
+        .collect()
 }
 
 /// Disable VNC on a running QEMU VM
 ///
+/// The node's `vnc_port` is cleared in the database as well, returning its
+/// display to the pool `next_vnc_display` allocates from.
+///
 /// # Arguments
 /// * `instance` - The QEMU instance to disable VNC on
+/// * `app_state` - Application state containing the db pool
 ///
 /// # Returns
 /// Ok(()) if VNC was disabled successfully
-pub async fn disable_vnc(_instance: &mut QemuInstance) -> Result<(), QemuError> {
-    // TODO: Implement VNC disable via QEMU monitor
-    // 1. Connect to monitor socket
-    // 2. Send "change vnc none" command
-    // 3. Clear instance vnc_port
-    unimplemented!("disable_vnc is not yet implemented")
+pub async fn disable_vnc(
+    instance: &mut QemuInstance,
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    if instance.vnc_port.is_none() {
+        return Err(QemuError::VncNotEnabled);
+    }
+
+    let socket_path = monitor_socket(instance)?;
+
+    // An empty address list closes the listener but keeps the VNC server
+    // around so it can be enabled again later
+    send_monitor_command(
+        &socket_path,
+        "display-update",
+        Some(json!({ "type": "vnc", "addresses": [] })),
+    )
+    .await?;
+
+    let info = send_monitor_command(&socket_path, "query-vnc", None).await?;
+    if info.get("enabled").and_then(Value::as_bool) != Some(false) {
+        return Err(QemuError::MonitorError(format!(
+            "VNC server is still listening after disable: {}",
+            info
+        )));
+    }
+
+    instance.vnc_port = None;
+    release_vnc_display(instance.node_id, app_state).await?;
+    debug!("Disabled VNC for node {}", instance.node_id);
+    Ok(())
+}
+
+/// Forget the VNC display recorded for a node so it can be allocated again
+///
+/// Stopping a node releases its display through the same `vnc_port = NULL`
+/// in the update that marks it stopped.
+pub async fn release_vnc_display(node_id: Uuid, app_state: &AppState) -> Result<(), QemuError> {
+    sqlx::query("UPDATE nodes SET vnc_port = NULL WHERE id = $1")
+        .bind(node_id)
+        .execute(&app_state.db)
+        .await?;
+    Ok(())
 }
 
 /// Get the VNC connection info for a running QEMU VM
@@ -176,43 +1379,689 @@ pub async fn disable_vnc(_instance: &mut QemuInstance) -> Result<(), QemuError>
 ///
 /// # Returns
 /// Tuple of (host, port) for VNC connection
-pub fn get_vnc_info(_instance: &QemuInstance) -> Result<(String, u16), QemuError> {
-    // TODO: Return VNC connection information
-    // 1. Check if VNC is enabled
-    // 2. Return localhost and port
-    unimplemented!("get_vnc_info is not yet implemented")
+pub fn get_vnc_info(instance: &QemuInstance) -> Result<(String, u16), QemuError> {
+    let port = instance.vnc_port.ok_or(QemuError::VncNotEnabled)?;
+    Ok((instance.vnc_host.clone(), port))
 }
 
-/// Check if a QEMU instance is still running
-///
-/// # Arguments
-/// * `instance` - The QEMU instance to check
+/// Get the SPICE connection info for a running QEMU VM
 ///
 /// # Returns
-/// true if the process is still running
-pub async fn is_running(_instance: &mut QemuInstance) -> Result<bool, QemuError> {
-    // TODO: Check process status
-    // 1. Try to get process exit status without blocking
-    // 2. Return true if still running, false if exited
-    unimplemented!("is_running is not yet implemented")
+/// Tuple of (host, port) for an external SPICE client
+pub fn get_spice_info(instance: &QemuInstance) -> Result<(String, u16), QemuError> {
+    let port = instance.spice_port.ok_or(QemuError::SpiceNotEnabled)?;
+    Ok((instance.vnc_host.clone(), port))
 }
 
-/// Create an overlay image for copy-on-write disk operations
+/// Freeze the guest's CPUs; the process and its sockets stay up
 ///
 /// # Arguments
-/// * `backing_image` - Path to the backing (parent) disk image
-/// * `overlay_path` - Path where the overlay should be created
-///
-/// # Returns
-/// Ok(()) if the overlay was created successfully
-pub async fn create_overlay(
-    _backing_image: &PathBuf,
-    _overlay_path: &PathBuf,
-) -> Result<(), QemuError> {
-    // TODO: Create qcow2 overlay image
-    // 1. Run qemu-img create -f qcow2 -b backing_image -F qcow2 overlay_path
-    // 2. Verify the overlay was created
-    unimplemented!("create_overlay is not yet implemented")
+/// * `instance` - The QEMU instance to pause
+pub async fn pause_node(instance: &QemuInstance) -> Result<(), QemuError> {
+    send_monitor_command(&monitor_socket(instance)?, "stop", None).await?;
+    debug!("Paused node {}", instance.node_id);
+    Ok(())
+}
+
+/// Let a paused guest continue running
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to resume
+pub async fn resume_node(instance: &QemuInstance) -> Result<(), QemuError> {
+    send_monitor_command(&monitor_socket(instance)?, "cont", None).await?;
+    debug!("Resumed node {}", instance.node_id);
+    Ok(())
+}
+
+/// Get the guest's run state as reported by QMP `query-status`
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to query
+///
+/// # Returns
+/// The run state, e.g. `running`, `paused`, `shutdown` or `internal-error`
+pub async fn query_status(instance: &QemuInstance) -> Result<String, QemuError> {
+    let status = send_monitor_command(&monitor_socket(instance)?, "query-status", None).await?;
+    status
+        .get("status")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| QemuError::MonitorError(format!("Malformed status: {}", status)))
+}
+
+/// Run an arbitrary QMP command against a running VM, for debugging
+///
+/// HMP commands such as `info registers` go through `human-monitor-command`
+/// with a `command-line` argument. Nothing stops a command from changing
+/// the VM behind the registry's back (e.g. `quit`), so this is only exposed
+/// to admins.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to send the command to
+/// * `command` - The QMP command to execute
+/// * `arguments` - Optional `arguments` object for the command
+///
+/// # Returns
+/// The contents of the response's `return` member
+pub async fn monitor_command(
+    instance: &QemuInstance,
+    command: &str,
+    arguments: Option<Value>,
+) -> Result<Value, QemuError> {
+    let result = send_monitor_command(&monitor_socket(instance)?, command, arguments).await?;
+    debug!("Ran QMP command `{}` on node {}", command, instance.node_id);
+    Ok(result)
+}
+
+/// State of one of a VM's drives as reported by QMP `query-block`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BlockDevice {
+    /// Drive id, e.g. `disk0` or `cdrom`
+    pub device: String,
+    pub removable: bool,
+    /// Image the drive reads from, `None` for an empty drive
+    pub file: Option<String>,
+    /// Block driver of the image, e.g. `qcow2`
+    pub format: Option<String>,
+    /// Set when the drive was opened read-only or QEMU fell back to read-only
+    pub read_only: bool,
+    /// Backing files of the image, nearest first, as recorded in each image's header
+    pub backing_chain: Vec<String>,
+    /// `ok`, `failed` or `nospace`, for drives that track I/O errors
+    pub io_status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QmpBlockInfo {
+    device: String,
+    #[serde(default)]
+    removable: bool,
+    #[serde(rename = "io-status")]
+    io_status: Option<String>,
+    inserted: Option<QmpBlockInserted>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QmpBlockInserted {
+    file: String,
+    ro: bool,
+    drv: String,
+    image: QmpImageInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct QmpImageInfo {
+    #[serde(rename = "backing-filename")]
+    backing_filename: Option<String>,
+    #[serde(rename = "backing-image")]
+    backing_image: Option<Box<QmpImageInfo>>,
+}
+
+/// List the VM's drives with their images and I/O status via QMP `query-block`
+///
+/// Useful for spotting overlays that went read-only or drives that hit I/O
+/// errors; a backing file listed in the chain need not exist on disk anymore.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to query
+pub async fn query_block(instance: &QemuInstance) -> Result<Vec<BlockDevice>, QemuError> {
+    let result = send_monitor_command(&monitor_socket(instance)?, "query-block", None).await?;
+    let blocks: Vec<QmpBlockInfo> = serde_json::from_value(result)
+        .map_err(|e| QemuError::MonitorError(format!("Malformed block info: {}", e)))?;
+
+    Ok(blocks
+        .into_iter()
+        .map(|block| {
+            let mut backing_chain = Vec::new();
+            let (file, format, read_only) = match block.inserted {
+                Some(inserted) => {
+                    let mut image = Some(&inserted.image);
+                    while let Some(info) = image {
+                        backing_chain.extend(info.backing_filename.clone());
+                        image = info.backing_image.as_deref();
+                    }
+                    (Some(inserted.file), Some(inserted.drv), inserted.ro)
+                }
+                None => (None, None, false),
+            };
+            BlockDevice {
+                device: block.device,
+                removable: block.removable,
+                file,
+                format,
+                read_only,
+                backing_chain,
+                io_status: block.io_status,
+            }
+        })
+        .collect())
+}
+
+/// Memory of a VM as adjusted by its balloon device
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct BalloonInfo {
+    /// Memory the guest was asked to shrink or grow to, in MB
+    pub target_mb: u64,
+    /// Memory the guest currently has, in MB. The guest's balloon driver
+    /// gives memory back gradually, so this can lag behind the target.
+    pub actual_mb: u64,
+}
+
+/// Ask the guest to shrink or grow its memory through the balloon device
+///
+/// The balloon can only take memory away from what the VM was started
+/// with, so targets above `instance.memory_mb` are rejected. A guest
+/// without a balloon driver ignores the request.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to adjust
+/// * `target_mb` - Memory the guest should end up with, in MB
+pub async fn set_balloon(
+    instance: &QemuInstance,
+    target_mb: u64,
+) -> Result<BalloonInfo, QemuError> {
+    if !(u64::from(MIN_MEMORY_MB)..=instance.memory_mb).contains(&target_mb) {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "memory target must be between {} and the {} MB the node was started with",
+            MIN_MEMORY_MB, instance.memory_mb
+        )));
+    }
+
+    let socket_path = monitor_socket(instance)?;
+    send_monitor_command(
+        &socket_path,
+        "balloon",
+        Some(json!({ "value": target_mb * BYTES_PER_MB })),
+    )
+    .await?;
+
+    let balloon = send_monitor_command(&socket_path, "query-balloon", None).await?;
+    let actual = balloon
+        .get("actual")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| QemuError::MonitorError(format!("Malformed balloon info: {}", balloon)))?;
+
+    debug!(
+        "Set balloon target of node {} to {} MB",
+        instance.node_id, target_mb
+    );
+    Ok(BalloonInfo {
+        target_mb,
+        actual_mb: actual / BYTES_PER_MB,
+    })
+}
+
+/// Hotplug one more vCPU into a running VM
+///
+/// Fills the first free slot reported by `query-hotpluggable-cpus`; the VM
+/// must have been started with `maxcpus` above its current count. Guests
+/// may need to bring the new CPU online themselves (e.g. through sysfs).
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to add a CPU to
+///
+/// # Returns
+/// The number of CPUs QEMU reports after the hotplug
+pub async fn add_cpu(instance: &mut QemuInstance) -> Result<u32, QemuError> {
+    if instance.cpu_cores >= instance.max_cpus {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "node already has its maximum of {} CPUs",
+            instance.max_cpus
+        )));
+    }
+
+    let socket_path = monitor_socket(instance)?;
+    let slots = send_monitor_command(&socket_path, "query-hotpluggable-cpus", None).await?;
+    // Slots already holding a CPU have a `qom-path`
+    let slot = slots
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|slot| slot.get("qom-path").is_none())
+        .ok_or_else(|| {
+            QemuError::InvalidConfiguration("no free CPU slot left for hotplug".into())
+        })?;
+
+    let driver = slot
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| QemuError::MonitorError(format!("Malformed CPU slot: {}", slot)))?;
+    let mut arguments = json!({
+        "driver": driver,
+        "id": format!("cpu{}", instance.cpu_cores),
+    });
+    if let (Some(arguments), Some(props)) = (
+        arguments.as_object_mut(),
+        slot.get("props").and_then(Value::as_object),
+    ) {
+        arguments.extend(props.clone());
+    }
+    send_monitor_command(&socket_path, "device_add", Some(arguments)).await?;
+
+    // `query-cpus` was removed in QEMU 6.0; the fast variant lists the same CPUs
+    let cpus = send_monitor_command(&socket_path, "query-cpus-fast", None).await?;
+    let count = cpus
+        .as_array()
+        .map(|cpus| cpus.len() as u32)
+        .ok_or_else(|| QemuError::MonitorError(format!("Malformed CPU list: {}", cpus)))?;
+
+    instance.cpu_cores = count;
+    debug!("Node {} now has {} CPUs", instance.node_id, count);
+    Ok(count)
+}
+
+/// Insert an ISO image into a running VM's CD-ROM drive
+///
+/// Any ISO already inserted is replaced. The path is handed to QEMU as-is,
+/// so callers must have validated it (see `models::resolve_iso_path`).
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to insert the ISO into
+/// * `iso_path` - Absolute path of the ISO image
+pub async fn attach_cdrom(instance: &mut QemuInstance, iso_path: &Path) -> Result<(), QemuError> {
+    if !instance.cdrom_drive {
+        return Err(QemuError::InvalidConfiguration(
+            "node has no CD-ROM drive".into(),
+        ));
+    }
+
+    send_monitor_command(
+        &monitor_socket(instance)?,
+        "blockdev-change-medium",
+        Some(json!({
+            "id": CDROM_DEVICE_ID,
+            "filename": iso_path.to_string_lossy(),
+            "format": "raw",
+            "read-only-mode": "read-only",
+        })),
+    )
+    .await?;
+
+    instance.cdrom = Some(iso_path.to_path_buf());
+    debug!(
+        "Inserted {} into node {}",
+        iso_path.display(),
+        instance.node_id
+    );
+    Ok(())
+}
+
+/// Eject the ISO image from a running VM's CD-ROM drive
+///
+/// The eject is forced, so a guest that locked the tray loses the disc anyway.
+pub async fn detach_cdrom(instance: &mut QemuInstance) -> Result<(), QemuError> {
+    if instance.cdrom.is_none() {
+        return Err(QemuError::InvalidConfiguration("no ISO is inserted".into()));
+    }
+
+    send_monitor_command(
+        &monitor_socket(instance)?,
+        "eject",
+        Some(json!({ "id": CDROM_DEVICE_ID, "force": true })),
+    )
+    .await?;
+
+    instance.cdrom = None;
+    debug!("Ejected the ISO from node {}", instance.node_id);
+    Ok(())
+}
+
+/// Reboot the guest with `system_reset`, like pressing the reset button
+///
+/// The QEMU process keeps running, so the monitor socket, VNC server and
+/// any Guacamole connection survive. A hard restart instead is a
+/// `stop_node` followed by `start_node`, which recreates the monitor socket
+/// and leaves VNC disabled.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to reboot
+pub async fn restart_node(instance: &QemuInstance) -> Result<(), QemuError> {
+    send_monitor_command(&monitor_socket(instance)?, "system_reset", None).await?;
+    debug!("Reset node {}", instance.node_id);
+    Ok(())
+}
+
+/// Save the VM's disk and RAM state as an internal snapshot in its overlay
+///
+/// A snapshot with the same name is overwritten. The guest is paused while
+/// its state is written.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to snapshot
+/// * `name` - Snapshot name (letters, digits, `-`, `_` and `.`)
+pub async fn save_snapshot(instance: &QemuInstance, name: &str) -> Result<(), QemuError> {
+    validate_snapshot_name(name)?;
+    let socket_path = monitor_socket(instance)?;
+
+    human_monitor_command(&socket_path, &format!("savevm {}", name)).await?;
+    info!("Saved snapshot '{}' of node {}", name, instance.node_id);
+    Ok(())
+}
+
+/// Roll the VM back to an internal snapshot
+///
+/// Loading VM state needs a live QEMU process, so this fails with
+/// `NodeNotRunning` on a stopped instance.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to restore
+/// * `name` - Name of an existing snapshot
+pub async fn restore_snapshot(instance: &QemuInstance, name: &str) -> Result<(), QemuError> {
+    validate_snapshot_name(name)?;
+    let socket_path = monitor_socket(instance)?;
+
+    if !list_snapshots(instance)
+        .await?
+        .iter()
+        .any(|snapshot| snapshot.name == name)
+    {
+        return Err(QemuError::SnapshotNotFound(name.to_string()));
+    }
+
+    human_monitor_command(&socket_path, &format!("loadvm {}", name)).await?;
+    info!("Restored node {} to snapshot '{}'", instance.node_id, name);
+    Ok(())
+}
+
+/// List the internal snapshots stored in the VM's overlay
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to query
+pub async fn list_snapshots(instance: &QemuInstance) -> Result<Vec<SnapshotInfo>, QemuError> {
+    let socket_path = monitor_socket(instance)?;
+    let devices = send_monitor_command(&socket_path, "query-block", None).await?;
+
+    let snapshots = devices
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|device| device.get("device").and_then(Value::as_str) == Some(DISK_DRIVE_ID))
+        .and_then(|device| device.pointer("/inserted/image/snapshots"))
+        .cloned()
+        .unwrap_or_else(|| json!([]));
+
+    serde_json::from_value(snapshots)
+        .map_err(|e| QemuError::MonitorError(format!("Malformed snapshot list: {}", e)))
+}
+
+/// Path of the instance's QMP socket, which is gone once it has stopped
+fn monitor_socket(instance: &QemuInstance) -> Result<PathBuf, QemuError> {
+    instance
+        .monitor_socket
+        .clone()
+        .ok_or(QemuError::NodeNotRunning)
+}
+
+/// Snapshot names are passed through the human monitor, so keep them to a
+/// conservative character set
+fn validate_snapshot_name(name: &str) -> Result<(), QemuError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_SNAPSHOT_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(QemuError::InvalidSnapshotName(name.to_string()))
+    }
+}
+
+/// Check if a QEMU instance is still running
+///
+/// # Arguments
+/// * `instance` - The QEMU instance to check
+///
+/// # Returns
+/// true if the process is still running
+pub async fn is_running(instance: &mut QemuInstance) -> Result<bool, QemuError> {
+    match instance.process.try_wait()? {
+        None => Ok(true),
+        Some(status) if status.success() => Ok(false),
+        Some(status) => Err(QemuError::ProcessExited(match status.code() {
+            Some(code) => format!("exit code {}", code),
+            None => status.to_string(),
+        })),
+    }
+}
+
+/// Create an overlay image for copy-on-write disk operations
+///
+/// # Arguments
+/// * `backing_image` - Path to the backing (parent) disk image
+/// * `overlay_path` - Path where the overlay should be created
+///
+/// # Returns
+/// Ok(()) if the overlay was created successfully
+pub async fn create_overlay(backing_image: &Path, overlay_path: &Path) -> Result<(), QemuError> {
+    if !backing_image.is_file() {
+        return Err(QemuError::ImagePathError(format!(
+            "Backing image {} does not exist",
+            backing_image.display()
+        )));
+    }
+
+    if let Some(parent) = overlay_path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| {
+            QemuError::ImagePathError(format!(
+                "Failed to create overlay directory {}: {}",
+                parent.display(),
+                e
+            ))
+        })?;
+    }
+
+    let output = Command::new(QEMU_IMG_BINARY)
+        .args(["create", "-f", "qcow2", "-b"])
+        .arg(backing_image)
+        .args(["-F", "qcow2"])
+        .arg(overlay_path)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(QemuError::ImagePathError(format!(
+            "qemu-img create failed for {}: {}",
+            overlay_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    if !overlay_path.is_file() {
+        return Err(QemuError::ImagePathError(format!(
+            "qemu-img reported success but {} was not created",
+            overlay_path.display()
+        )));
+    }
+
+    debug!(
+        "Created overlay {} backed by {}",
+        overlay_path.display(),
+        backing_image.display()
+    );
+    Ok(())
+}
+
+/// Check that the backing chain qcow2 records on disk matches the image
+/// ancestry in the database and doesn't loop
+///
+/// # Arguments
+/// * `image_chain` - Ancestry from the base image to the node's image
+/// * `app_state` - Application state whose config holds the image directory
+///
+/// # Returns
+/// `QemuError::InvalidConfiguration` naming the offending file on a mismatch
+pub async fn validate_image_chain(
+    image_chain: &[Image],
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    let Some(top) = image_chain.last() else {
+        return Ok(());
+    };
+    let top_path = top
+        .get_full_path(app_state)
+        .map_err(|_| QemuError::ImageNotFound(top.id))?;
+
+    // qemu-img refuses most loops itself, but don't let a pathological chain
+    // hold up the request indefinitely
+    let output = timeout(
+        BACKING_CHAIN_TIMEOUT,
+        Command::new(QEMU_IMG_BINARY)
+            .args(["info", "--backing-chain", "--output=json"])
+            .arg(&top_path)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| {
+        QemuError::InvalidConfiguration(format!(
+            "Timed out reading the backing chain of {}; it may contain a loop",
+            top_path.display()
+        ))
+    })??;
+
+    if !output.status.success() {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "Invalid backing chain for {}: {}",
+            top_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let infos: Vec<Value> = serde_json::from_slice(&output.stdout).map_err(|e| {
+        QemuError::ImagePathError(format!(
+            "Failed to parse qemu-img info for {}: {}",
+            top_path.display(),
+            e
+        ))
+    })?;
+
+    // qemu-img lists the chain from the given image down to its base, the
+    // reverse of the database ancestry
+    let mut seen = HashSet::new();
+    let mut expected = image_chain.iter().rev();
+    for info in &infos {
+        let filename = info
+            .get("filename")
+            .and_then(Value::as_str)
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                QemuError::ImagePathError(format!(
+                    "qemu-img info for {} has an entry without a filename",
+                    top_path.display()
+                ))
+            })?;
+        let on_disk = tokio::fs::canonicalize(&filename).await.map_err(|e| {
+            QemuError::InvalidConfiguration(format!(
+                "Backing file {} can't be resolved: {}",
+                filename.display(),
+                e
+            ))
+        })?;
+        if !seen.insert(on_disk.clone()) {
+            return Err(QemuError::InvalidConfiguration(format!(
+                "Backing chain of {} loops through {}",
+                top_path.display(),
+                on_disk.display()
+            )));
+        }
+
+        let Some(image) = expected.next() else {
+            return Err(QemuError::InvalidConfiguration(format!(
+                "{} has a backing file that isn't a registered ancestor",
+                on_disk.display()
+            )));
+        };
+        let registered = image
+            .get_full_path(app_state)
+            .map_err(|_| QemuError::ImageNotFound(image.id))?;
+        if on_disk != registered {
+            return Err(QemuError::InvalidConfiguration(format!(
+                "{} is in the backing chain where image {} ({}) is expected",
+                on_disk.display(),
+                image.id,
+                registered.display()
+            )));
+        }
+    }
+
+    if let Some(image) = expected.next() {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "Image {} is registered as an ancestor but isn't a backing file in the chain",
+            image.id
+        )));
+    }
+    Ok(())
+}
+
+/// Compute the hex SHA-256 of a file.
+///
+/// Images can be many gigabytes, so the file is streamed on the blocking
+/// thread pool rather than read on the async runtime.
+pub async fn file_sha256(path: &Path) -> Result<String, QemuError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        use std::io::Read;
+
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; CHECKSUM_BUFFER_SIZE];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await
+    .map_err(|e| QemuError::ImagePathError(format!("Checksum task failed: {}", e)))?
+}
+
+/// Check every image in a chain that has a recorded checksum against its file
+///
+/// A file is only hashed again when its size or modification time changed
+/// since it last matched, so starting a node doesn't reread every image.
+///
+/// # Returns
+/// `QemuError::ChecksumMismatch` for the first image whose file changed
+pub async fn verify_image_chain(
+    image_chain: &[Image],
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    for image in image_chain {
+        let Some(expected) = &image.sha256 else {
+            continue;
+        };
+        let path = image
+            .get_full_path(app_state)
+            .map_err(|_| QemuError::ImageNotFound(image.id))?;
+        // Stat before hashing, so a write during the hash forces another one
+        let metadata = tokio::fs::metadata(&path).await?;
+        let file = VerifiedFile {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+            sha256: expected.clone(),
+        };
+        if app_state.instances.is_verified(image.id, &file) {
+            debug!("Image {} unchanged since its last verification", image.id);
+            continue;
+        }
+
+        let actual = file_sha256(&path).await?;
+        if &actual != expected {
+            return Err(QemuError::ChecksumMismatch {
+                image_id: image.id,
+                expected: expected.clone(),
+                actual,
+            });
+        }
+        app_state.instances.mark_verified(image.id, file);
+        debug!("Verified checksum of image {}", image.id);
+    }
+    Ok(())
 }
 
 /// Create the instance overlay for a node
@@ -220,20 +2069,34 @@ pub async fn create_overlay(
 /// # Arguments
 /// * `node` - The node to create an overlay for
 /// * `image` - The image the node is based on
-/// * `app_state` - Application state containing env
+/// * `app_state` - Application state whose config holds the image and overlay directories
 ///
 /// # Returns
 /// Ok(()) if the overlay was created successfully
 pub async fn create_instance_overlay(
-    _node: &Node,
-    _image: &Image,
-    _app_state: &AppState,
+    node: &Node,
+    image: &Image,
+    app_state: &AppState,
 ) -> Result<(), QemuError> {
-    // TODO: Create instance overlay for node
-    // 1. Get the image's full path
-    // 2. Get the node's instance overlay path
-    // 3. Create overlay pointing to the image
-    unimplemented!("create_instance_overlay is not yet implemented")
+    let image_path = image
+        .get_full_path(app_state)
+        .map_err(|_| QemuError::ImageNotFound(image.id))?;
+    if !image_path.is_file() {
+        return Err(QemuError::ImageNotFound(image.id));
+    }
+
+    let overlay_path = node
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+
+    // Overwriting would silently discard the node's disk state
+    if overlay_path.exists() {
+        return Err(QemuError::OverlayAlreadyExists(
+            overlay_path.display().to_string(),
+        ));
+    }
+
+    create_overlay(&image_path, &overlay_path).await
 }
 
 /// Delete an overlay image
@@ -243,11 +2106,40 @@ pub async fn create_instance_overlay(
 ///
 /// # Returns
 /// Ok(()) if the overlay was deleted successfully
-pub async fn delete_overlay(_overlay_path: &PathBuf) -> Result<(), QemuError> {
-    // TODO: Delete the overlay file
-    // 1. Check if file exists
-    // 2. Remove the file
-    unimplemented!("delete_overlay is not yet implemented")
+pub async fn delete_overlay(overlay_path: &Path) -> Result<(), QemuError> {
+    let metadata = match tokio::fs::symlink_metadata(overlay_path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(QemuError::ImagePathError(format!(
+                "Failed to inspect {}: {}",
+                overlay_path.display(),
+                e
+            )));
+        }
+    };
+
+    // Callers resolve paths inside OVERLAY_DIR, but refuse anything that
+    // isn't a plain file in case a link or directory was planted there
+    if !metadata.is_file() {
+        return Err(QemuError::ImagePathError(format!(
+            "{} is not a regular file",
+            overlay_path.display()
+        )));
+    }
+
+    match tokio::fs::remove_file(overlay_path).await {
+        Ok(()) => {
+            debug!("Deleted overlay {}", overlay_path.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(QemuError::ImagePathError(format!(
+            "Failed to delete {}: {}",
+            overlay_path.display(),
+            e
+        ))),
+    }
 }
 
 /// Remove an overlay from an image, rebasing to the base image
@@ -260,14 +2152,147 @@ pub async fn delete_overlay(_overlay_path: &PathBuf) -> Result<(), QemuError> {
 ///
 /// # Returns
 /// Ok(()) if the overlay was successfully removed and changes committed
-pub async fn remove_overlay(_overlay_path: &PathBuf) -> Result<(), QemuError> {
-    // TODO: Remove overlay by committing changes to base image
-    // 1. Verify the overlay exists and is a valid qcow2 image
-    // 2. Get the backing file path from the overlay
-    // 3. Run qemu-img commit overlay_path to commit changes to backing file
-    // 4. Delete the overlay file
-    // 5. Verify the base image is intact
-    unimplemented!("remove_overlay is not yet implemented")
+#[allow(dead_code)] // No route merges an overlay into its image yet
+pub async fn remove_overlay(overlay_path: &Path) -> Result<(), QemuError> {
+    let info = image_info(overlay_path).await?;
+
+    if info.get("format").and_then(Value::as_str) != Some("qcow2") {
+        return Err(QemuError::ImagePathError(format!(
+            "{} is not a qcow2 image",
+            overlay_path.display()
+        )));
+    }
+
+    let backing_file = info
+        .get("full-backing-filename")
+        .or_else(|| info.get("backing-filename"))
+        .and_then(Value::as_str)
+        .map(PathBuf::from)
+        .ok_or_else(|| {
+            QemuError::ImagePathError(format!(
+                "{} has no backing file to commit into",
+                overlay_path.display()
+            ))
+        })?;
+
+    let output = Command::new(QEMU_IMG_BINARY)
+        .arg("commit")
+        .arg(overlay_path)
+        .output()
+        .await?;
+
+    // Leave the overlay in place on failure so no changes are lost
+    if !output.status.success() {
+        return Err(QemuError::ImagePathError(format!(
+            "qemu-img commit failed for {}: {}",
+            overlay_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    tokio::fs::remove_file(overlay_path).await.map_err(|e| {
+        QemuError::ImagePathError(format!(
+            "Committed {} but failed to delete it: {}",
+            overlay_path.display(),
+            e
+        ))
+    })?;
+
+    // Make sure the commit left a readable image behind
+    image_info(&backing_file).await?;
+
+    debug!(
+        "Committed overlay {} into {}",
+        overlay_path.display(),
+        backing_file.display()
+    );
+    Ok(())
+}
+
+/// Disk space taken by a node's instance overlay
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct DiskUsage {
+    /// Bytes the overlay file occupies on the host
+    pub actual_bytes: u64,
+    /// Size of the disk as seen by the guest
+    pub virtual_bytes: u64,
+}
+
+/// Measure a node's instance overlay with `qemu-img info`
+///
+/// Works on running nodes too. Only the overlay is counted; the images it
+/// is backed by are shared with other nodes.
+///
+/// # Returns
+/// `None` if the node has no overlay yet because it has never been started
+pub async fn overlay_disk_usage(
+    node: &Node,
+    app_state: &AppState,
+) -> Result<Option<DiskUsage>, QemuError> {
+    let overlay_path = node
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    if !overlay_path.exists() {
+        return Ok(None);
+    }
+
+    let info = image_info(&overlay_path).await?;
+    let size = |key: &str| {
+        info.get(key).and_then(Value::as_u64).ok_or_else(|| {
+            QemuError::ImagePathError(format!(
+                "qemu-img info for {} has no {}",
+                overlay_path.display(),
+                key
+            ))
+        })
+    };
+    Ok(Some(DiskUsage {
+        actual_bytes: size("actual-size")?,
+        virtual_bytes: size("virtual-size")?,
+    }))
+}
+
+/// Check that a file is a readable qcow2 image, returning its backing file if any
+pub async fn check_qcow2(path: &Path) -> Result<Option<String>, QemuError> {
+    let info = image_info(path).await?;
+    if info.get("format").and_then(Value::as_str) != Some("qcow2") {
+        return Err(QemuError::ImagePathError(format!(
+            "{} is not a qcow2 image",
+            path.display()
+        )));
+    }
+    Ok(info
+        .get("backing-filename")
+        .and_then(Value::as_str)
+        .map(str::to_string))
+}
+
+/// Run `qemu-img info` on a disk image and return its JSON description
+///
+/// The image may be in use by a running VM, so its lock is shared rather
+/// than taken.
+async fn image_info(path: &Path) -> Result<Value, QemuError> {
+    let output = Command::new(QEMU_IMG_BINARY)
+        .args(["info", "--force-share", "--output=json"])
+        .arg(path)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(QemuError::ImagePathError(format!(
+            "qemu-img info failed for {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| {
+        QemuError::ImagePathError(format!(
+            "Failed to parse qemu-img info for {}: {}",
+            path.display(),
+            e
+        ))
+    })
 }
 
 /// Wipe a node by deleting and recreating its instance overlay
@@ -275,20 +2300,184 @@ pub async fn remove_overlay(_overlay_path: &PathBuf) -> Result<(), QemuError> {
 /// # Arguments
 /// * `node` - The node to wipe
 /// * `image` - The image the node is based on
-/// * `app_state` - Application state containing env
+/// * `app_state` - Application state whose config holds the image and overlay directories
 ///
 /// # Returns
 /// Ok(()) if the wipe was successful
-pub async fn wipe_node(
-    _node: &Node,
-    _image: &Image,
-    _app_state: &AppState,
+pub async fn wipe_node(node: &Node, image: &Image, app_state: &AppState) -> Result<(), QemuError> {
+    // There may be no QemuInstance for the node in memory, so go by the
+    // persisted status rather than a process handle
+    if node.status == NodeStatus::Running {
+        return Err(QemuError::NodeAlreadyRunning);
+    }
+
+    let overlay_path = node
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+
+    delete_overlay(&overlay_path).await?;
+    delete_uefi_vars(node, app_state).await?;
+    create_instance_overlay(node, image, app_state).await?;
+
+    info!("Wiped node {}", node.id);
+    Ok(())
+}
+
+/// Give `clone` a copy of `source`'s disk state and UEFI variable store
+///
+/// Only the data in the source overlay is copied; the clone's overlay is
+/// backed by the same image. A source that has never run gets the clone a
+/// fresh overlay instead.
+///
+/// # Arguments
+/// * `source` - The stopped node to copy
+/// * `clone` - The new node, which must not have an overlay yet
+/// * `image` - The image both nodes are based on
+/// * `app_state` - Application state whose config holds the image and overlay directories
+///
+/// # Returns
+/// Ok(()) if the clone's disk was created
+pub async fn clone_node(
+    source: &Node,
+    clone: &Node,
+    image: &Image,
+    app_state: &AppState,
+) -> Result<(), QemuError> {
+    // The overlay may be mid-write while the VM runs
+    if source.status == NodeStatus::Running {
+        return Err(QemuError::NodeAlreadyRunning);
+    }
+
+    let source_overlay = source
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    if !tokio::fs::try_exists(&source_overlay).await? {
+        return create_instance_overlay(clone, image, app_state).await;
+    }
+
+    let image_path = image
+        .get_full_path(app_state)
+        .map_err(|_| QemuError::ImageNotFound(image.id))?;
+    let clone_overlay = clone
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    if clone_overlay.exists() {
+        return Err(QemuError::OverlayAlreadyExists(
+            clone_overlay.display().to_string(),
+        ));
+    }
+
+    convert_overlay(&source_overlay, &image_path, &clone_overlay).await?;
+
+    let source_vars = source
+        .get_uefi_vars_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    if tokio::fs::try_exists(&source_vars).await? {
+        let clone_vars = clone
+            .get_uefi_vars_path(app_state)
+            .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+        tokio::fs::copy(&source_vars, &clone_vars).await?;
+    }
+
+    info!("Cloned node {} into {}", source.id, clone.id);
+    Ok(())
+}
+
+/// Save a stopped node's disk as a new image layered on the node's image
+///
+/// The new image holds only what the node changed, backed by the node's
+/// image just like the overlay was, so `target` must have that image as its
+/// parent. UEFI variables are not part of the image.
+///
+/// # Arguments
+/// * `node` - The stopped node whose changes to keep
+/// * `image` - The image the node is based on
+/// * `target` - The new image, whose file must not exist yet
+/// * `app_state` - Application state whose config holds the image and overlay directories
+pub async fn commit_node(
+    node: &Node,
+    image: &Image,
+    target: &Image,
+    app_state: &AppState,
 ) -> Result<(), QemuError> {
-    // TODO: Wipe node's instance overlay
-    // 1. Ensure the node is stopped
-    // 2. Delete existing instance overlay
-    // 3. Create fresh instance overlay pointing to the image
-    unimplemented!("wipe_node is not yet implemented")
+    // The overlay may be mid-write while the VM runs
+    if node.status == NodeStatus::Running {
+        return Err(QemuError::NodeAlreadyRunning);
+    }
+
+    let overlay = node
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    if !tokio::fs::try_exists(&overlay).await? {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "node {} has never been started, so it has no changes to commit",
+            node.id
+        )));
+    }
+
+    let image_path = image
+        .get_full_path(app_state)
+        .map_err(|_| QemuError::ImageNotFound(image.id))?;
+    let target_path = target
+        .get_full_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    if target_path.exists() {
+        return Err(QemuError::OverlayAlreadyExists(
+            target_path.display().to_string(),
+        ));
+    }
+
+    convert_overlay(&overlay, &image_path, &target_path).await?;
+    info!("Committed node {} into image {}", node.id, target.id);
+    Ok(())
+}
+
+/// Copy an overlay to `destination`, keeping it backed by `backing_image`
+async fn convert_overlay(
+    source: &Path,
+    backing_image: &Path,
+    destination: &Path,
+) -> Result<(), QemuError> {
+    // With -B only clusters that differ from the backing image are written
+    let output = Command::new(QEMU_IMG_BINARY)
+        .args(["convert", "-O", "qcow2", "-B"])
+        .arg(backing_image)
+        .args(["-F", "qcow2"])
+        .arg(source)
+        .arg(destination)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(QemuError::ImagePathError(format!(
+            "qemu-img convert failed for {}: {}",
+            destination.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Allocate a SPICE port within `SPICE_PORT_RANGE` ports of `range_start`
+///
+/// # Arguments
+/// * `used_ports` - Ports taken by other running nodes
+/// * `range_start` - Lowest port to allocate from
+pub fn allocate_spice_port(used_ports: &HashSet<u16>, range_start: u16) -> Result<u16, QemuError> {
+    let range_end = range_start.saturating_add(SPICE_PORT_RANGE - 1);
+    (range_start..=range_end)
+        .find(|port| !used_ports.contains(port))
+        .ok_or(QemuError::SpicePortAllocationFailed)
+}
+
+/// Whether `/dev/kvm` exists and this process may open it for reading and
+/// writing, which QEMU needs for `-enable-kvm`
+pub fn kvm_available() -> bool {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(KVM_DEVICE)
+        .is_ok()
 }
 
 /// Allocate an available VNC display number
@@ -301,15 +2490,77 @@ pub async fn wipe_node(
 /// # Returns
 /// An available display number
 pub fn allocate_vnc_display(
-    _used_displays: &std::collections::HashSet<u16>,
-    _range_start: u16,
-    _range_end: u16,
+    used_displays: &HashSet<u16>,
+    range_start: u16,
+    range_end: u16,
 ) -> Result<u16, QemuError> {
-    // TODO: Find an available VNC display number
-    // 1. Iterate through range
-    // 2. Return first unused display
-    // 3. Error if all displays in range are used
-    unimplemented!("allocate_vnc_display is not yet implemented")
+    (range_start..=range_end)
+        .find(|display| !used_displays.contains(display))
+        .ok_or(QemuError::VncPortAllocationFailed)
+}
+
+/// VNC displays taken by running nodes, derived from their recorded `vnc_port`
+///
+/// The database rather than the instance registry is the source of truth,
+/// so allocations made by an earlier run of the backend are still seen.
+/// Only running nodes count, so a display left on a stopped node's row is
+/// never treated as taken.
+pub async fn used_vnc_displays(app_state: &AppState) -> Result<HashSet<u16>, QemuError> {
+    let ports: Vec<i32> =
+        sqlx::query_scalar("SELECT vnc_port FROM nodes WHERE vnc_port IS NOT NULL AND status = $1")
+            .bind(NodeStatus::Running)
+            .fetch_all(&app_state.db)
+            .await?;
+
+    Ok(ports
+        .into_iter()
+        .filter_map(|port| u16::try_from(port).ok())
+        .filter_map(|port| port.checked_sub(VNC_BASE_PORT))
+        .collect())
+}
+
+/// Claim a VNC display that no running node is using.
+///
+/// Reading the displays in use and claiming a free one happen under one
+/// lock, and the claim lasts until it is dropped, so concurrent requests
+/// get distinct displays as long as each keeps its claim until `vnc_port`
+/// is recorded.
+///
+/// Claims are read before the database: a claim released after that read
+/// was recorded first, so the query that follows sees its display.
+pub async fn next_vnc_display(app_state: &AppState) -> Result<VncDisplayClaim, QemuError> {
+    let _allocating = app_state.instances.lock_vnc_allocation().await;
+    let mut used = app_state.instances.claimed_vnc_displays();
+    used.extend(used_vnc_displays(app_state).await?);
+    let display = allocate_vnc_display(&used, FIRST_VNC_DISPLAY, LAST_VNC_DISPLAY)?;
+    Ok(app_state.instances.claim_vnc_display(display))
+}
+
+/// SPICE ports recorded on running nodes
+pub async fn used_spice_ports(app_state: &AppState) -> Result<HashSet<u16>, QemuError> {
+    let ports: Vec<i32> = sqlx::query_scalar(
+        "SELECT spice_port FROM nodes WHERE spice_port IS NOT NULL AND status = $1",
+    )
+    .bind(NodeStatus::Running)
+    .fetch_all(&app_state.db)
+    .await?;
+
+    Ok(ports
+        .into_iter()
+        .filter_map(|port| u16::try_from(port).ok())
+        .collect())
+}
+
+/// Claim a SPICE port that no running node is using.
+///
+/// Works like `next_vnc_display`: keep the claim until the port is recorded
+/// as the node's `spice_port`.
+pub async fn next_spice_port(app_state: &AppState) -> Result<SpicePortClaim, QemuError> {
+    let _allocating = app_state.instances.lock_spice_allocation().await;
+    let mut used = app_state.instances.claimed_spice_ports();
+    used.extend(used_spice_ports(app_state).await?);
+    let port = allocate_spice_port(&used, app_state.config.qemu.spice_port_base)?;
+    Ok(app_state.instances.claim_spice_port(port))
 }
 
 /// Build the QEMU command line arguments
@@ -317,29 +2568,210 @@ pub fn allocate_vnc_display(
 /// # Arguments
 /// * `node` - The node to build arguments for
 /// * `image_chain` - Full chain of ancestor images
-/// * `config` - QEMU configuration
-/// * `app_state` - Application state containing env
+/// * `config` - Per-node QEMU options
+/// * `app_state` - Application state whose config holds the overlay directory
 ///
 /// # Returns
 /// Vector of command line arguments
 fn build_qemu_args(
-    _node: &Node,
-    _image_chain: &[Image],
-    _config: &QemuConfig,
-    _app_state: &AppState,
+    node: &Node,
+    image_chain: &[Image],
+    config: &QemuConfig,
+    app_state: &AppState,
 ) -> Result<Vec<String>, QemuError> {
-    // TODO: Build QEMU command line
-    // Arguments should include:
-    // - Memory (-m)
-    // - CPU (-smp)
-    // - KVM (-enable-kvm if supported)
-    // - Disk image (-drive) pointing to instance overlay
-    //   (which chains back through image_chain to base)
-    // - VNC (-vnc if enabled)
-    // - Monitor socket (-monitor)
-    // - Network configuration
-    // - Any extra args
-    unimplemented!("build_qemu_args is not yet implemented")
+    if image_chain.is_empty() {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "Node {} has an empty image chain",
+            node.id
+        )));
+    }
+
+    // The instance overlay's qcow2 header already chains back through
+    // image_chain to the base image, so only the overlay is passed as a drive
+    let overlay_path = node
+        .get_instance_overlay_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+    let monitor_socket = node
+        .get_monitor_socket_path(app_state)
+        .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+
+    let mut args = vec![
+        "-name".to_string(),
+        escape_option_value(&node.name),
+        "-m".to_string(),
+        config.memory_mb.to_string(),
+        "-smp".to_string(),
+        format!(
+            "{},maxcpus={}",
+            config.cpu_cores,
+            config.max_cpus.max(config.cpu_cores)
+        ),
+    ];
+
+    if config.enable_kvm {
+        if kvm_available() {
+            args.push("-enable-kvm".into());
+        } else if app_state.config.qemu.kvm_mode == KvmMode::Strict {
+            return Err(QemuError::InvalidConfiguration(format!(
+                "KVM is not available: {KVM_DEVICE} is missing or not accessible"
+            )));
+        } else {
+            warn!(
+                "KVM is not available, starting node {} with TCG emulation",
+                node.id
+            );
+        }
+    }
+
+    if let Some(uefi) = &config.uefi {
+        args.push("-drive".into());
+        args.push(format!(
+            "if=pflash,format=raw,readonly=on,file={}",
+            escape_option_value(&uefi.code.to_string_lossy())
+        ));
+        args.push("-drive".into());
+        args.push(format!(
+            "if=pflash,format=raw,file={}",
+            escape_option_value(&uefi.vars.to_string_lossy())
+        ));
+    }
+
+    args.push("-drive".into());
+    args.push(format!(
+        "file={},format=qcow2,if=virtio,id={}",
+        escape_option_value(&overlay_path.to_string_lossy()),
+        DISK_DRIVE_ID
+    ));
+
+    if let Some(network) = &config.network {
+        let (netdev, mac) = match network {
+            NetworkConfig::Tap { mac, .. } => (
+                format!(
+                    "tap,id=net0,ifname={},script=no,downscript=no",
+                    tap_device_name(node.id)
+                ),
+                mac.clone(),
+            ),
+            NetworkConfig::User { forwards } => {
+                let mut netdev = "user,id=net0".to_string();
+                for forward in forwards {
+                    netdev.push_str(&format!(
+                        ",hostfwd=tcp::{}-:{}",
+                        forward.host_port, forward.guest_port
+                    ));
+                }
+                (netdev, None)
+            }
+        };
+        let mac = mac.unwrap_or_else(|| default_mac_address(node.id));
+
+        args.push("-netdev".into());
+        args.push(netdev);
+        args.push("-device".into());
+        args.push(format!("virtio-net,netdev=net0,mac={}", mac));
+    }
+
+    if config.cdrom {
+        // An empty drive with a device id, so media can be changed through QMP
+        args.push("-drive".into());
+        args.push(format!("if=none,id={}-drive,media=cdrom", CDROM_DEVICE_ID));
+        args.push("-device".into());
+        args.push(format!(
+            "ide-cd,drive={}-drive,id={}",
+            CDROM_DEVICE_ID, CDROM_DEVICE_ID
+        ));
+    }
+
+    if config.guest_agent {
+        let socket = node
+            .get_guest_agent_socket_path(app_state)
+            .map_err(|e| QemuError::ImagePathError(e.to_string()))?;
+        args.push("-chardev".into());
+        args.push(format!(
+            "socket,id=qga0,path={},server=on,wait=off",
+            escape_option_value(&socket.to_string_lossy())
+        ));
+        args.push("-device".into());
+        args.push("virtio-serial".into());
+        args.push("-device".into());
+        args.push(format!(
+            "virtserialport,chardev=qga0,name={}",
+            GUEST_AGENT_PORT_NAME
+        ));
+    }
+
+    if config.balloon {
+        args.push("-device".into());
+        args.push(format!("virtio-balloon,id={}", BALLOON_DEVICE_ID));
+    }
+
+    if let Some(serial_log) = &config.serial_log {
+        args.push("-chardev".into());
+        args.push(format!(
+            "file,id=serial0,path={}",
+            escape_option_value(&serial_log.to_string_lossy())
+        ));
+        args.push("-serial".into());
+        args.push("chardev:serial0".into());
+    }
+
+    // Links come after the primary NIC so its guest interface name stays stable
+    let bytes = node.id.as_bytes();
+    for (index, link) in config.links.iter().enumerate() {
+        trace!("Node {} joins link {}", node.id, link.link_id);
+        args.push("-netdev".into());
+        args.push(format!(
+            "tap,id=link{},ifname={},script=no,downscript=no",
+            index, link.tap_device
+        ));
+        args.push("-device".into());
+        args.push(format!(
+            "virtio-net,netdev=link{},mac=52:54:{:02x}:{:02x}:{:02x}:{:02x}",
+            index,
+            // The primary NIC uses 52:54:00, so number links from 01
+            index + 1,
+            bytes[13],
+            bytes[14],
+            bytes[15]
+        ));
+    }
+
+    args.push("-qmp".into());
+    args.push(format!(
+        "unix:{},server=on,wait=off",
+        escape_option_value(&monitor_socket.to_string_lossy())
+    ));
+
+    // Without an explicit display QEMU would not create a VNC server at all,
+    // so start one that is not listening and bind it later through the monitor
+    args.push("-display".into());
+    args.push("none".into());
+    args.push("-vnc".into());
+    let vnc_address = match config.vnc_display {
+        Some(display) => format!("{}:{}", app_state.config.qemu.vnc_bind_host, display),
+        None => "none".into(),
+    };
+    // Password authentication can only be turned on at startup; without a
+    // password set through the monitor every client is refused
+    args.push(format!("{},password=on", vnc_address));
+
+    if let Some(port) = config.spice_port {
+        args.push("-spice".into());
+        args.push(format!(
+            "port={},addr={},disable-ticketing=on",
+            port,
+            escape_option_value(&app_state.config.qemu.vnc_bind_host)
+        ));
+    }
+
+    args.extend(config.extra_args.iter().cloned());
+
+    Ok(args)
+}
+
+/// Escape a value for use inside a comma-separated QEMU option string
+fn escape_option_value(value: &str) -> String {
+    value.replace(',', ",,")
 }
 
 /// Get the full image chain for a node (from base to immediate parent)
@@ -351,29 +2783,549 @@ fn build_qemu_args(
 /// # Returns
 /// Vector of images from root base image to the specified image
 pub async fn get_image_chain(
-    _image_id: Uuid,
-    _app_state: &AppState,
+    image_id: Uuid,
+    app_state: &AppState,
 ) -> Result<Vec<Image>, QemuError> {
-    // TODO: Fetch full image ancestry from database
-    // 1. Start with the given image_id
-    // 2. Follow parent_id links until reaching a base image (parent_id = None)
-    // 3. Return the chain in order from base to leaf
-    unimplemented!("get_image_chain is not yet implemented")
+    // Walk the parent links in a single query; the depth bound keeps a
+    // malformed parent_id loop from recursing forever
+    let chain: Vec<Image> = sqlx::query_as(
+        r#"
+        WITH RECURSIVE chain AS (
+            SELECT id, name, path, parent_id, description, sha256, 0 AS depth
+            FROM images
+            WHERE id = $1
+            UNION ALL
+            SELECT i.id, i.name, i.path, i.parent_id, i.description, i.sha256, c.depth + 1
+            FROM images i
+            JOIN chain c ON i.id = c.parent_id
+            WHERE c.depth < $2
+        )
+        SELECT id, name, path, parent_id, description, sha256
+        FROM chain
+        ORDER BY depth DESC
+        "#,
+    )
+    .bind(image_id)
+    .bind(MAX_IMAGE_CHAIN_DEPTH)
+    .fetch_all(&app_state.db)
+    .await?;
+
+    if chain.is_empty() {
+        return Err(QemuError::ImageNotFound(image_id));
+    }
+
+    let mut visited = HashSet::new();
+    for image in &chain {
+        if !visited.insert(image.id) {
+            return Err(QemuError::InvalidConfiguration(format!(
+                "Image {} appears twice in the ancestry of {}",
+                image.id, image_id
+            )));
+        }
+    }
+
+    if !chain[0].is_base_image() {
+        return Err(QemuError::InvalidConfiguration(format!(
+            "Ancestry of image {} exceeds {} levels",
+            image_id, MAX_IMAGE_CHAIN_DEPTH
+        )));
+    }
+
+    Ok(chain)
 }
 
 /// Send a command to the QEMU monitor
 ///
+/// Speaks QMP over the monitor's Unix socket: negotiates capabilities, then
+/// executes `command` and returns the contents of its `return` member.
+///
 /// # Arguments
 /// * `socket_path` - Path to the monitor socket
-/// * `command` - The command to send
+/// * `command` - The QMP command to execute
+/// * `arguments` - Optional `arguments` object for the command
 ///
 /// # Returns
 /// The response from the monitor
-async fn send_monitor_command(_socket_path: &PathBuf, _command: &str) -> Result<String, QemuError> {
-    // TODO: Communicate with QEMU monitor via Unix socket
-    // 1. Connect to socket
-    // 2. Send command
-    // 3. Read response
-    // 4. Return response
-    unimplemented!("send_monitor_command is not yet implemented")
+async fn send_monitor_command(
+    socket_path: &Path,
+    command: &str,
+    arguments: Option<Value>,
+) -> Result<Value, QemuError> {
+    send_monitor_command_with_timeout(socket_path, command, arguments, MONITOR_TIMEOUT).await
+}
+
+async fn send_monitor_command_with_timeout(
+    socket_path: &Path,
+    command: &str,
+    arguments: Option<Value>,
+    limit: Duration,
+) -> Result<Value, QemuError> {
+    timeout(limit, qmp_exchange(socket_path, command, arguments))
+        .await
+        .map_err(|_| {
+            QemuError::MonitorError(format!("Timed out waiting for a response to `{}`", command))
+        })?
+}
+
+/// Run a human monitor command (e.g. `savevm`) through QMP
+///
+/// HMP reports failures as text output rather than a QMP error, so any
+/// output is treated as an error message.
+async fn human_monitor_command(socket_path: &Path, command_line: &str) -> Result<(), QemuError> {
+    let output = send_monitor_command_with_timeout(
+        socket_path,
+        "human-monitor-command",
+        Some(json!({ "command-line": command_line })),
+        SNAPSHOT_TIMEOUT,
+    )
+    .await?;
+
+    match output.as_str().map(str::trim) {
+        None | Some("") => Ok(()),
+        Some(message) => Err(QemuError::MonitorError(message.to_string())),
+    }
+}
+
+async fn qmp_exchange(
+    socket_path: &Path,
+    command: &str,
+    arguments: Option<Value>,
+) -> Result<Value, QemuError> {
+    let stream = UnixStream::connect(socket_path).await.map_err(|e| {
+        QemuError::MonitorError(format!(
+            "Failed to connect to {}: {}",
+            socket_path.display(),
+            e
+        ))
+    })?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let greeting = read_qmp_message(&mut lines).await?;
+    if greeting.get("QMP").is_none() {
+        return Err(QemuError::MonitorError(format!(
+            "Unexpected QMP greeting: {}",
+            greeting
+        )));
+    }
+
+    write_qmp_command(&mut writer, "qmp_capabilities", None).await?;
+    read_qmp_response(&mut lines).await?;
+
+    trace!("Sending QMP command `{}`", command);
+    write_qmp_command(&mut writer, command, arguments).await?;
+    read_qmp_response(&mut lines).await
+}
+
+/// Send a command to qemu-guest-agent inside the VM
+///
+/// The agent speaks the same JSON framing as QMP over its virtio-serial
+/// channel but has no greeting. A `guest-sync` with a random id comes first
+/// so replies left over from an earlier, abandoned exchange are skipped.
+/// Fails with a timeout when the guest isn't running the agent.
+///
+/// # Arguments
+/// * `instance` - The QEMU instance whose guest to talk to
+/// * `command` - The guest agent command, e.g. `guest-info`
+/// * `arguments` - Optional `arguments` object for the command
+///
+/// # Returns
+/// The contents of the response's `return` member
+pub async fn guest_agent_command(
+    instance: &QemuInstance,
+    command: &str,
+    arguments: Option<Value>,
+) -> Result<Value, QemuError> {
+    let socket_path = instance
+        .guest_agent_socket
+        .as_deref()
+        .ok_or_else(|| QemuError::MonitorError("Node has no guest agent channel".into()))?;
+    timeout(
+        GUEST_AGENT_TIMEOUT,
+        guest_agent_exchange(socket_path, command, arguments, true),
+    )
+    .await
+    .map_err(|_| QemuError::MonitorError("Guest agent did not respond".into()))?
+}
+
+/// Ask the guest agent to power the guest off
+///
+/// `guest-shutdown` sends no reply when it succeeds, so the agent is pinged
+/// first to confirm it is listening.
+async fn guest_agent_shutdown(instance: &QemuInstance) -> Result<(), QemuError> {
+    guest_agent_command(instance, "guest-ping", None).await?;
+    let socket_path = instance
+        .guest_agent_socket
+        .as_deref()
+        .ok_or_else(|| QemuError::MonitorError("Node has no guest agent channel".into()))?;
+    timeout(
+        GUEST_AGENT_TIMEOUT,
+        guest_agent_exchange(
+            socket_path,
+            "guest-shutdown",
+            Some(json!({ "mode": "powerdown" })),
+            false,
+        ),
+    )
+    .await
+    .map_err(|_| QemuError::MonitorError("Guest agent did not respond".into()))?
+    .map(|_| ())
+}
+
+async fn guest_agent_exchange(
+    socket_path: &Path,
+    command: &str,
+    arguments: Option<Value>,
+    await_reply: bool,
+) -> Result<Value, QemuError> {
+    let stream = UnixStream::connect(socket_path).await.map_err(|e| {
+        QemuError::MonitorError(format!(
+            "Failed to connect to {}: {}",
+            socket_path.display(),
+            e
+        ))
+    })?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let sync_id = Uuid::new_v4().as_u128() as u32;
+    write_qmp_command(&mut writer, "guest-sync", Some(json!({ "id": sync_id }))).await?;
+    while read_qmp_response(&mut lines).await?.as_u64() != Some(u64::from(sync_id)) {}
+
+    trace!("Sending guest agent command `{}`", command);
+    write_qmp_command(&mut writer, command, arguments).await?;
+    if await_reply {
+        read_qmp_response(&mut lines).await
+    } else {
+        Ok(Value::Null)
+    }
+}
+
+async fn write_qmp_command(
+    writer: &mut OwnedWriteHalf,
+    command: &str,
+    arguments: Option<Value>,
+) -> Result<(), QemuError> {
+    let mut message = json!({ "execute": command });
+    if let Some(arguments) = arguments {
+        message["arguments"] = arguments;
+    }
+
+    let mut payload = message.to_string();
+    payload.push('\n');
+    writer
+        .write_all(payload.as_bytes())
+        .await
+        .map_err(|e| QemuError::MonitorError(e.to_string()))
+}
+
+async fn read_qmp_message(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Result<Value, QemuError> {
+    let line = lines
+        .next_line()
+        .await
+        .map_err(|e| QemuError::MonitorError(e.to_string()))?
+        .ok_or_else(|| QemuError::MonitorError("Monitor closed the connection".into()))?;
+
+    serde_json::from_str(&line)
+        .map_err(|e| QemuError::MonitorError(format!("Malformed QMP message: {}", e)))
+}
+
+async fn read_qmp_response(
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+) -> Result<Value, QemuError> {
+    loop {
+        let message = read_qmp_message(lines).await?;
+
+        if let Some(result) = message.get("return") {
+            return Ok(result.clone());
+        }
+
+        if let Some(error) = message.get("error") {
+            let description = error
+                .get("desc")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            return Err(QemuError::MonitorError(description.to_string()));
+        }
+
+        // Asynchronous events can arrive between a command and its response
+        trace!("Skipping QMP event: {}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use axum::{
+        extract::{Path as PathParam, State},
+        http::StatusCode,
+        response::IntoResponse,
+    };
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use sqlx::PgPool;
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::audit::Actor;
+    use crate::config::Config;
+    use crate::models::{InstanceRegistry, NodeEvent};
+    use crate::routes;
+
+    /// State for tests that only need the database; nothing else is reachable
+    fn test_state(db: PgPool) -> AppState {
+        let env: HashMap<String, String> = [
+            ("POSTGRES_USER", "test"),
+            ("POSTGRES_PASSWORD", "test"),
+            ("POSTGRES_HOST", "localhost"),
+            ("POSTGRES_PORT", "5432"),
+            ("BACKEND_DB", "test"),
+            ("BACKEND_HOST", "127.0.0.1"),
+            ("BACKEND_PORT", "0"),
+            ("IMAGE_DIR", "/nonexistent/images"),
+            ("OVERLAY_DIR", "/nonexistent/overlays"),
+            ("GUAC_HTTPS", "0"),
+            ("GUAC_HOST", "localhost"),
+            ("GUAC_PORT", "8080"),
+            ("GUAC_TUNNEL_PATH", "websocket-tunnel"),
+            ("GUAC_API_PATH", "api"),
+            ("GUAC_CONNECTION_PREFIX", "test"),
+            ("GUAC_USER", "guacadmin"),
+            ("GUAC_PASS", "guacadmin"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+        AppState {
+            db,
+            config: Arc::new(Config::from_env(&env).unwrap()),
+            instances: InstanceRegistry::default(),
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            events: broadcast::channel(16).0,
+        }
+    }
+
+    /// Insert a running node, returning its id
+    async fn insert_running_node(db: &PgPool, name: &str) -> Uuid {
+        let image_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO images (name, path) VALUES ($1, $1) \
+             ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name RETURNING id",
+        )
+        .bind("base.qcow2")
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        let node_id = Uuid::now_v7();
+        sqlx::query(
+            "INSERT INTO nodes (id, name, status, image_id, instance_overlay_path) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(node_id)
+        .bind(name)
+        .bind(NodeStatus::Running)
+        .bind(image_id)
+        .bind(format!("{}.qcow2", node_id))
+        .execute(db)
+        .await
+        .unwrap();
+        node_id
+    }
+
+    async fn record_vnc_display(db: &PgPool, node_id: Uuid, display: u16) {
+        sqlx::query("UPDATE nodes SET vnc_port = $1 WHERE id = $2")
+            .bind(i32::from(VNC_BASE_PORT + display))
+            .bind(node_id)
+            .execute(db)
+            .await
+            .unwrap();
+    }
+
+    async fn allocate(state: &AppState) -> u16 {
+        let used = used_vnc_displays(state).await.unwrap();
+        allocate_vnc_display(&used, FIRST_VNC_DISPLAY, LAST_VNC_DISPLAY).unwrap()
+    }
+
+    /// An instance tracking `process`, with nothing else attached
+    fn instance_with(process: Child) -> QemuInstance {
+        QemuInstance {
+            node_id: Uuid::now_v7(),
+            process,
+            memory_mb: 512,
+            cpu_cores: 1,
+            max_cpus: 1,
+            cdrom_drive: false,
+            cdrom: None,
+            vnc_port: None,
+            vnc_host: "127.0.0.1".into(),
+            vnc_password: None,
+            spice_port: None,
+            monitor_socket: None,
+            guest_agent_socket: None,
+            tap_device: None,
+            links: Vec::new(),
+            capture: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn is_running_sees_the_process_exit() {
+        let process = Command::new("sleep").arg("0.2").spawn().unwrap();
+        let mut instance = instance_with(process);
+
+        assert!(matches!(is_running(&mut instance).await, Ok(true)));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while matches!(is_running(&mut instance).await, Ok(true)) {
+            assert!(Instant::now() < deadline, "sleep did not exit");
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(matches!(is_running(&mut instance).await, Ok(false)));
+    }
+
+    #[tokio::test]
+    async fn is_running_reports_a_failed_exit() {
+        let process = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let mut instance = instance_with(process);
+        instance.process.wait().await.unwrap();
+
+        match is_running(&mut instance).await {
+            Err(QemuError::ProcessExited(status)) => assert_eq!(status, "exit code 3"),
+            other => panic!("expected ProcessExited, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn allocate_vnc_display_fails_on_an_exhausted_range() {
+        let used: HashSet<u16> = (5..=9).collect();
+        assert!(matches!(
+            allocate_vnc_display(&used, 5, 9),
+            Err(QemuError::VncPortAllocationFailed)
+        ));
+    }
+
+    #[test]
+    fn allocate_vnc_display_fills_holes_first() {
+        let used = HashSet::from([5, 6, 8, 9]);
+        assert_eq!(allocate_vnc_display(&used, 5, 9).unwrap(), 7);
+    }
+
+    #[test]
+    fn allocate_vnc_display_includes_the_upper_bound() {
+        let used: HashSet<u16> = (5..9).collect();
+        assert_eq!(allocate_vnc_display(&used, 5, 9).unwrap(), 9);
+        assert_eq!(allocate_vnc_display(&HashSet::new(), 9, 9).unwrap(), 9);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a PostgreSQL server in DATABASE_URL"]
+    async fn stopping_a_node_frees_its_vnc_display(db: PgPool) {
+        let state = test_state(db.clone());
+        let node_id = insert_running_node(&db, "first").await;
+
+        let display = allocate(&state).await;
+        record_vnc_display(&db, node_id, display).await;
+        assert_ne!(allocate(&state).await, display);
+
+        let response = routes::stop_node(
+            State(state.clone()),
+            Actor("test".into()),
+            PathParam(node_id),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(allocate(&state).await, display);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a PostgreSQL server in DATABASE_URL"]
+    async fn disabling_vnc_frees_the_display(db: PgPool) {
+        let state = test_state(db.clone());
+        let node_id = insert_running_node(&db, "first").await;
+
+        let display = allocate(&state).await;
+        record_vnc_display(&db, node_id, display).await;
+        assert_ne!(allocate(&state).await, display);
+
+        let mut events = state.events.subscribe();
+        let response = routes::disable_node_vnc(
+            State(state.clone()),
+            Actor("test".into()),
+            PathParam(node_id),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(NodeEvent::VncDisabled { node_id: event_node }) if event_node == node_id
+        ));
+
+        assert_eq!(allocate(&state).await, display);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a PostgreSQL server in DATABASE_URL"]
+    async fn concurrent_vnc_claims_are_distinct(db: PgPool) {
+        let state = test_state(db.clone());
+        let mut nodes = Vec::new();
+        for i in 0..50 {
+            nodes.push(insert_running_node(&db, &format!("node-{}", i)).await);
+        }
+
+        // Like a launch: hold the claim until vnc_port is recorded, then drop it
+        let tasks: Vec<_> = nodes
+            .into_iter()
+            .map(|node_id| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let claim = next_vnc_display(&state).await.unwrap();
+                    record_vnc_display(&state.db, node_id, claim.display()).await;
+                    claim.display()
+                })
+            })
+            .collect();
+        let mut displays = HashSet::new();
+        for task in tasks {
+            assert!(displays.insert(task.await.unwrap()));
+        }
+
+        assert!(state.instances.claimed_vnc_displays().is_empty());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a PostgreSQL server in DATABASE_URL"]
+    async fn concurrent_spice_claims_are_distinct(db: PgPool) {
+        let state = test_state(db.clone());
+        let mut nodes = Vec::new();
+        for i in 0..50 {
+            nodes.push(insert_running_node(&db, &format!("node-{}", i)).await);
+        }
+
+        let tasks: Vec<_> = nodes
+            .into_iter()
+            .map(|node_id| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let claim = next_spice_port(&state).await.unwrap();
+                    sqlx::query("UPDATE nodes SET spice_port = $1 WHERE id = $2")
+                        .bind(i32::from(claim.port()))
+                        .bind(node_id)
+                        .execute(&state.db)
+                        .await
+                        .unwrap();
+                    claim.port()
+                })
+            })
+            .collect();
+        let mut ports = HashSet::new();
+        for task in tasks {
+            assert!(ports.insert(task.await.unwrap()));
+        }
+
+        assert!(state.instances.claimed_spice_ports().is_empty());
+    }
 }
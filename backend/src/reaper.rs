@@ -0,0 +1,94 @@
+use ::metrics::counter;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::metrics;
+use crate::models::{AppState, NodeEvent, NodeStatus, SharedInstance};
+use crate::qemu;
+
+/// Notice QEMU processes that exit on their own and record them as stopped.
+///
+/// Every SIGCHLD triggers a check of all tracked instances. Stopping a node
+/// through the API removes it from the registry before QEMU exits, so only
+/// exits nobody asked for are handled here.
+pub async fn run(state: AppState) {
+    let mut sigchld = match signal(SignalKind::child()) {
+        Ok(stream) => stream,
+        Err(err) => {
+            error!("Failed to listen for SIGCHLD, exited nodes won't be noticed: {err}");
+            return;
+        }
+    };
+
+    while sigchld.recv().await.is_some() {
+        for (node_id, instance) in state.instances.entries().await {
+            // An instance can be locked for a while by a slow monitor command,
+            // which shouldn't hold up checking the others
+            tokio::spawn(check_instance(state.clone(), node_id, instance));
+        }
+    }
+}
+
+/// Record the exit of `instance` if its process has exited and it is still
+/// the node's tracked instance.
+///
+/// The instance stays locked and tracked until the exit is recorded. A
+/// concurrent start is refused as already running until then, and a
+/// concurrent stop waits for the lock, so neither can have its own state
+/// overwritten here.
+pub async fn check_instance(state: AppState, node_id: Uuid, instance: SharedInstance) {
+    let mut guard = instance.lock().await;
+    let status = match guard.process.try_wait() {
+        Ok(Some(status)) => status,
+        Ok(None) => return,
+        Err(err) => {
+            warn!("Failed to check the QEMU process of node {node_id}: {err}");
+            return;
+        }
+    };
+
+    // A stop we initiated removes the instance from the registry first
+    if !state.instances.tracks(node_id, &instance).await {
+        return;
+    }
+
+    // Release before marking the node stopped: a start is only accepted
+    // once the node no longer reads as running, and its reservation must
+    // not be the one released
+    state.instances.release(node_id).await;
+    if let Err(err) = sqlx::query(
+        "UPDATE nodes SET status = $1, vnc_port = NULL, paused = FALSE, spice_port = NULL \
+         WHERE id = $2 AND status = $3",
+    )
+    .bind(NodeStatus::Stopped)
+    .bind(node_id)
+    .bind(NodeStatus::Running)
+    .execute(&state.db)
+    .await
+    {
+        error!("Failed to mark exited node {node_id} stopped: {err}");
+    }
+
+    state.instances.remove_if_same(node_id, &instance).await;
+    // The process is already gone; this only removes its sockets and TAP device
+    if let Err(err) = qemu::kill_node(&mut guard).await {
+        warn!("Failed to clean up after node {node_id} exited: {err}");
+    }
+    drop(guard);
+
+    let crashed = !status.success();
+    if crashed {
+        warn!("QEMU of node {node_id} exited unexpectedly with {status}");
+        counter!(metrics::NODES_STOPPED, "mode" => "crashed").increment(1);
+    } else {
+        info!("Node {node_id} was powered off from inside the guest");
+        counter!(metrics::NODES_STOPPED, "mode" => "guest").increment(1);
+    }
+
+    state.publish(NodeEvent::Exited {
+        node_id,
+        crashed,
+        exit_status: status.to_string(),
+    });
+}
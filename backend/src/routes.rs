@@ -1,48 +1,2860 @@
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
 use axum::{
     Json, Router,
-    extract::{Path, State},
-    response::IntoResponse,
-    routing::post,
+    body::Body,
+    extract::{
+        DefaultBodyLimit, Multipart, Path, Query, State,
+        multipart::MultipartError,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom},
+    sync::{
+        Mutex,
+        broadcast::{Receiver, error::RecvError},
+    },
 };
+use tokio_util::io::ReaderStream;
+use tower_http::{limit::RequestBodyLimitLayer, timeout::TimeoutLayer};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::guacamole::GuacamoleConnection;
-use crate::models::{
-    ApiResponse, AppState, CreateNodeRequest, CreateVncConnectionRequest,
-    CreateVncConnectionResponse, NodeStatus,
-};
+use crate::audit::{self, Actor, AuditAction, AuditEntry};
+use crate::auth::{self, Admin};
+use crate::cors;
+use crate::download::{self, DownloadError, PartialFile};
+use crate::guacamole::{
+    ConnectionOptions, GuacamoleConnection, GuacamoleError, SessionRecording, SshCredentials,
+};
+use crate::metrics;
+use crate::models::{
+    self, ApiError, ApiResponse, AppState, AttachCdromRequest, AuditLogQuery,
+    BulkCreateNodesRequest, CloneNodeRequest, CommitNodeRequest, CpuCountResponse,
+    CreateConnectionResponse, CreateImageRequest, CreateLinkRequest, CreateNodeRequest,
+    CreateSnapshotRequest, CreateSshConnectionRequest, CreateVncConnectionRequest,
+    EnableNodeVncRequest, HealthResponse, Image, ImageVerification, ImageWithAncestors,
+    ImportImageRequest, ImportedImage, Link, ListNodesQuery, MonitorCommandRequest, NoData, Node,
+    NodeEvent, NodeList, NodeStatus, NodeStatusResponse, NodeWithImage, PortForward,
+    ReadinessResponse, RenameNodeRequest, ReservationError, ResourceBudget, ResourceUsage,
+    RestartQuery, SetMemoryRequest, ShareConnectionRequest, SharedInstance, SpiceInfoResponse,
+};
+use crate::openapi;
+use crate::qemu::{self, QemuConfig, QemuError};
+use crate::reaper;
+use crate::request_id;
+use crate::validate;
+
+/// Columns selected whenever a full `Image` row is loaded
+const IMAGE_COLUMNS: &str = "id, name, path, parent_id, description, sha256";
+
+/// Columns selected whenever a full `Node` row is loaded
+const NODE_COLUMNS: &str = "id, name, status, image_id, instance_overlay_path, vnc_port, guacamole_connection_id, paused, \
+     memory_mb, cpu_cores, network_mode, port_forwards, firmware, spice, spice_port, netem";
+
+/// Most nodes `create_nodes_bulk` creates in one request
+const MAX_BULK_NODES: u32 = 100;
+
+/// Page size of `list_nodes` when the client doesn't ask for one
+const DEFAULT_NODE_PAGE_SIZE: u32 = 50;
+/// Largest page `list_nodes` returns
+const MAX_NODE_PAGE_SIZE: u32 = 500;
+
+/// Number of audit entries `list_audit_log` returns when the client doesn't ask
+const DEFAULT_AUDIT_PAGE_SIZE: u32 = 100;
+/// Most audit entries `list_audit_log` returns at once
+const MAX_AUDIT_PAGE_SIZE: u32 = 1000;
+
+/// Room for multipart framing and text parts on top of IMAGE_UPLOAD_MAX_MB
+const UPLOAD_FORM_SLACK: usize = 1024 * 1024;
+
+/// Most console output returned by `get_console_log`
+const MAX_CONSOLE_LOG_BYTES: usize = 1024 * 1024;
+
+/// Columns selected whenever a full `Link` row is loaded
+const LINK_COLUMNS: &str = "id, node_a, node_b, latency_ms, bandwidth_kbps";
+
+/// POST /node - Create a new node
+#[utoipa::path(
+    post,
+    path = "/node",
+    tag = "nodes",
+    request_body = CreateNodeRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn create_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Json(payload): Json<CreateNodeRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = validate::name("name", &payload.name) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let image_exists: bool =
+        match sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM images WHERE id = $1)")
+            .bind(payload.image_id)
+            .fetch_one(&state.db)
+            .await
+        {
+            Ok(exists) => exists,
+            Err(e) => return internal_error("Failed to look up image", e),
+        };
+
+    if !image_exists {
+        return ApiError::NotFound(format!("Image {} not found", payload.image_id)).into_response();
+    }
+
+    if let Err(e) = qemu::validate_resources(payload.memory_mb, payload.cpu_cores, &state)
+        .and_then(|()| qemu::validate_network(payload.network_mode, &payload.port_forwards, &state))
+        .and_then(|()| {
+            qemu::validate_netem(
+                payload.netem.as_ref(),
+                payload.network_mode,
+                &payload.port_forwards,
+                &state,
+            )
+        })
+        .and_then(|()| qemu::validate_firmware(payload.firmware, &state))
+    {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let node_id = Uuid::now_v7();
+    let result: Result<Node, _> = sqlx::query_as(&format!(
+        "INSERT INTO nodes (id, name, status, image_id, instance_overlay_path, memory_mb, cpu_cores, \
+         network_mode, port_forwards, firmware, spice, netem) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(node_id)
+    .bind(&payload.name)
+    .bind(NodeStatus::Stopped)
+    .bind(payload.image_id)
+    .bind(format!("{}.qcow2", node_id))
+    .bind(payload.memory_mb.map(|v| v as i32))
+    .bind(payload.cpu_cores.map(|v| v as i32))
+    .bind(payload.network_mode)
+    .bind(SqlJson(&payload.port_forwards))
+    .bind(payload.firmware)
+    .bind(payload.spice)
+    .bind(payload.netem.map(SqlJson))
+    .fetch_one(&state.db)
+    .await;
+
+    match result {
+        Ok(node) => {
+            state.publish(NodeEvent::Created { node_id: node.id });
+            audit::record(
+                &state,
+                &actor,
+                AuditAction::NodeCreated,
+                node.id,
+                json!({ "name": node.name, "image_id": node.image_id }),
+            )
+            .await;
+            info!("Created node {} ({})", node.name, node.id);
+            ApiResponse::ok(node)
+                .with_status(StatusCode::CREATED)
+                .into_response()
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            ApiError::Conflict(format!("A node named `{}` already exists", payload.name))
+                .into_response()
+        }
+        Err(e) => internal_error("Failed to create node", e),
+    }
+}
+
+/// POST /node/bulk - Create several identical nodes at once
+///
+/// The nodes are inserted in one transaction, so either all of them are
+/// created or none are.
+#[utoipa::path(
+    post,
+    path = "/node/bulk",
+    tag = "nodes",
+    request_body = BulkCreateNodesRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<Vec<Node>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn create_nodes_bulk(
+    State(state): State<AppState>,
+    actor: Actor,
+    Json(payload): Json<BulkCreateNodesRequest>,
+) -> impl IntoResponse {
+    if payload.count == 0 || payload.count > MAX_BULK_NODES {
+        return ApiError::Validation(format!("`count` must be between 1 and {}", MAX_BULK_NODES))
+            .into_response();
+    }
+    // Pad to at least two digits so names sort in creation order
+    let width = payload.count.to_string().len().max(2);
+    let names: Vec<String> = (1..=payload.count)
+        .map(|i| format!("{}-{:0width$}", payload.name_prefix, i))
+        .collect();
+    for name in &names {
+        if let Err(e) = validate::name("name_prefix", name) {
+            return ApiError::Validation(e.to_string()).into_response();
+        }
+    }
+
+    if let Err(response) = find_image(&state, payload.image_id).await {
+        return response;
+    }
+    if let Err(e) = qemu::validate_resources(payload.memory_mb, payload.cpu_cores, &state)
+        .and_then(|()| qemu::validate_network(payload.network_mode, &[], &state))
+        .and_then(|()| {
+            qemu::validate_netem(payload.netem.as_ref(), payload.network_mode, &[], &state)
+        })
+        .and_then(|()| qemu::validate_firmware(payload.firmware, &state))
+    {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return internal_error("Failed to start transaction", e),
+    };
+    let mut nodes: Vec<Node> = Vec::with_capacity(names.len());
+    for name in &names {
+        let node_id = Uuid::now_v7();
+        let result = sqlx::query_as(&format!(
+            "INSERT INTO nodes (id, name, status, image_id, instance_overlay_path, memory_mb, cpu_cores, \
+             network_mode, port_forwards, firmware, spice, netem) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING {}",
+            NODE_COLUMNS
+        ))
+        .bind(node_id)
+        .bind(name)
+        .bind(NodeStatus::Stopped)
+        .bind(payload.image_id)
+        .bind(format!("{}.qcow2", node_id))
+        .bind(payload.memory_mb.map(|v| v as i32))
+        .bind(payload.cpu_cores.map(|v| v as i32))
+        .bind(payload.network_mode)
+        .bind(SqlJson(Vec::<PortForward>::new()))
+        .bind(payload.firmware)
+        .bind(payload.spice)
+        .bind(payload.netem.map(SqlJson))
+        .fetch_one(&mut *tx)
+        .await;
+
+        // Dropping `tx` on return rolls back the nodes inserted so far
+        match result {
+            Ok(node) => nodes.push(node),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return ApiError::Conflict(format!("A node named `{}` already exists", name))
+                    .into_response();
+            }
+            Err(e) => return internal_error("Failed to create node", e),
+        }
+    }
+    if let Err(e) = tx.commit().await {
+        return internal_error("Failed to commit node creation", e);
+    }
+
+    for node in &nodes {
+        state.publish(NodeEvent::Created { node_id: node.id });
+        audit::record(
+            &state,
+            &actor,
+            AuditAction::NodeCreated,
+            node.id,
+            json!({ "name": node.name, "image_id": node.image_id, "bulk": true }),
+        )
+        .await;
+    }
+    info!(
+        "Created {} nodes {}-* from image {}",
+        nodes.len(),
+        payload.name_prefix,
+        payload.image_id
+    );
+    ApiResponse::ok(nodes)
+        .with_status(StatusCode::CREATED)
+        .into_response()
+}
+
+/// GET /node - List nodes a page at a time, optionally filtered by `status`
+#[utoipa::path(
+    get,
+    path = "/node",
+    tag = "nodes",
+    params(ListNodesQuery),
+    responses(
+        (status = OK, body = ApiResponse<NodeList>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn list_nodes(
+    State(state): State<AppState>,
+    Query(query): Query<ListNodesQuery>,
+) -> impl IntoResponse {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_NODE_PAGE_SIZE)
+        .clamp(1, MAX_NODE_PAGE_SIZE);
+
+    let total: i64 = match sqlx::query_scalar(
+        "SELECT COUNT(*) FROM nodes WHERE $1::text IS NULL OR status = $1",
+    )
+    .bind(&query.status)
+    .fetch_one(&state.db)
+    .await
+    {
+        Ok(total) => total,
+        Err(e) => return internal_error("Failed to count nodes", e),
+    };
+
+    let nodes: Vec<Node> = match sqlx::query_as(&format!(
+        "SELECT {} FROM nodes WHERE $1::text IS NULL OR status = $1 \
+         ORDER BY created_at, id LIMIT $2 OFFSET $3",
+        NODE_COLUMNS
+    ))
+    .bind(&query.status)
+    .bind(i64::from(limit))
+    .bind(i64::from(query.offset))
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(nodes) => nodes,
+        Err(e) => return internal_error("Failed to list nodes", e),
+    };
+
+    // Many nodes usually share a handful of images, so each chain is only fetched once
+    let mut chains = HashMap::new();
+    let mut result = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        match with_image(&state, node, &mut chains).await {
+            Ok(node) => result.push(node),
+            Err(response) => return response,
+        }
+    }
+
+    ApiResponse::ok(NodeList {
+        nodes: result,
+        total,
+        limit,
+        offset: query.offset,
+    })
+    .into_response()
+}
+
+/// GET /node/{id} - Get a single node with its image ancestry
+#[utoipa::path(
+    get,
+    path = "/node/{id}",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<NodeWithImage>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_node(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    match with_image(&state, node, &mut HashMap::new()).await {
+        Ok(node) => ApiResponse::ok(node).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// PATCH /node/{id} - Rename a node
+///
+/// With `rename_connection`, the node's Guacamole connection is renamed to
+/// match. The node is renamed even if that fails, with a warning logged.
+#[utoipa::path(
+    patch,
+    path = "/node/{id}",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = RenameNodeRequest,
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn rename_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RenameNodeRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = validate::name("name", &payload.name) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let previous = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    let result: Result<Node, _> = sqlx::query_as(&format!(
+        "UPDATE nodes SET name = $1 WHERE id = $2 RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(&payload.name)
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+
+    let node = match result {
+        Ok(node) => node,
+        Err(sqlx::Error::RowNotFound) => {
+            return ApiError::NotFound(format!("Node {} not found", id)).into_response();
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return ApiError::Conflict(format!("A node named `{}` already exists", payload.name))
+                .into_response();
+        }
+        Err(e) => return internal_error("Failed to rename node", e),
+    };
+
+    if payload.rename_connection
+        && let Some(connection_id) = &node.guacamole_connection_id
+        && let Err(e) =
+            GuacamoleConnection::rename_by_id(&state.config.guacamole, connection_id, &node.name)
+                .await
+    {
+        warn!(
+            "Failed to rename Guacamole connection {} of node {}: {}",
+            connection_id, id, e
+        );
+    }
+
+    state.publish(NodeEvent::Renamed {
+        node_id: id,
+        name: node.name.clone(),
+    });
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::NodeRenamed,
+        id,
+        json!({ "from": previous.name, "to": node.name }),
+    )
+    .await;
+    info!(
+        "Renamed node {} from {} to {}",
+        id, previous.name, node.name
+    );
+    ApiResponse::ok(node).into_response()
+}
+
+/// GET /node/{id}/status - Report the live state of a node's VM
+#[utoipa::path(
+    get,
+    path = "/node/{id}/status",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<NodeStatusResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_node_status(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    let run_state = match state.instances.get(id).await {
+        Some(instance) => {
+            let status = qemu::query_status(&*instance.lock().await).await;
+            match status {
+                Ok(run_state) => run_state,
+                Err(QemuError::NodeNotRunning) => "stopped".to_string(),
+                Err(e) => return internal_error("Failed to query node status", e),
+            }
+        }
+        None => "stopped".to_string(),
+    };
+
+    ApiResponse::ok(NodeStatusResponse {
+        node_id: id,
+        status: node.status,
+        run_state,
+        vnc_port: node.vnc_port,
+    })
+    .into_response()
+}
+
+/// GET /node/{id}/disk - Disk space used by the node's instance overlay
+#[utoipa::path(
+    get,
+    path = "/node/{id}/disk",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<qemu::DiskUsage>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_node_disk_usage(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    match qemu::overlay_disk_usage(&node, &state).await {
+        Ok(Some(usage)) => ApiResponse::ok(usage).into_response(),
+        Ok(None) => ApiError::NotFound(format!("Node {} has no overlay yet", id)).into_response(),
+        Err(e) => internal_error("Failed to measure overlay", e),
+    }
+}
+
+/// GET /node/{id}/blocks - Drives of a running VM with their images and I/O status
+#[utoipa::path(
+    get,
+    path = "/node/{id}/blocks",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Vec<qemu::BlockDevice>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_node_blocks(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let blocks = qemu::query_block(&*instance.lock().await).await;
+    match blocks {
+        Ok(blocks) => ApiResponse::ok(blocks).into_response(),
+        Err(QemuError::NodeNotRunning) => {
+            ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+        }
+        Err(e) => internal_error("Failed to query block devices", e),
+    }
+}
+
+/// GET /node/{id}/spice - Address of the node's SPICE display for an external client
+///
+/// Guacamole has no SPICE support, so clients such as `remote-viewer`
+/// connect to this address directly.
+#[utoipa::path(
+    get,
+    path = "/node/{id}/spice",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<SpiceInfoResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_spice_info(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let info = qemu::get_spice_info(&*instance.lock().await);
+    match info {
+        Ok((host, port)) => ApiResponse::ok(SpiceInfoResponse {
+            node_id: id,
+            host,
+            port,
+        })
+        .into_response(),
+        Err(e) => ApiError::Conflict(e.to_string()).into_response(),
+    }
+}
+
+/// GET /node/{id}/console/log - Return the captured serial console output
+///
+/// Only the last `MAX_CONSOLE_LOG_BYTES` are returned for long-running nodes.
+#[utoipa::path(
+    get,
+    path = "/node/{id}/console/log",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, content_type = "text/plain", body = String),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_console_log(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    let log_path = match node.get_console_log_path(&state) {
+        Ok(path) => path,
+        Err(e) => return internal_error("Failed to resolve console log", e),
+    };
+
+    let contents = match read_log_tail(&log_path, MAX_CONSOLE_LOG_BYTES).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return ApiError::NotFound(format!("Node {} has no console log yet", id))
+                .into_response();
+        }
+        Err(e) => return internal_error("Failed to read console log", e),
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        String::from_utf8_lossy(&contents).into_owned(),
+    )
+        .into_response()
+}
+
+/// Read at most the last `max_bytes` of a file
+async fn read_log_tail(path: &std::path::Path, max_bytes: usize) -> std::io::Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    let start = len.saturating_sub(max_bytes as u64);
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let mut contents = Vec::with_capacity((len - start) as usize);
+    file.take(max_bytes as u64)
+        .read_to_end(&mut contents)
+        .await?;
+    Ok(contents)
+}
+
+/// POST /node/{id}/run - Start a node
+#[utoipa::path(
+    post,
+    path = "/node/{id}/run",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn run_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    detached(run_stopped_node(state, actor, id)).await
+}
+
+async fn run_stopped_node(state: AppState, actor: Actor, id: Uuid) -> Response {
+    // Held until the instance is tracked, so a concurrent run sees it running
+    let Some(_starting) = state.instances.claim_start(id) else {
+        return ApiError::Conflict(format!("Node {} is already starting", id)).into_response();
+    };
+
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    if node.status == NodeStatus::Running || state.instances.contains(id).await {
+        return ApiError::Conflict(format!("Node {} is already running", id)).into_response();
+    }
+
+    match launch_node(&state, node).await {
+        Ok(node) => {
+            audit::record(&state, &actor, AuditAction::NodeStarted, id, json!({})).await;
+            ApiResponse::ok(node).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+/// POST /node/{id}/restart - Reboot a running node
+///
+/// By default the guest gets a `system_reset`, which keeps the QEMU process,
+/// its monitor socket, VNC server and Guacamole connection. With `?hard=true`
+/// the process is stopped and spawned again from the same overlay instead;
+/// the monitor socket is recreated but VNC starts disabled.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/restart",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id"), RestartQuery),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn restart_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    Query(query): Query<RestartQuery>,
+) -> impl IntoResponse {
+    if !query.hard {
+        let instance = match running_instance(&state, id).await {
+            Ok(instance) => instance,
+            Err(response) => return response,
+        };
+
+        let restarted = qemu::restart_node(&*instance.lock().await).await;
+        return match restarted {
+            Ok(()) => match find_node(&state, id).await {
+                Ok(node) => {
+                    audit::record(
+                        &state,
+                        &actor,
+                        AuditAction::NodeRestarted,
+                        id,
+                        json!({ "hard": false }),
+                    )
+                    .await;
+                    info!("Node {} restarted", id);
+                    ApiResponse::ok(node).into_response()
+                }
+                Err(response) => response,
+            },
+            Err(QemuError::NodeNotRunning) => {
+                ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+            }
+            Err(e) => internal_error("Failed to restart node", e),
+        };
+    }
+
+    detached(hard_restart_node(state, actor, id)).await
+}
+
+/// Stop a node's QEMU process and spawn a new one from the same overlay
+async fn hard_restart_node(state: AppState, actor: Actor, id: Uuid) -> Response {
+    // Keep a run request from starting the node between the stop and relaunch
+    let Some(_starting) = state.instances.claim_start(id) else {
+        return ApiError::Conflict(format!("Node {} is already starting", id)).into_response();
+    };
+
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    match stop_tracked_instance(&state, id).await {
+        Ok(true) => state.publish(NodeEvent::Stopped { node_id: id }),
+        Ok(false) => {
+            return ApiError::Conflict(format!("Node {} is not running", id)).into_response();
+        }
+        Err(response) => return response,
+    }
+
+    match launch_node(&state, node).await {
+        Ok(node) => {
+            audit::record(
+                &state,
+                &actor,
+                AuditAction::NodeRestarted,
+                id,
+                json!({ "hard": true }),
+            )
+            .await;
+            info!("Node {} restarted with a new process", id);
+            ApiResponse::ok(node).into_response()
+        }
+        Err(response) => {
+            // The old process is gone, so don't leave the node marked running
+            if let Err(e) = sqlx::query(
+                "UPDATE nodes SET status = $1, vnc_port = NULL, paused = FALSE, spice_port = NULL WHERE id = $2",
+            )
+            .bind(NodeStatus::Stopped)
+            .bind(id)
+            .execute(&state.db)
+            .await
+            {
+                error!("Failed to mark node {} stopped: {}", id, e);
+            }
+            response
+        }
+    }
+}
+
+/// Spawn a node's VM, track it and record it as running
+async fn launch_node(state: &AppState, node: Node) -> Result<Node, Response> {
+    let id = node.id;
+    let chain = match qemu::get_image_chain(node.image_id, state).await {
+        Ok(chain) => chain,
+        Err(e) => return Err(internal_error("Failed to load image ancestry", e)),
+    };
+    let Some(image) = chain.last() else {
+        return Err(internal_error(
+            "Failed to load image ancestry",
+            "empty image chain",
+        ));
+    };
+
+    let links: Vec<Link> = sqlx::query_as(&format!(
+        "SELECT {} FROM links WHERE node_a = $1 OR node_b = $1 ORDER BY created_at",
+        LINK_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| internal_error("Failed to load node links", e))?;
+
+    let mut config = match QemuConfig::for_node(&node, state) {
+        Ok(config) => config.with_links(id, &links),
+        Err(e) => return Err(ApiError::Conflict(e.to_string()).into_response()),
+    };
+
+    // Held until the port is recorded below so no concurrent start picks it
+    let spice_claim = if node.spice {
+        match qemu::next_spice_port(state).await {
+            Ok(claim) => {
+                config = config.with_spice(claim.port());
+                Some(claim)
+            }
+            Err(e @ QemuError::SpicePortAllocationFailed) => {
+                return Err(ApiError::Conflict(e.to_string()).into_response());
+            }
+            Err(e) => return Err(internal_error("Failed to allocate SPICE port", e)),
+        }
+    } else {
+        None
+    };
+    let spice_port = config.spice_port.map(i32::from);
+
+    let usage = config.resource_usage();
+    let budget = qemu::resource_budget(state);
+    match state.instances.reserve(id, usage, budget).await {
+        Ok(()) => {}
+        Err(ReservationError::AlreadyReserved) => {
+            return Err(
+                ApiError::Conflict(format!("Node {} is already running", id)).into_response(),
+            );
+        }
+        Err(ReservationError::OverBudget(in_use)) => {
+            return Err(
+                ApiError::Conflict(over_budget_message(id, usage, in_use, budget)).into_response(),
+            );
+        }
+    }
+
+    let mut instance = match qemu::start_node(&node, image, &chain, config, state).await {
+        Ok(instance) => instance,
+        Err(e) => {
+            state.instances.release(id).await;
+            return Err(match e {
+                QemuError::ChecksumMismatch { .. } | QemuError::InvalidConfiguration(_) => {
+                    ApiError::Conflict(e.to_string()).into_response()
+                }
+                e => internal_error("Failed to start node", e),
+            });
+        }
+    };
+
+    let updated: Result<Node, _> = sqlx::query_as(&format!(
+        "UPDATE nodes SET status = $1, vnc_port = NULL, paused = FALSE, spice_port = $2 \
+         WHERE id = $3 RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(NodeStatus::Running)
+    .bind(spice_port)
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+    drop(spice_claim);
+
+    match updated {
+        Ok(node) => {
+            let instance = Arc::new(Mutex::new(instance));
+            state.instances.insert(id, instance.clone()).await;
+            // A QEMU that died before it was tracked sent its SIGCHLD before
+            // the reaper could see it
+            if !matches!(
+                qemu::is_running(&mut *instance.lock().await).await,
+                Ok(true)
+            ) {
+                tokio::spawn(reaper::check_instance(state.clone(), id, instance));
+            }
+            state.publish(NodeEvent::Started { node_id: id });
+            info!("Node {} is running", id);
+            Ok(node)
+        }
+        Err(e) => {
+            // Don't leave a VM running that the database doesn't know about
+            if let Err(kill_err) = qemu::kill_node(&mut instance).await {
+                error!(
+                    "Failed to kill node {} after failed start: {}",
+                    id, kill_err
+                );
+            }
+            state.instances.release(id).await;
+            Err(internal_error("Failed to update node status", e))
+        }
+    }
+}
+
+/// Explain why starting a node would exceed the host-wide resource budget
+fn over_budget_message(
+    id: Uuid,
+    needed: ResourceUsage,
+    in_use: ResourceUsage,
+    budget: ResourceBudget,
+) -> String {
+    let limit =
+        |max: Option<u64>| max.map_or_else(|| "unlimited".to_string(), |max| max.to_string());
+    format!(
+        "Node {} needs {} MB and {} cores, but other running nodes already use \
+         {} of {} MB and {} of {} cores",
+        id,
+        needed.memory_mb,
+        needed.cpu_cores,
+        in_use.memory_mb,
+        limit(budget.memory_mb),
+        in_use.cpu_cores,
+        limit(budget.cpu_cores)
+    )
+}
+
+/// POST /node/{id}/stop - Stop a node
+#[utoipa::path(
+    post,
+    path = "/node/{id}/stop",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn stop_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    let stopped = match stop_tracked_instance(&state, id).await {
+        Ok(stopped) => stopped,
+        Err(response) => return response,
+    };
+
+    if !stopped {
+        if node.status != NodeStatus::Running {
+            return ApiError::Conflict(format!("Node {} is not running", id)).into_response();
+        }
+        // Nothing is tracked for this node (e.g. after a backend restart),
+        // so the database is out of date and only needs correcting
+        warn!(
+            "Node {} is marked running but has no process, marking it stopped",
+            id
+        );
+    }
+
+    let updated: Result<Node, _> = sqlx::query_as(&format!(
+        "UPDATE nodes SET status = $1, vnc_port = NULL, paused = FALSE, spice_port = NULL WHERE id = $2 RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(NodeStatus::Stopped)
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+
+    match updated {
+        Ok(node) => {
+            state.publish(NodeEvent::Stopped { node_id: id });
+            audit::record(&state, &actor, AuditAction::NodeStopped, id, json!({})).await;
+            info!("Node {} stopped", id);
+            ApiResponse::ok(node).into_response()
+        }
+        Err(e) => internal_error("Failed to update node status", e),
+    }
+}
+
+/// DELETE /node/{id} - Stop a node if needed and remove it along with its overlay
+#[utoipa::path(
+    delete,
+    path = "/node/{id}",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn delete_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    match stop_tracked_instance(&state, id).await {
+        Ok(true) => state.publish(NodeEvent::Stopped { node_id: id }),
+        Ok(false) => {}
+        Err(response) => return response,
+    }
+
+    if let Some(connection_id) = &node.guacamole_connection_id {
+        // A leaked connection is easier to clean up than a node that can't be deleted
+        match GuacamoleConnection::delete_by_id(&state.config.guacamole, connection_id).await {
+            Ok(()) => {
+                audit::record(
+                    &state,
+                    &actor,
+                    AuditAction::ConnectionDeleted,
+                    connection_id,
+                    json!({ "node_id": id }),
+                )
+                .await
+            }
+            Err(e) => warn!(
+                "Failed to delete Guacamole connection {} of node {}: {}",
+                connection_id, id, e
+            ),
+        }
+    }
+
+    let overlay_path = match node.get_instance_overlay_path(&state) {
+        Ok(path) => path,
+        Err(e) => return internal_error("Failed to resolve instance overlay", e),
+    };
+    if let Err(e) = qemu::delete_overlay(&overlay_path).await {
+        return internal_error("Failed to delete instance overlay", e);
+    }
+    if let Err(e) = qemu::delete_uefi_vars(&node, &state).await {
+        return internal_error("Failed to delete UEFI variable store", e);
+    }
+
+    match node.get_console_log_path(&state) {
+        Ok(log_path) => match tokio::fs::remove_file(&log_path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove console log {}: {}", log_path.display(), e),
+        },
+        Err(e) => warn!("Failed to resolve console log of node {}: {}", id, e),
+    }
+
+    if let Err(e) = sqlx::query("DELETE FROM nodes WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await
+    {
+        return internal_error("Failed to delete node", e);
+    }
+
+    state.publish(NodeEvent::Deleted { node_id: id });
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::NodeDeleted,
+        id,
+        json!({ "name": node.name }),
+    )
+    .await;
+    info!("Deleted node {} ({})", node.name, id);
+    ApiResponse::ok(node).into_response()
+}
+
+/// POST /node/{id}/clone - Create a new node with a copy of a stopped node's disk
+///
+/// The clone gets the source's image and settings but none of its links.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/clone",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = CloneNodeRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn clone_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CloneNodeRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = validate::name("name", &payload.name) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let source = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    if state.instances.contains(id).await || source.status == NodeStatus::Running {
+        return ApiError::Conflict(format!("Node {} must be stopped before cloning", id))
+            .into_response();
+    }
+
+    let chain = match qemu::get_image_chain(source.image_id, &state).await {
+        Ok(chain) => chain,
+        Err(e) => return internal_error("Failed to load image ancestry", e),
+    };
+    let Some(image) = chain.last() else {
+        return internal_error("Failed to load image ancestry", "empty image chain");
+    };
+
+    let clone_id = Uuid::now_v7();
+    let result: Result<Node, _> = sqlx::query_as(&format!(
+        "INSERT INTO nodes (id, name, status, image_id, instance_overlay_path, memory_mb, cpu_cores, \
+         network_mode, port_forwards, firmware, spice, netem) \
+         SELECT $1, $2, $3, image_id, $4, memory_mb, cpu_cores, network_mode, port_forwards, \
+         firmware, spice, netem FROM nodes WHERE id = $5 RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(clone_id)
+    .bind(&payload.name)
+    .bind(NodeStatus::Stopped)
+    .bind(format!("{}.qcow2", clone_id))
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+    let clone = match result {
+        Ok(node) => node,
+        // The source was deleted since it was looked up
+        Err(sqlx::Error::RowNotFound) => {
+            return ApiError::NotFound(format!("Node {} not found", id)).into_response();
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return ApiError::Conflict(format!("A node named `{}` already exists", payload.name))
+                .into_response();
+        }
+        Err(e) => return internal_error("Failed to create node", e),
+    };
+
+    if let Err(e) = qemu::clone_node(&source, &clone, image, &state).await {
+        // Don't leave behind a node without a disk
+        if let Ok(path) = clone.get_instance_overlay_path(&state)
+            && let Err(cleanup_err) = qemu::delete_overlay(&path).await
+        {
+            warn!(
+                "Failed to remove overlay of node {}: {}",
+                clone_id, cleanup_err
+            );
+        }
+        if let Err(cleanup_err) = qemu::delete_uefi_vars(&clone, &state).await {
+            warn!(
+                "Failed to remove UEFI variable store of node {}: {}",
+                clone_id, cleanup_err
+            );
+        }
+        if let Err(cleanup_err) = sqlx::query("DELETE FROM nodes WHERE id = $1")
+            .bind(clone_id)
+            .execute(&state.db)
+            .await
+        {
+            warn!("Failed to delete node {}: {}", clone_id, cleanup_err);
+        }
+        return match e {
+            QemuError::NodeAlreadyRunning => {
+                ApiError::Conflict(format!("Node {} must be stopped before cloning", id))
+                    .into_response()
+            }
+            e => internal_error("Failed to clone node disk", e),
+        };
+    }
+
+    state.publish(NodeEvent::Created { node_id: clone_id });
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::NodeCreated,
+        clone_id,
+        json!({ "name": clone.name, "image_id": clone.image_id, "cloned_from": id }),
+    )
+    .await;
+    info!("Cloned node {} into {} ({})", id, clone.name, clone_id);
+    let mut chains = HashMap::from([(source.image_id, chain.clone())]);
+    match with_image(&state, clone, &mut chains).await {
+        Ok(node) => ApiResponse::ok(node)
+            .with_status(StatusCode::CREATED)
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// POST /node/{id}/commit - Save a stopped node's disk as a new layered image
+///
+/// The image is written to `IMAGE_DIR` with the node's image as its parent,
+/// so nodes created from it start with everything the node had configured.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/commit",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = CommitNodeRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<Image>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn commit_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CommitNodeRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = validate::name("name", &payload.name) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    if state.instances.contains(id).await || node.status == NodeStatus::Running {
+        return ApiError::Conflict(format!("Node {} must be stopped before committing", id))
+            .into_response();
+    }
+
+    let image = match find_image(&state, node.image_id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+
+    let image_id = Uuid::now_v7();
+    let mut committed = Image {
+        id: image_id,
+        name: payload.name,
+        path: format!("{}.qcow2", image_id),
+        parent_id: Some(image.id),
+        description: payload.description,
+        sha256: None,
+    };
+
+    if let Err(e) = qemu::commit_node(&node, &image, &committed, &state).await {
+        return match e {
+            QemuError::NodeAlreadyRunning => {
+                ApiError::Conflict(format!("Node {} must be stopped before committing", id))
+                    .into_response()
+            }
+            QemuError::InvalidConfiguration(message) => ApiError::Conflict(message).into_response(),
+            e => internal_error("Failed to commit node disk", e),
+        };
+    }
+
+    let inserted = match committed.get_full_path(&state) {
+        Ok(path) => match qemu::file_sha256(&path).await {
+            Ok(checksum) => {
+                committed.sha256 = Some(checksum);
+                insert_image(&state, &committed).await
+            }
+            Err(e) => Err(internal_error("Failed to checksum image", e)),
+        },
+        Err(e) => Err(internal_error("Failed to resolve image path", e)),
+    };
+
+    let image: Image = match inserted {
+        Ok(image) => image,
+        Err(response) => {
+            // Don't leave behind an image file nothing refers to
+            if let Ok(path) = committed.get_full_path(&state)
+                && let Err(cleanup_err) = tokio::fs::remove_file(&path).await
+            {
+                warn!(
+                    "Failed to remove image file {}: {}",
+                    path.display(),
+                    cleanup_err
+                );
+            }
+            return response;
+        }
+    };
+
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::NodeCommitted,
+        id,
+        json!({ "image_id": image.id, "name": image.name }),
+    )
+    .await;
+    info!(
+        "Committed node {} into image {} ({})",
+        id, image.name, image.id
+    );
+    ApiResponse::ok(image)
+        .with_status(StatusCode::CREATED)
+        .into_response()
+}
+
+/// POST /node/{id}/wipe - Wipe a node
+#[utoipa::path(
+    post,
+    path = "/node/{id}/wipe",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn wipe_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    if state.instances.contains(id).await {
+        return ApiError::Conflict(format!("Node {} must be stopped before wiping", id))
+            .into_response();
+    }
+
+    let chain = match qemu::get_image_chain(node.image_id, &state).await {
+        Ok(chain) => chain,
+        Err(e) => return internal_error("Failed to load image ancestry", e),
+    };
+    let Some(image) = chain.last() else {
+        return internal_error("Failed to load image ancestry", "empty image chain");
+    };
+
+    match qemu::wipe_node(&node, image, &state).await {
+        Ok(()) => {
+            state.publish(NodeEvent::Wiped { node_id: id });
+            audit::record(&state, &actor, AuditAction::NodeWiped, id, json!({})).await;
+            ApiResponse::ok(node).into_response()
+        }
+        Err(QemuError::NodeAlreadyRunning) => {
+            ApiError::Conflict(format!("Node {} must be stopped before wiping", id)).into_response()
+        }
+        Err(e) => internal_error("Failed to wipe node", e),
+    }
+}
+
+/// POST /node/{id}/pause - Freeze a running VM's CPUs
+#[utoipa::path(
+    post,
+    path = "/node/{id}/pause",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn pause_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_paused(&state, &actor, id, true).await
+}
+
+/// POST /node/{id}/resume - Unfreeze a paused VM
+#[utoipa::path(
+    post,
+    path = "/node/{id}/resume",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn resume_node(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_paused(&state, &actor, id, false).await
+}
+
+async fn set_paused(state: &AppState, actor: &Actor, id: Uuid, paused: bool) -> Response {
+    let instance = match running_instance(state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let result = {
+        let guard = instance.lock().await;
+        if paused {
+            qemu::pause_node(&guard).await
+        } else {
+            qemu::resume_node(&guard).await
+        }
+    };
+    if let Err(e) = result {
+        return match e {
+            QemuError::NodeNotRunning => {
+                ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+            }
+            e if paused => internal_error("Failed to pause node", e),
+            e => internal_error("Failed to resume node", e),
+        };
+    }
+
+    let updated: Result<Node, _> = sqlx::query_as(&format!(
+        "UPDATE nodes SET paused = $1 WHERE id = $2 RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(paused)
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+
+    match updated {
+        Ok(node) => {
+            let action = if paused {
+                AuditAction::NodePaused
+            } else {
+                AuditAction::NodeResumed
+            };
+            audit::record(state, actor, action, id, json!({})).await;
+            info!("Node {} {}", id, if paused { "paused" } else { "resumed" });
+            ApiResponse::ok(node).into_response()
+        }
+        Err(e) => internal_error("Failed to update node status", e),
+    }
+}
+
+/// POST /node/{id}/memory - Shrink or grow a running VM's memory through its balloon
+///
+/// The target can't exceed the memory the node was started with, so growing
+/// only gives back memory taken by an earlier shrink.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/memory",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = SetMemoryRequest,
+    responses(
+        (status = OK, body = ApiResponse<qemu::BalloonInfo>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn set_node_memory(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetMemoryRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = qemu::validate_resources(Some(payload.memory_mb), None, &state) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let balloon = qemu::set_balloon(&*instance.lock().await, u64::from(payload.memory_mb)).await;
+    match balloon {
+        Ok(balloon) => {
+            info!("Set memory of node {} to {} MB", id, balloon.target_mb);
+            ApiResponse::ok(balloon).into_response()
+        }
+        Err(QemuError::InvalidConfiguration(message)) => {
+            ApiError::Validation(message).into_response()
+        }
+        Err(QemuError::NodeNotRunning) => {
+            ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+        }
+        Err(e) => internal_error("Failed to adjust node memory", e),
+    }
+}
+
+/// POST /node/{id}/cpu - Hotplug one more vCPU into a running VM
+///
+/// The extra CPU counts against `MAX_TOTAL_CORES` and is gone after the node
+/// restarts; the node's configured `cpu_cores` is left unchanged.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/cpu",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<CpuCountResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn add_node_cpu(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+    let mut instance = instance.lock().await;
+
+    let current = ResourceUsage {
+        memory_mb: instance.memory_mb,
+        cpu_cores: u64::from(instance.cpu_cores),
+    };
+    let grown = ResourceUsage {
+        cpu_cores: current.cpu_cores + 1,
+        ..current
+    };
+    let budget = qemu::resource_budget(&state);
+    if let Err(in_use) = state.instances.resize(id, grown, budget).await {
+        return ApiError::Conflict(over_budget_message(id, grown, in_use, budget)).into_response();
+    }
+
+    match qemu::add_cpu(&mut instance).await {
+        Ok(cpu_cores) => {
+            info!("Node {} now has {} CPUs", id, cpu_cores);
+            ApiResponse::ok(CpuCountResponse {
+                cpu_cores,
+                max_cpus: instance.max_cpus,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            // Shrinking back always fits, so this only restores the old claim
+            let _ = state.instances.resize(id, current, budget).await;
+            match e {
+                QemuError::InvalidConfiguration(message) => {
+                    ApiError::Conflict(message).into_response()
+                }
+                QemuError::NodeNotRunning => {
+                    ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+                }
+                e => internal_error("Failed to add CPU", e),
+            }
+        }
+    }
+}
+
+/// POST /node/{id}/cdrom - Insert an ISO from `ISO_DIR` into a running VM
+#[utoipa::path(
+    post,
+    path = "/node/{id}/cdrom",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = AttachCdromRequest,
+    responses(
+        (status = OK, body = ApiResponse<NoData>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn attach_cdrom(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AttachCdromRequest>,
+) -> impl IntoResponse {
+    let iso_path = match models::resolve_iso_path(&state, &payload.path) {
+        Some(Ok(path)) if path.is_file() => path,
+        Some(Ok(_)) => {
+            return ApiError::NotFound(format!("ISO {} does not exist", payload.path))
+                .into_response();
+        }
+        Some(Err(e)) => return ApiError::Validation(e.to_string()).into_response(),
+        None => {
+            return ApiError::Conflict("Attaching ISOs requires ISO_DIR to be set".into())
+                .into_response();
+        }
+    };
+
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let attached = qemu::attach_cdrom(&mut *instance.lock().await, &iso_path).await;
+    match attached {
+        Ok(()) => {
+            info!("Inserted ISO {} into node {}", payload.path, id);
+            ApiResponse::ok(NoData).into_response()
+        }
+        Err(e) => instance_error_response(id, "Failed to insert ISO", e),
+    }
+}
+
+/// DELETE /node/{id}/cdrom - Eject the ISO from a running VM
+#[utoipa::path(
+    delete,
+    path = "/node/{id}/cdrom",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<NoData>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn detach_cdrom(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let detached = qemu::detach_cdrom(&mut *instance.lock().await).await;
+    match detached {
+        Ok(()) => {
+            info!("Ejected ISO from node {}", id);
+            ApiResponse::ok(NoData).into_response()
+        }
+        Err(e) => instance_error_response(id, "Failed to eject ISO", e),
+    }
+}
+
+/// POST /node/{id}/capture/start - Capture a running node's bridged traffic
+///
+/// The capture is written to `{id}.pcap` in CAPTURE_DIR, replacing any
+/// earlier capture of the node, and runs until stopped or the node stops.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/capture/start",
+    tag = "capture",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<NoData>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn start_capture(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let path = match models::capture_path(&state, id) {
+        Some(Ok(path)) => path,
+        Some(Err(e)) => return internal_error("Failed to resolve capture path", e),
+        None => {
+            return ApiError::Conflict("Packet capture requires CAPTURE_DIR to be set".into())
+                .into_response();
+        }
+    };
+
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let started = qemu::start_capture(&mut *instance.lock().await, &path).await;
+    match started {
+        Ok(()) => {
+            info!("Started packet capture of node {}", id);
+            ApiResponse::ok(NoData).into_response()
+        }
+        Err(e) => instance_error_response(id, "Failed to start packet capture", e),
+    }
+}
+
+/// POST /node/{id}/capture/stop - Stop a node's packet capture
+#[utoipa::path(
+    post,
+    path = "/node/{id}/capture/stop",
+    tag = "capture",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<NoData>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn stop_capture(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let stopped = qemu::stop_capture(&mut *instance.lock().await).await;
+    match stopped {
+        Ok(_) => {
+            info!("Stopped packet capture of node {}", id);
+            ApiResponse::ok(NoData).into_response()
+        }
+        Err(e) => instance_error_response(id, "Failed to stop packet capture", e),
+    }
+}
+
+/// GET /node/{id}/capture - Download a node's latest packet capture as pcap
+///
+/// A capture that is still running can be downloaded; it holds the packets
+/// seen so far.
+#[utoipa::path(
+    get,
+    path = "/node/{id}/capture",
+    tag = "capture",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, content_type = "application/vnd.tcpdump.pcap", body = Vec<u8>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_capture(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    if let Err(response) = find_node(&state, id).await {
+        return response;
+    }
+    let path = match models::capture_path(&state, id) {
+        Some(Ok(path)) => path,
+        Some(Err(e)) => return internal_error("Failed to resolve capture path", e),
+        None => {
+            return ApiError::Conflict("Packet capture requires CAPTURE_DIR to be set".into())
+                .into_response();
+        }
+    };
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return ApiError::NotFound(format!("Node {} has no packet capture", id))
+                .into_response();
+        }
+        Err(e) => return internal_error("Failed to open packet capture", e),
+    };
+
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.tcpdump.pcap".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.pcap\"", id),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response()
+}
+
+fn instance_error_response(id: Uuid, context: &str, err: QemuError) -> Response {
+    match err {
+        QemuError::InvalidConfiguration(message) => ApiError::Conflict(message).into_response(),
+        QemuError::NodeNotRunning => {
+            ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+        }
+        err => internal_error(context, err),
+    }
+}
+
+/// POST /node/{id}/snapshot - Save the running VM's state under a name
+#[utoipa::path(
+    post,
+    path = "/node/{id}/snapshot",
+    tag = "snapshots",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = CreateSnapshotRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<qemu::SnapshotInfo>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn create_snapshot(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CreateSnapshotRequest>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let snapshots = {
+        let guard = instance.lock().await;
+        match qemu::save_snapshot(&guard, &payload.name).await {
+            Ok(()) => qemu::list_snapshots(&guard).await,
+            Err(e) => Err(e),
+        }
+    };
+
+    match snapshots {
+        Ok(snapshots) => match snapshots.into_iter().find(|s| s.name == payload.name) {
+            Some(snapshot) => {
+                audit::record(
+                    &state,
+                    &actor,
+                    AuditAction::SnapshotCreated,
+                    id,
+                    json!({ "name": snapshot.name }),
+                )
+                .await;
+                ApiResponse::ok(snapshot)
+                    .with_status(StatusCode::CREATED)
+                    .into_response()
+            }
+            None => internal_error("Failed to save snapshot", "snapshot missing after save"),
+        },
+        Err(e) => snapshot_error_response(id, "Failed to save snapshot", e),
+    }
+}
+
+/// GET /node/{id}/snapshot - List the snapshots of a running VM
+#[utoipa::path(
+    get,
+    path = "/node/{id}/snapshot",
+    tag = "snapshots",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Vec<qemu::SnapshotInfo>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn list_snapshots(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let snapshots = qemu::list_snapshots(&*instance.lock().await).await;
+    match snapshots {
+        Ok(snapshots) => ApiResponse::ok(snapshots).into_response(),
+        Err(e) => snapshot_error_response(id, "Failed to list snapshots", e),
+    }
+}
+
+/// POST /node/{id}/snapshot/{name}/restore - Roll a running VM back to a snapshot
+#[utoipa::path(
+    post,
+    path = "/node/{id}/snapshot/{name}/restore",
+    tag = "snapshots",
+    params(("id" = Uuid, Path, description = "Node id"), ("name" = String, Path, description = "Snapshot name")),
+    responses(
+        (status = OK, body = ApiResponse<NoData>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn restore_snapshot(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path((id, name)): Path<(Uuid, String)>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let restored = qemu::restore_snapshot(&*instance.lock().await, &name).await;
+    match restored {
+        Ok(()) => {
+            audit::record(
+                &state,
+                &actor,
+                AuditAction::SnapshotRestored,
+                id,
+                json!({ "name": name }),
+            )
+            .await;
+            ApiResponse::ok(NoData).into_response()
+        }
+        Err(e) => snapshot_error_response(id, "Failed to restore snapshot", e),
+    }
+}
+
+/// POST /node/{id}/monitor - Run a raw QMP command on a running VM (admin only)
+#[utoipa::path(
+    post,
+    path = "/node/{id}/monitor",
+    tag = "nodes",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = MonitorCommandRequest,
+    responses(
+        (status = OK, body = ApiResponse<serde_json::Value>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn run_monitor_command(
+    State(state): State<AppState>,
+    _admin: Admin,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<MonitorCommandRequest>,
+) -> impl IntoResponse {
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+
+    let result = qemu::monitor_command(
+        &*instance.lock().await,
+        &payload.command,
+        payload.arguments.clone(),
+    )
+    .await;
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::MonitorCommand,
+        id,
+        json!({
+            "command": payload.command,
+            "arguments": payload.arguments,
+            "succeeded": result.is_ok(),
+        }),
+    )
+    .await;
+
+    match result {
+        Ok(response) => ApiResponse::ok(response).into_response(),
+        Err(QemuError::NodeNotRunning) => {
+            ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+        }
+        Err(e) => internal_error("Monitor command failed", e),
+    }
+}
+
+fn snapshot_error_response(id: Uuid, context: &str, err: QemuError) -> Response {
+    match err {
+        QemuError::InvalidSnapshotName(_) => ApiError::Validation(err.to_string()).into_response(),
+        QemuError::SnapshotNotFound(_) => ApiError::NotFound(err.to_string()).into_response(),
+        QemuError::NodeNotRunning => {
+            ApiError::Conflict(format!("Node {} is not running", id)).into_response()
+        }
+        err => internal_error(context, err),
+    }
+}
+
+/// POST /link - Connect two nodes with a point-to-point link
+///
+/// The link is realized as an extra NIC on each node the next time it
+/// starts, shaped by the link's latency and bandwidth; running nodes must be
+/// restarted to pick it up.
+#[utoipa::path(
+    post,
+    path = "/link",
+    tag = "links",
+    request_body = CreateLinkRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<Link>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn create_link(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateLinkRequest>,
+) -> impl IntoResponse {
+    if payload.node_a == payload.node_b {
+        return ApiError::Validation("A link must connect two different nodes".to_string())
+            .into_response();
+    }
+    if payload.bandwidth_kbps == Some(0) {
+        return ApiError::Validation("bandwidth_kbps must be greater than zero".to_string())
+            .into_response();
+    }
+    for node_id in [payload.node_a, payload.node_b] {
+        if let Err(response) = find_node(&state, node_id).await {
+            return response;
+        }
+    }
 
-/// POST /node - Create a new node
-pub async fn create_node(
+    let result: Result<Link, _> = sqlx::query_as(&format!(
+        "INSERT INTO links (id, node_a, node_b, latency_ms, bandwidth_kbps) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING {}",
+        LINK_COLUMNS
+    ))
+    .bind(Uuid::now_v7())
+    .bind(payload.node_a)
+    .bind(payload.node_b)
+    .bind(payload.latency_ms.map(|v| v as i32))
+    .bind(payload.bandwidth_kbps.map(|v| v as i32))
+    .fetch_one(&state.db)
+    .await;
+
+    match result {
+        Ok(link) => {
+            info!(
+                "Linked nodes {} and {} ({})",
+                link.node_a, link.node_b, link.id
+            );
+            ApiResponse::ok(link)
+                .with_status(StatusCode::CREATED)
+                .into_response()
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => ApiError::Conflict(format!(
+            "Nodes {} and {} are already linked",
+            payload.node_a, payload.node_b
+        ))
+        .into_response(),
+        // A node may have been deleted since it was looked up
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            ApiError::NotFound("Node not found".to_string()).into_response()
+        }
+        Err(e) => internal_error("Failed to create link", e),
+    }
+}
+
+/// GET /link - List all links
+#[utoipa::path(
+    get,
+    path = "/link",
+    tag = "links",
+    responses(
+        (status = OK, body = ApiResponse<Vec<Link>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn list_links(State(state): State<AppState>) -> impl IntoResponse {
+    let links: Result<Vec<Link>, _> = sqlx::query_as(&format!(
+        "SELECT {} FROM links ORDER BY created_at",
+        LINK_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await;
+
+    match links {
+        Ok(links) => ApiResponse::ok(links).into_response(),
+        Err(e) => internal_error("Failed to list links", e),
+    }
+}
+
+/// GET /link/{id} - Get a link
+#[utoipa::path(
+    get,
+    path = "/link/{id}",
+    tag = "links",
+    params(("id" = Uuid, Path, description = "Link id")),
+    responses(
+        (status = OK, body = ApiResponse<Link>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_link(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    match find_link(&state, id).await {
+        Ok(link) => ApiResponse::ok(link).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// DELETE /link/{id} - Remove a link; running nodes keep the NIC until restarted
+#[utoipa::path(
+    delete,
+    path = "/link/{id}",
+    tag = "links",
+    params(("id" = Uuid, Path, description = "Link id")),
+    responses(
+        (status = OK, body = ApiResponse<Link>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn delete_link(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let link = match find_link(&state, id).await {
+        Ok(link) => link,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = sqlx::query("DELETE FROM links WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await
+    {
+        return internal_error("Failed to delete link", e);
+    }
+
+    info!("Deleted link {}", id);
+    ApiResponse::ok(link).into_response()
+}
+
+/// POST /image - Register an image file within IMAGE_DIR
+#[utoipa::path(
+    post,
+    path = "/image",
+    tag = "images",
+    request_body = CreateImageRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<Image>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn create_image(
     State(state): State<AppState>,
-    Json(payload): Json<CreateNodeRequest>,
+    Json(payload): Json<CreateImageRequest>,
 ) -> impl IntoResponse {
-    Json(ApiResponse::<()>::error("Not yet implemented".into()))
+    if let Err(e) = validate::name("name", &payload.name)
+        .and_then(|()| validate::relative_path("path", &payload.path))
+    {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+
+    if let Some(parent_id) = payload.parent_id
+        && let Err(response) = find_image(&state, parent_id).await
+    {
+        return response;
+    }
+
+    let mut image = Image {
+        id: Uuid::now_v7(),
+        name: payload.name,
+        path: payload.path,
+        parent_id: payload.parent_id,
+        description: payload.description,
+        sha256: None,
+    };
+
+    let path = match image.get_full_path(&state) {
+        Ok(path) if path.is_file() => path,
+        Ok(path) => {
+            return ApiError::Validation(format!("Image file {} does not exist", path.display()))
+                .into_response();
+        }
+        Err(e) => return ApiError::Validation(e.to_string()).into_response(),
+    };
+
+    let checksum = match qemu::file_sha256(&path).await {
+        Ok(checksum) => checksum,
+        Err(e) => return internal_error("Failed to checksum image", e),
+    };
+    if let Some(expected) = &payload.sha256
+        && !expected.eq_ignore_ascii_case(&checksum)
+    {
+        return ApiError::Validation(format!(
+            "Image file {} has SHA-256 {}, expected {}",
+            path.display(),
+            checksum,
+            expected
+        ))
+        .into_response();
+    }
+    image.sha256 = Some(checksum);
+
+    match insert_image(&state, &image).await {
+        Ok(image) => {
+            info!("Registered image {} ({})", image.name, image.id);
+            ApiResponse::ok(image)
+                .with_status(StatusCode::CREATED)
+                .into_response()
+        }
+        Err(response) => response,
+    }
 }
 
-/// GET /node - List all nodes
-pub async fn list_nodes(State(state): State<AppState>) -> impl IntoResponse {
-    Json(ApiResponse::<()>::error("Not yet implemented".into()))
+/// POST /image/import - Download a qcow2 image into IMAGE_DIR and register it
+///
+/// The file is streamed to disk, checked with `qemu-img info` and only then
+/// recorded. A failed or abandoned import removes what was downloaded.
+#[utoipa::path(
+    post,
+    path = "/image/import",
+    tag = "images",
+    request_body = ImportImageRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<ImportedImage>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn import_image(
+    State(state): State<AppState>,
+    Json(payload): Json<ImportImageRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = validate::name("name", &payload.name) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+    let url = match download::parse_url(&payload.url) {
+        Ok(url) => url,
+        Err(e) => return ApiError::Validation(e.to_string()).into_response(),
+    };
+
+    if let Some(parent_id) = payload.parent_id
+        && let Err(response) = find_image(&state, parent_id).await
+    {
+        return response;
+    }
+
+    let image_id = Uuid::now_v7();
+    let image = Image {
+        id: image_id,
+        name: payload.name,
+        path: format!("{}.qcow2", image_id),
+        parent_id: payload.parent_id,
+        description: payload.description,
+        sha256: None,
+    };
+    let path = match image.get_full_path(&state) {
+        Ok(path) => path,
+        Err(e) => return internal_error("Failed to resolve image path", e),
+    };
+    let partial = PartialFile::new(path.clone());
+
+    info!("Importing image {} from {}", image.name, url);
+    let size_bytes = match download::download_to(&url, &path).await {
+        Ok(size) => size,
+        Err(DownloadError::Io(e)) => return internal_error("Failed to write image", e),
+        Err(e @ DownloadError::Timeout) => {
+            return ApiError::UpstreamTimeout(e.to_string()).into_response();
+        }
+        Err(e) => return ApiError::Upstream(e.to_string()).into_response(),
+    };
+
+    let image = match register_image_file(&state, image, &path).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    partial.keep();
+
+    info!(
+        "Imported image {} ({}), {} bytes",
+        image.name, image.id, size_bytes
+    );
+    ApiResponse::ok(ImportedImage { image, size_bytes })
+        .with_status(StatusCode::CREATED)
+        .into_response()
 }
 
-/// POST /node/{id}/run - Start a node
-pub async fn run_node(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
-    Json(ApiResponse::<()>::error("Not yet implemented".into()))
+/// POST /image/upload - Upload a qcow2 image into IMAGE_DIR and register it
+///
+/// Takes a multipart form with a `file` part plus `name` and optional
+/// `parent_id` and `description` parts. The file is streamed to disk and
+/// refused once it passes `IMAGE_UPLOAD_MAX_MB`.
+#[utoipa::path(
+    post,
+    path = "/image/upload",
+    tag = "images",
+    request_body(content_type = "multipart/form-data", description = "`file`, `name` and optional `parent_id` and `description` parts"),
+    responses(
+        (status = CREATED, body = ApiResponse<ImportedImage>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn upload_image(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let image_id = Uuid::now_v7();
+    let mut image = Image {
+        id: image_id,
+        name: String::new(),
+        path: format!("{}.qcow2", image_id),
+        parent_id: None,
+        description: None,
+        sha256: None,
+    };
+    let path = match image.get_full_path(&state) {
+        Ok(path) => path,
+        Err(e) => return internal_error("Failed to resolve image path", e),
+    };
+    let mut partial = None;
+    let mut size_bytes = 0;
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return multipart_error(e),
+        };
+        match field.name() {
+            Some("file") => {
+                if partial.is_some() {
+                    return ApiError::Validation("Only one `file` part may be sent".to_string())
+                        .into_response();
+                }
+                partial = Some(PartialFile::new(path.clone()));
+                let max_bytes = state.config.qemu.image_upload_max_bytes;
+                let mut file = match tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .await
+                {
+                    Ok(file) => file,
+                    Err(e) => return internal_error("Failed to create image file", e),
+                };
+                loop {
+                    let chunk = match field.chunk().await {
+                        Ok(Some(chunk)) => chunk,
+                        Ok(None) => break,
+                        Err(e) => return multipart_error(e),
+                    };
+                    size_bytes += chunk.len() as u64;
+                    if size_bytes > max_bytes {
+                        return ApiError::PayloadTooLarge(format!(
+                            "Image uploads are limited to {} MB",
+                            max_bytes / (1024 * 1024)
+                        ))
+                        .into_response();
+                    }
+                    if let Err(e) = file.write_all(&chunk).await {
+                        return internal_error("Failed to write image", e);
+                    }
+                }
+                if let Err(e) = file.sync_all().await {
+                    return internal_error("Failed to write image", e);
+                }
+            }
+            Some(name @ ("name" | "parent_id" | "description")) => {
+                let name = name.to_string();
+                let value = match field.text().await {
+                    Ok(value) => value,
+                    Err(e) => return multipart_error(e),
+                };
+                match name.as_str() {
+                    "name" => image.name = value,
+                    "description" => image.description = Some(value),
+                    _ => match value.parse() {
+                        Ok(parent_id) => image.parent_id = Some(parent_id),
+                        Err(_) => {
+                            return ApiError::Validation(format!(
+                                "`parent_id` is not a valid UUID: {}",
+                                value
+                            ))
+                            .into_response();
+                        }
+                    },
+                }
+            }
+            other => {
+                return ApiError::Validation(format!(
+                    "Unexpected form part `{}`",
+                    other.unwrap_or_default()
+                ))
+                .into_response();
+            }
+        }
+    }
+
+    let Some(partial) = partial else {
+        return ApiError::Validation("Missing `file` part".to_string()).into_response();
+    };
+    if let Err(e) = validate::name("name", &image.name) {
+        return ApiError::Validation(e.to_string()).into_response();
+    }
+    if let Some(parent_id) = image.parent_id
+        && let Err(response) = find_image(&state, parent_id).await
+    {
+        return response;
+    }
+
+    let image = match register_image_file(&state, image, &path).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    partial.keep();
+
+    info!(
+        "Uploaded image {} ({}), {} bytes",
+        image.name, image.id, size_bytes
+    );
+    ApiResponse::ok(ImportedImage { image, size_bytes })
+        .with_status(StatusCode::CREATED)
+        .into_response()
 }
 
-/// POST /node/{id}/stop - Stop a node
-pub async fn stop_node(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
-    Json(ApiResponse::<()>::error("Not yet implemented".into()))
+/// Check that a downloaded or uploaded file at `path` is a qcow2 image
+/// consistent with `image.parent_id`, then checksum and record it
+async fn register_image_file(
+    state: &AppState,
+    mut image: Image,
+    path: &std::path::Path,
+) -> Result<Image, Response> {
+    match qemu::check_qcow2(path).await {
+        Ok(None) => {}
+        Ok(Some(backing)) => {
+            let Some(parent_id) = image.parent_id else {
+                return Err(ApiError::Validation(format!(
+                    "The image is an overlay of `{}`; register it with a parent_id",
+                    backing
+                ))
+                .into_response());
+            };
+            let mut chain = qemu::get_image_chain(parent_id, state)
+                .await
+                .map_err(|e| internal_error("Failed to load image ancestry", e))?;
+            chain.push(image.clone());
+            if let Err(e) = qemu::validate_image_chain(&chain, state).await {
+                return Err(ApiError::Validation(e.to_string()).into_response());
+            }
+        }
+        Err(e) => return Err(ApiError::Validation(e.to_string()).into_response()),
+    }
+
+    let checksum = qemu::file_sha256(path)
+        .await
+        .map_err(|e| internal_error("Failed to checksum image", e))?;
+    image.sha256 = Some(checksum);
+    insert_image(state, &image).await
 }
 
-/// POST /node/{id}/wipe - Wipe a node
-pub async fn wipe_node(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
-    Json(ApiResponse::<()>::error("Not yet implemented".into()))
+fn multipart_error(e: MultipartError) -> Response {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::PayloadTooLarge(e.body_text()).into_response()
+    } else {
+        ApiError::Validation(e.body_text()).into_response()
+    }
+}
+
+/// POST /image/{id}/verify - Checksum an image file and compare it to the recorded value
+#[utoipa::path(
+    post,
+    path = "/image/{id}/verify",
+    tag = "images",
+    params(("id" = Uuid, Path, description = "Image id")),
+    responses(
+        (status = OK, body = ApiResponse<ImageVerification>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn verify_image(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let image = match find_image(&state, id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    let path = match image.get_full_path(&state) {
+        Ok(path) => path,
+        Err(e) => return internal_error("Failed to resolve image path", e),
+    };
+
+    match qemu::file_sha256(&path).await {
+        Ok(actual) => ApiResponse::ok(ImageVerification {
+            image_id: id,
+            matches: image
+                .sha256
+                .as_ref()
+                .is_none_or(|expected| *expected == actual),
+            expected: image.sha256,
+            actual,
+        })
+        .into_response(),
+        Err(e) => internal_error("Failed to checksum image", e),
+    }
+}
+
+/// GET /image - List all images
+#[utoipa::path(
+    get,
+    path = "/image",
+    tag = "images",
+    responses(
+        (status = OK, body = ApiResponse<Vec<Image>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn list_images(State(state): State<AppState>) -> impl IntoResponse {
+    let images: Result<Vec<Image>, _> = sqlx::query_as(&format!(
+        "SELECT {} FROM images ORDER BY created_at",
+        IMAGE_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await;
+
+    match images {
+        Ok(images) => ApiResponse::ok(images).into_response(),
+        Err(e) => internal_error("Failed to list images", e),
+    }
+}
+
+/// GET /image/{id} - Get a single image with its ancestry
+#[utoipa::path(
+    get,
+    path = "/image/{id}",
+    tag = "images",
+    params(("id" = Uuid, Path, description = "Image id")),
+    responses(
+        (status = OK, body = ApiResponse<ImageWithAncestors>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn get_image(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+    let chain = match qemu::get_image_chain(id, &state).await {
+        Ok(chain) => chain,
+        Err(QemuError::ImageNotFound(_)) => {
+            return ApiError::NotFound(format!("Image {} not found", id)).into_response();
+        }
+        Err(e) => return internal_error("Failed to load image ancestry", e),
+    };
+
+    match ImageWithAncestors::from_chain(chain) {
+        Some(image) => ApiResponse::ok(image).into_response(),
+        None => internal_error("Failed to load image ancestry", "empty image chain"),
+    }
+}
+
+/// DELETE /image/{id} - Unregister an image that nothing depends on
+#[utoipa::path(
+    delete,
+    path = "/image/{id}",
+    tag = "images",
+    params(("id" = Uuid, Path, description = "Image id")),
+    responses(
+        (status = OK, body = ApiResponse<Image>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn delete_image(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let image = match find_image(&state, id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+
+    let dependents: Result<(i64, i64), _> = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM images WHERE parent_id = $1), \
+                (SELECT COUNT(*) FROM nodes WHERE image_id = $1)",
+    )
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+
+    match dependents {
+        Ok((0, 0)) => {}
+        Ok((children, nodes)) => {
+            return ApiError::Conflict(format!(
+                "Image {} is still used by {} child image(s) and {} node(s)",
+                id, children, nodes
+            ))
+            .into_response();
+        }
+        Err(e) => return internal_error("Failed to check image dependents", e),
+    }
+
+    match sqlx::query("DELETE FROM images WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await
+    {
+        Ok(_) => {
+            info!("Deleted image {} ({})", image.name, id);
+            ApiResponse::ok(image).into_response()
+        }
+        // A dependent may have been added since the check above
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            ApiError::Conflict(format!("Image {} is still in use", id)).into_response()
+        }
+        Err(e) => internal_error("Failed to delete image", e),
+    }
+}
+
+/// POST /node/{id}/vnc - Enable VNC on a running node and bind it to Guacamole
+///
+/// Allocates a free VNC display, starts the node's VNC server on it and
+/// registers a Guacamole connection, recording both on the node. A
+/// connection left on the node from an earlier run is replaced.
+#[utoipa::path(
+    post,
+    path = "/node/{id}/vnc",
+    tag = "connections",
+    params(("id" = Uuid, Path, description = "Node id")),
+    request_body = Option<EnableNodeVncRequest>,
+    responses(
+        (status = CREATED, body = ApiResponse<CreateConnectionResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn enable_node_vnc(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<EnableNodeVncRequest>>,
+) -> impl IntoResponse {
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+    let instance = match running_instance(&state, id).await {
+        Ok(instance) => instance,
+        Err(response) => return response,
+    };
+    let connection_name = payload.connection_name.as_deref().unwrap_or(&node.name);
+
+    let recording = match session_recording(
+        &state,
+        payload.record,
+        connection_name,
+        payload.recording_name.as_deref(),
+    ) {
+        Ok(recording) => recording,
+        Err(message) => return ApiError::Validation(message).into_response(),
+    };
+    let options = ConnectionOptions {
+        group: payload.group.clone(),
+        recording,
+        reuse: node.guacamole_connection_id.clone(),
+    };
+
+    let mut guard = instance.lock().await;
+    if guard.vnc_port.is_some() {
+        return ApiError::Conflict(format!("VNC is already enabled for node {}", id))
+            .into_response();
+    }
+
+    let claim = match qemu::next_vnc_display(&state).await {
+        Ok(claim) => claim,
+        Err(QemuError::VncPortAllocationFailed) => {
+            return ApiError::Conflict("No free VNC display is left".to_string()).into_response();
+        }
+        Err(e) => return internal_error("Failed to allocate VNC display", e),
+    };
+
+    let created = GuacamoleConnection::new(
+        &state.config.guacamole,
+        connection_name,
+        &mut guard,
+        Some(claim.display()),
+        payload.display,
+        payload.password,
+        options,
+    )
+    .await;
+    metrics::record_connection_result("vnc", &created);
+
+    let connection = match created {
+        Ok(connection) => connection,
+        Err(e) => {
+            // Don't leave a VNC server listening that nothing points at
+            if guard.vnc_port.is_some()
+                && let Err(e) = qemu::disable_vnc(&mut guard, &state).await
+            {
+                warn!("Failed to disable VNC on node {} after error: {}", id, e);
+            }
+            return match e {
+                GuacamoleError::Qemu(QemuError::InvalidVncPassword(_)) => {
+                    ApiError::Validation(e.to_string()).into_response()
+                }
+                GuacamoleError::Qemu(QemuError::VncPortAllocationFailed) => {
+                    ApiError::Conflict(e.to_string()).into_response()
+                }
+                e => guacamole_error_response("Failed to create VNC connection", e),
+            };
+        }
+    };
+
+    if let Err(e) =
+        sqlx::query("UPDATE nodes SET vnc_port = $1, guacamole_connection_id = $2 WHERE id = $3")
+            .bind(connection.port as i32)
+            .bind(&connection.connection_id)
+            .bind(id)
+            .execute(&state.db)
+            .await
+    {
+        // Undo like a failed creation, so nothing is left that the node doesn't
+        // record; a reused connection is still the node's own, so it stays
+        if let Err(e) = qemu::disable_vnc(&mut guard, &state).await {
+            warn!("Failed to disable VNC on node {} after error: {}", id, e);
+        }
+        if node.guacamole_connection_id.as_ref() != Some(&connection.connection_id)
+            && let Err(e) = connection.delete(&state.config.guacamole).await
+        {
+            warn!(
+                "Failed to delete Guacamole connection {} of node {} after error: {}",
+                connection.connection_id, id, e
+            );
+        }
+        return internal_error("Failed to bind connection to node", e);
+    }
+    drop(claim);
+    drop(guard);
+
+    // A reused connection is the very one the node already records
+    if let Some(stale_id) = &node.guacamole_connection_id
+        && *stale_id != connection.connection_id
+    {
+        match GuacamoleConnection::delete_by_id(&state.config.guacamole, stale_id).await {
+            Ok(()) => {
+                audit::record(
+                    &state,
+                    &actor,
+                    AuditAction::ConnectionDeleted,
+                    stale_id,
+                    json!({ "node_id": id }),
+                )
+                .await
+            }
+            Err(e) => warn!(
+                "Failed to delete stale Guacamole connection {} of node {}: {}",
+                stale_id, id, e
+            ),
+        }
+    }
+
+    state.publish(NodeEvent::VncEnabled {
+        node_id: id,
+        vnc_port: connection.port,
+    });
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::ConnectionCreated,
+        &connection.connection_id,
+        json!({
+            "protocol": "vnc",
+            "port": connection.port,
+            "node_id": id,
+            "group": payload.group,
+            "recorded": payload.record,
+        }),
+    )
+    .await;
+    info!(
+        "Enabled VNC for node {} on port {} as connection {}",
+        id, connection.port, connection.connection_id
+    );
+
+    ApiResponse::ok(CreateConnectionResponse {
+        connection_name: connection.connection_name,
+        connection_id: connection.connection_id,
+        parent_identifier: connection.parent_identifier,
+        client_url: connection.client_url,
+        websocket_url: connection.websocket_url,
+        tunnel_url: connection.tunnel_url,
+    })
+    .with_status(StatusCode::CREATED)
+    .into_response()
+}
+
+/// DELETE /node/{id}/vnc - Turn off a node's VNC server and delete its Guacamole connection
+///
+/// Succeeds without doing anything when the node has neither, so it is safe to retry.
+#[utoipa::path(
+    delete,
+    path = "/node/{id}/vnc",
+    tag = "connections",
+    params(("id" = Uuid, Path, description = "Node id")),
+    responses(
+        (status = OK, body = ApiResponse<Node>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn disable_node_vnc(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let node = match find_node(&state, id).await {
+        Ok(node) => node,
+        Err(response) => return response,
+    };
+
+    // Close the listener first so nobody connects while the connection is removed
+    if let Some(instance) = state.instances.get(id).await {
+        let mut guard = instance.lock().await;
+        if guard.vnc_port.is_some()
+            && let Err(e) = qemu::disable_vnc(&mut guard, &state).await
+        {
+            return internal_error("Failed to disable VNC", e);
+        }
+    }
+
+    if let Some(connection_id) = &node.guacamole_connection_id {
+        if let Err(e) =
+            GuacamoleConnection::delete_by_id(&state.config.guacamole, connection_id).await
+        {
+            return guacamole_error_response("Failed to delete Guacamole connection", e);
+        }
+        audit::record(
+            &state,
+            &actor,
+            AuditAction::ConnectionDeleted,
+            connection_id,
+            json!({ "node_id": id }),
+        )
+        .await;
+        info!(
+            "Deleted Guacamole connection {} of node {}",
+            connection_id, id
+        );
+    }
+
+    let result: Result<Node, _> = sqlx::query_as(&format!(
+        "UPDATE nodes SET vnc_port = NULL, guacamole_connection_id = NULL WHERE id = $1 RETURNING {}",
+        NODE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&state.db)
+    .await;
+
+    match result {
+        Ok(node) => {
+            state.publish(NodeEvent::VncDisabled { node_id: id });
+            ApiResponse::ok(node).into_response()
+        }
+        Err(e) => internal_error("Failed to unbind connection from node", e),
+    }
 }
 
 /// POST /vnc - Create a VNC connection and bind it to Guacamole
+#[utoipa::path(
+    post,
+    path = "/vnc",
+    tag = "connections",
+    request_body = CreateVncConnectionRequest,
+    responses(
+        (status = OK, body = ApiResponse<CreateConnectionResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
 pub async fn create_vnc_connection(
     State(state): State<AppState>,
+    actor: Actor,
     Json(payload): Json<CreateVncConnectionRequest>,
 ) -> impl IntoResponse {
     let connection_name = payload
@@ -50,36 +2862,676 @@ pub async fn create_vnc_connection(
         .as_deref()
         .unwrap_or("vnc-connection");
 
-    match GuacamoleConnection::from_vnc(
-        &state.env,
+    // Check the node up front so a bad id doesn't leave an unbound connection behind
+    let node = match payload.node_id {
+        Some(node_id) => match find_node(&state, node_id).await {
+            Ok(node) => Some(node),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    // A node's VNC server always requires a password, so reuse it when the
+    // connection points at that server
+    let mut password = payload.password;
+    if password.is_none()
+        && let Some(node_id) = payload.node_id
+        && let Some(instance) = state.instances.get(node_id).await
+    {
+        let instance = instance.lock().await;
+        if instance.vnc_port == Some(payload.vnc_port) {
+            password = instance.vnc_password.clone();
+        }
+    }
+
+    let recording = match session_recording(
+        &state,
+        payload.record,
+        connection_name,
+        payload.recording_name.as_deref(),
+    ) {
+        Ok(recording) => recording,
+        Err(message) => return ApiError::Validation(message).into_response(),
+    };
+    let options = ConnectionOptions {
+        group: payload.group.clone(),
+        recording,
+        reuse: node.and_then(|node| node.guacamole_connection_id),
+    };
+
+    let created = GuacamoleConnection::from_vnc(
+        &state.config.guacamole,
         connection_name,
         &payload.vnc_host,
         payload.vnc_port,
+        payload.display,
+        password,
+        options,
     )
-    .await
+    .await;
+    metrics::record_connection_result("vnc", &created);
+
+    let connection = match created {
+        Ok(connection) => connection,
+        Err(e) => {
+            return guacamole_error_response("Failed to create VNC connection", e);
+        }
+    };
+
+    if let Some(node_id) = payload.node_id
+        && let Err(e) = sqlx::query("UPDATE nodes SET guacamole_connection_id = $1 WHERE id = $2")
+            .bind(&connection.connection_id)
+            .bind(node_id)
+            .execute(&state.db)
+            .await
     {
-        Ok(connection) => Json(ApiResponse::ok(CreateVncConnectionResponse {
-            connection_name: connection.connection_name,
-            connection_id: connection.connection_id,
-            client_url: connection.client_url,
-            websocket_url: connection.websocket_url,
-            tunnel_url: connection.tunnel_url,
-        }))
-        .into_response(),
-        Err(e) => Json(ApiResponse::<()>::error(format!(
-            "Failed to create VNC connection: {}",
-            e
-        )))
+        return internal_error("Failed to bind connection to node", e);
+    }
+
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::ConnectionCreated,
+        &connection.connection_id,
+        json!({
+            "protocol": "vnc",
+            "host": payload.vnc_host,
+            "port": payload.vnc_port,
+            "node_id": payload.node_id,
+            "group": payload.group,
+            "recorded": payload.record,
+        }),
+    )
+    .await;
+
+    ApiResponse::ok(CreateConnectionResponse {
+        connection_name: connection.connection_name,
+        connection_id: connection.connection_id,
+        parent_identifier: connection.parent_identifier,
+        client_url: connection.client_url,
+        websocket_url: connection.websocket_url,
+        tunnel_url: connection.tunnel_url,
+    })
+    .into_response()
+}
+
+/// GET /ws/nodes - Stream node lifecycle events as JSON text messages
+///
+/// A client that falls too far behind gets a `lagged` message with the
+/// number of events it missed and should reload node state.
+#[utoipa::path(
+    get,
+    path = "/ws/nodes",
+    tag = "nodes",
+    responses((status = SWITCHING_PROTOCOLS, description = "WebSocket of `NodeEvent` JSON messages"))
+)]
+pub async fn node_events(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    let events = state.events.subscribe();
+    ws.on_upgrade(move |socket| stream_node_events(socket, events))
+}
+
+async fn stream_node_events(mut socket: WebSocket, mut events: Receiver<NodeEvent>) {
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => serde_json::to_string(&event),
+                Err(RecvError::Lagged(skipped)) => serde_json::to_string(
+                    &serde_json::json!({ "event": "lagged", "skipped": skipped }),
+                ),
+                Err(RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                // Clients only listen, so anything but a close is ignored
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let text = match message {
+            Ok(text) => text,
+            Err(e) => {
+                error!("Failed to serialize node event: {}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+    debug!("Node event subscriber disconnected");
+}
+
+/// Stop the tracked QEMU instance of a node, if there is one.
+///
+/// Returns whether an instance was tracked. On failure the instance is put
+/// back into the registry so it isn't lost.
+async fn stop_tracked_instance(state: &AppState, id: Uuid) -> Result<bool, Response> {
+    let Some(instance) = state.instances.remove(id).await else {
+        return Ok(false);
+    };
+
+    let stopped = {
+        let mut guard = instance.lock().await;
+        qemu::stop_node(&mut guard, state.config.qemu.shutdown_timeout).await
+    };
+
+    match stopped {
+        // The process died on its own; all that's left is to record it
+        Ok(()) | Err(QemuError::NodeNotRunning) => {
+            state.instances.release(id).await;
+            Ok(true)
+        }
+        Err(e) => {
+            state.instances.insert(id, instance).await;
+            Err(internal_error("Failed to stop node", e))
+        }
+    }
+}
+
+/// Attach a node's image and its ancestry, reusing chains already present in `chains`
+async fn with_image(
+    state: &AppState,
+    node: Node,
+    chains: &mut HashMap<Uuid, Vec<Image>>,
+) -> Result<NodeWithImage, Response> {
+    let chain = match chains.get(&node.image_id) {
+        Some(chain) => chain.clone(),
+        None => {
+            let chain = qemu::get_image_chain(node.image_id, state)
+                .await
+                .map_err(|e| internal_error("Failed to load image ancestry", e))?;
+            chains.insert(node.image_id, chain.clone());
+            chain
+        }
+    };
+
+    let image = ImageWithAncestors::from_chain(chain)
+        .ok_or_else(|| internal_error("Failed to load image ancestry", "empty image chain"))?;
+
+    Ok(NodeWithImage { node, image })
+}
+
+/// Load an image by id, mapping a missing row to a 404 response
+async fn find_image(state: &AppState, id: Uuid) -> Result<Image, Response> {
+    let image: Option<Image> = sqlx::query_as(&format!(
+        "SELECT {} FROM images WHERE id = $1",
+        IMAGE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| internal_error("Failed to load image", e))?;
+
+    image.ok_or_else(|| ApiError::NotFound(format!("Image {} not found", id)).into_response())
+}
+
+/// Record an image whose file is already in place, mapping a duplicate
+/// name or path to a 409 response
+async fn insert_image(state: &AppState, image: &Image) -> Result<Image, Response> {
+    sqlx::query_as(&format!(
+        "INSERT INTO images (id, name, path, parent_id, description, sha256) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+        IMAGE_COLUMNS
+    ))
+    .bind(image.id)
+    .bind(&image.name)
+    .bind(&image.path)
+    .bind(image.parent_id)
+    .bind(&image.description)
+    .bind(&image.sha256)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => ApiError::Conflict(format!(
+            "An image named `{}` or with path `{}` already exists",
+            image.name, image.path
+        ))
         .into_response(),
+        e => internal_error("Failed to register image", e),
+    })
+}
+
+/// Look up the tracked instance of a node, responding 404 for an unknown
+/// node and 409 for one that isn't running
+async fn running_instance(state: &AppState, id: Uuid) -> Result<SharedInstance, Response> {
+    find_node(state, id).await?;
+    state
+        .instances
+        .get(id)
+        .await
+        .ok_or_else(|| ApiError::Conflict(format!("Node {} is not running", id)).into_response())
+}
+
+async fn find_link(state: &AppState, id: Uuid) -> Result<Link, Response> {
+    let link: Option<Link> =
+        sqlx::query_as(&format!("SELECT {} FROM links WHERE id = $1", LINK_COLUMNS))
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|e| internal_error("Failed to load link", e))?;
+
+    link.ok_or_else(|| ApiError::NotFound(format!("Link {} not found", id)).into_response())
+}
+
+/// Load a node by id, mapping a missing row to a 404 response
+async fn find_node(state: &AppState, id: Uuid) -> Result<Node, Response> {
+    let node: Option<Node> =
+        sqlx::query_as(&format!("SELECT {} FROM nodes WHERE id = $1", NODE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|e| internal_error("Failed to load node", e))?;
+
+    node.ok_or_else(|| ApiError::NotFound(format!("Node {} not found", id)).into_response())
+}
+
+/// Run a handler's work in its own task, so it finishes even if the request
+/// times out or the client goes away. Starting a VM must not stop halfway:
+/// a dropped start would leak its reservation or leave QEMU running untracked.
+async fn detached(work: impl Future<Output = Response> + Send + 'static) -> Response {
+    match tokio::spawn(work).await {
+        Ok(response) => response,
+        Err(e) => internal_error("Request handler failed", e),
+    }
+}
+
+fn internal_error(context: &str, err: impl Display) -> Response {
+    error!("{context}: {err}");
+    ApiError::Internal(format!("{context}: {err}")).into_response()
+}
+
+/// POST /ssh - Create an SSH connection in Guacamole
+#[utoipa::path(
+    post,
+    path = "/ssh",
+    tag = "connections",
+    request_body = CreateSshConnectionRequest,
+    responses(
+        (status = OK, body = ApiResponse<CreateConnectionResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn create_ssh_connection(
+    State(state): State<AppState>,
+    actor: Actor,
+    Json(payload): Json<CreateSshConnectionRequest>,
+) -> impl IntoResponse {
+    let connection_name = payload
+        .connection_name
+        .as_deref()
+        .unwrap_or("ssh-connection");
+
+    let credentials = SshCredentials {
+        username: payload.username,
+        password: payload.password,
+        private_key: payload.private_key,
+        passphrase: payload.passphrase,
+    };
+
+    let recording = match session_recording(
+        &state,
+        payload.record,
+        connection_name,
+        payload.recording_name.as_deref(),
+    ) {
+        Ok(recording) => recording,
+        Err(message) => return ApiError::Validation(message).into_response(),
+    };
+    let options = ConnectionOptions {
+        group: payload.group.clone(),
+        recording,
+        reuse: None,
+    };
+
+    let ssh_port = payload.ssh_port.unwrap_or(22);
+    let created = GuacamoleConnection::from_ssh(
+        &state.config.guacamole,
+        connection_name,
+        &payload.ssh_host,
+        ssh_port,
+        credentials,
+        options,
+    )
+    .await;
+    metrics::record_connection_result("ssh", &created);
+
+    let connection = match created {
+        Ok(connection) => connection,
+        Err(e) => return guacamole_error_response("Failed to create SSH connection", e),
+    };
+
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::ConnectionCreated,
+        &connection.connection_id,
+        json!({
+            "protocol": "ssh",
+            "host": payload.ssh_host,
+            "port": ssh_port,
+            "group": payload.group,
+            "recorded": payload.record,
+        }),
+    )
+    .await;
+
+    ApiResponse::ok(CreateConnectionResponse {
+        connection_name: connection.connection_name,
+        connection_id: connection.connection_id,
+        parent_identifier: connection.parent_identifier,
+        client_url: connection.client_url,
+        websocket_url: connection.websocket_url,
+        tunnel_url: connection.tunnel_url,
+    })
+    .into_response()
+}
+
+/// GET /audit - Audit log entries, newest first, optionally within a time range
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(AuditLogQuery),
+    responses(
+        (status = OK, body = ApiResponse<Vec<AuditEntry>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn list_audit_log(
+    State(state): State<AppState>,
+    Query(query): Query<AuditLogQuery>,
+) -> impl IntoResponse {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_PAGE_SIZE)
+        .clamp(1, MAX_AUDIT_PAGE_SIZE);
+
+    let entries: Result<Vec<AuditEntry>, _> = sqlx::query_as(
+        "SELECT id, created_at, actor, action, target_id, details FROM audit_log \
+         WHERE ($1::timestamptz IS NULL OR created_at >= $1) \
+         AND ($2::timestamptz IS NULL OR created_at < $2) \
+         ORDER BY created_at DESC, id DESC LIMIT $3",
+    )
+    .bind(query.since)
+    .bind(query.until)
+    .bind(i64::from(limit))
+    .fetch_all(&state.db)
+    .await;
+
+    match entries {
+        Ok(entries) => ApiResponse::ok(entries).into_response(),
+        Err(e) => internal_error("Failed to load audit log", e),
+    }
+}
+
+/// Recording settings for a new connection, if the client asked for one.
+/// Fails with a message for a 400 when recording can't be honoured.
+fn session_recording(
+    state: &AppState,
+    record: bool,
+    connection_name: &str,
+    recording_name: Option<&str>,
+) -> Result<Option<SessionRecording>, String> {
+    if !record {
+        return Ok(None);
+    }
+    // guacd joins the name onto the recording directory
+    if let Some(name) = recording_name
+        && (name.is_empty() || name.contains('/') || name == "..")
+    {
+        return Err(format!("Invalid recording name {:?}", name));
+    }
+    SessionRecording::in_configured_dir(&state.config.guacamole, connection_name, recording_name)
+        .map(Some)
+        .ok_or_else(|| "Session recording requires GUAC_RECORDING_DIR to be set".to_string())
+}
+
+/// Report a failed Guacamole call as 504 if it timed out and 502 otherwise
+fn guacamole_error_response(context: &str, err: GuacamoleError) -> Response {
+    let message = format!("{}: {}", context, err);
+    match err {
+        GuacamoleError::Timeout => ApiError::UpstreamTimeout(message),
+        _ => ApiError::Upstream(message),
+    }
+    .into_response()
+}
+
+/// POST /connection/{id}/share - Create a sharing profile and link for a Guacamole connection
+#[utoipa::path(
+    post,
+    path = "/connection/{id}/share",
+    tag = "connections",
+    params(("id" = String, Path, description = "Guacamole connection identifier")),
+    request_body = ShareConnectionRequest,
+    responses(
+        (status = CREATED, body = ApiResponse<crate::guacamole::GuacamoleShare>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn share_connection(
+    State(state): State<AppState>,
+    actor: Actor,
+    Path(connection_id): Path<String>,
+    Json(payload): Json<ShareConnectionRequest>,
+) -> impl IntoResponse {
+    let read_only = payload.read_only.unwrap_or(true);
+    let share = match GuacamoleConnection::create_share_by_id(
+        &state.config.guacamole,
+        &connection_id,
+        read_only,
+    )
+    .await
+    {
+        Ok(share) => share,
+        Err(e) => return guacamole_error_response("Failed to share connection", e),
+    };
+
+    audit::record(
+        &state,
+        &actor,
+        AuditAction::ConnectionShared,
+        &connection_id,
+        json!({
+            "sharing_profile_id": share.sharing_profile_id,
+            "read_only": read_only,
+        }),
+    )
+    .await;
+
+    ApiResponse::ok(share)
+        .with_status(StatusCode::CREATED)
+        .into_response()
+}
+
+/// GET /connection - List the connections registered in Guacamole
+#[utoipa::path(
+    get,
+    path = "/connection",
+    tag = "connections",
+    responses(
+        (status = OK, body = ApiResponse<Vec<crate::guacamole::GuacamoleConnectionSummary>>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn list_connections(State(state): State<AppState>) -> impl IntoResponse {
+    match GuacamoleConnection::list_connections(&state.config.guacamole).await {
+        Ok(connections) => ApiResponse::ok(connections).into_response(),
+        Err(e) => guacamole_error_response("Failed to list Guacamole connections", e),
+    }
+}
+
+/// GET /health - Liveness probe checking that the database answers
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = OK, body = ApiResponse<HealthResponse>),
+        (status = "4XX", body = ApiResponse<NoData>),
+        (status = "5XX", body = ApiResponse<NoData>)
+    )
+)]
+pub async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    match sqlx::query("SELECT 1").execute(&state.db).await {
+        Ok(_) => ApiResponse::ok(HealthResponse { status: "ok" }).into_response(),
+        Err(e) => ApiError::Unavailable(format!("Database is unreachable: {}", e)).into_response(),
+    }
+}
+
+/// GET /ready - Readiness probe checking the database and Guacamole
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    responses(
+        (status = OK, body = ApiResponse<ReadinessResponse>),
+        (status = SERVICE_UNAVAILABLE, body = ApiResponse<ReadinessResponse>)
+    )
+)]
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let (database, guacamole) = tokio::join!(
+        sqlx::query("SELECT 1").execute(&state.db),
+        GuacamoleConnection::ping(&state.config.guacamole),
+    );
+
+    let readiness = ReadinessResponse {
+        database: database.map(|_| ()).into(),
+        guacamole: guacamole.into(),
+    };
+
+    if readiness.database.ok && readiness.guacamole.ok {
+        ApiResponse::ok(readiness).into_response()
+    } else {
+        ApiResponse {
+            success: false,
+            data: Some(readiness),
+            error: Some(ApiError::Unavailable(
+                "Not all dependencies are available".into(),
+            )),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+        .into_response()
+    }
+}
+
+/// Give the plain-text 408 and 413 responses of the timeout and body limit
+/// layers, and of body extractors, the usual JSON error body
+async fn json_limit_errors(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    match response.status() {
+        _ if is_json => response,
+        StatusCode::PAYLOAD_TOO_LARGE => {
+            ApiError::PayloadTooLarge("Request body is too large".into()).into_response()
+        }
+        StatusCode::REQUEST_TIMEOUT => {
+            ApiError::RequestTimeout("Request took too long to handle".into()).into_response()
+        }
+        _ => response,
     }
 }
 
 pub fn create_router(state: AppState) -> Router {
+    let server = &state.config.server;
+    let body_limit = || {
+        (
+            DefaultBodyLimit::disable(),
+            RequestBodyLimitLayer::new(server.request_body_limit),
+        )
+    };
+
+    // Copies, commits and checksums whole disk images, so these can
+    // legitimately outlast REQUEST_TIMEOUT. Starting verifies the image chain
+    // and a hard restart waits for the old process to shut down first.
+    let long_running = Router::new()
+        .route("/node/{id}/run", post(run_node))
+        .route("/node/{id}/restart", post(restart_node))
+        .route("/node/{id}/clone", post(clone_node))
+        .route("/node/{id}/commit", post(commit_node))
+        .route(
+            "/node/{id}/snapshot",
+            post(create_snapshot).get(list_snapshots),
+        )
+        .route("/node/{id}/snapshot/{name}/restore", post(restore_snapshot))
+        .route("/image/import", post(import_image))
+        .route("/image/{id}/verify", post(verify_image))
+        .layer(body_limit());
+
+    // Uploads are also capped by IMAGE_UPLOAD_MAX_MB while streaming; the
+    // slack leaves room for the multipart framing and text parts
+    let uploads = Router::new()
+        .route("/image/upload", post(upload_image))
+        .layer((
+            DefaultBodyLimit::disable(),
+            RequestBodyLimitLayer::new(
+                usize::try_from(state.config.qemu.image_upload_max_bytes)
+                    .unwrap_or(usize::MAX)
+                    .saturating_add(UPLOAD_FORM_SLACK),
+            ),
+        ));
+
     Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
         .route("/node", post(create_node).get(list_nodes))
-        .route("/node/{id}/run", post(run_node))
+        .route("/node/bulk", post(create_nodes_bulk))
+        .route(
+            "/node/{id}",
+            get(get_node).patch(rename_node).delete(delete_node),
+        )
+        .route("/node/{id}/status", get(get_node_status))
+        .route("/node/{id}/blocks", get(get_node_blocks))
+        .route("/node/{id}/console/log", get(get_console_log))
+        .route("/node/{id}/capture", get(get_capture))
+        .route("/node/{id}/capture/start", post(start_capture))
+        .route("/node/{id}/capture/stop", post(stop_capture))
+        .route("/node/{id}/disk", get(get_node_disk_usage))
+        .route("/node/{id}/spice", get(get_spice_info))
         .route("/node/{id}/stop", post(stop_node))
         .route("/node/{id}/wipe", post(wipe_node))
+        .route("/node/{id}/pause", post(pause_node))
+        .route("/node/{id}/resume", post(resume_node))
+        .route("/node/{id}/memory", post(set_node_memory))
+        .route("/node/{id}/cpu", post(add_node_cpu))
+        .route("/node/{id}/cdrom", post(attach_cdrom).delete(detach_cdrom))
+        .route("/node/{id}/monitor", post(run_monitor_command))
+        .route(
+            "/node/{id}/vnc",
+            post(enable_node_vnc).delete(disable_node_vnc),
+        )
+        .route("/link", post(create_link).get(list_links))
+        .route("/link/{id}", get(get_link).delete(delete_link))
+        .route("/image", post(create_image).get(list_images))
+        .route("/image/{id}", get(get_image).delete(delete_image))
         .route("/vnc", post(create_vnc_connection))
+        .route("/ssh", post(create_ssh_connection))
+        .route("/connection", get(list_connections))
+        .route("/connection/{id}/share", post(share_connection))
+        .route("/ws/nodes", get(node_events))
+        .route("/audit", get(list_audit_log))
+        .route("/metrics", get(metrics::render))
+        .route("/api-docs", get(openapi::swagger_ui))
+        .route("/api-docs/openapi.json", get(openapi::openapi_json))
+        .layer((
+            body_limit(),
+            TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, server.request_timeout),
+        ))
+        .merge(long_running)
+        .merge(uploads)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_api_key,
+        ))
+        .route_layer(middleware::from_fn(metrics::track_requests))
+        .layer(middleware::map_response(json_limit_errors))
+        .layer(middleware::from_fn(request_id::assign))
+        // Outermost, so preflights are answered before authentication
+        .layer(cors::layer(&state.config.server.cors_allowed_origins))
         .with_state(state)
 }
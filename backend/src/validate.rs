@@ -0,0 +1,161 @@
+use std::path::{Component, Path};
+
+use thiserror::Error;
+
+/// Longest node or image name accepted
+pub const MAX_NAME_LEN: usize = 64;
+/// Longest image path accepted, relative to `IMAGE_DIR`
+pub const MAX_PATH_LEN: usize = 255;
+
+/// Why a user-supplied name or path was rejected.
+///
+/// Checked before a request touches the database or filesystem, so these
+/// map to a 400 rather than surfacing later as a constraint or IO error.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("`{0}` must not be empty")]
+    Empty(&'static str),
+    #[error("`{field}` must be at most {max} characters")]
+    TooLong { field: &'static str, max: usize },
+    #[error("`{0}` may only contain letters, digits, spaces, `-`, `_` and `.`")]
+    InvalidCharacters(&'static str),
+    #[error("`{0}` must not contain control characters")]
+    ControlCharacters(&'static str),
+    #[error("`{0}` must not start or end with whitespace")]
+    SurroundingWhitespace(&'static str),
+    #[error("`{0}` must be a relative path")]
+    AbsolutePath(&'static str),
+    #[error("`{0}` must not contain `..` components")]
+    ParentComponent(&'static str),
+}
+
+/// Check a node or image name.
+///
+/// Names end up in Guacamole connection names and log lines, so keep them
+/// to a conservative, printable character set.
+pub fn name(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check_length(field, value, MAX_NAME_LEN)?;
+    if value.trim() != value {
+        return Err(ValidationError::SurroundingWhitespace(field));
+    }
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+    {
+        return Err(ValidationError::InvalidCharacters(field));
+    }
+    Ok(())
+}
+
+/// Check a path that will be joined onto a base directory.
+///
+/// `validate_and_resolve_path` still confines the resolved path to its base
+/// directory; this rejects the obvious escapes before anything is resolved.
+pub fn relative_path(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check_length(field, value, MAX_PATH_LEN)?;
+    if value.chars().any(char::is_control) {
+        return Err(ValidationError::ControlCharacters(field));
+    }
+    for component in Path::new(value).components() {
+        match component {
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(ValidationError::AbsolutePath(field));
+            }
+            Component::ParentDir => return Err(ValidationError::ParentComponent(field)),
+            Component::CurDir | Component::Normal(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_length(field: &'static str, value: &str, max: usize) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        Err(ValidationError::Empty(field))
+    } else if value.chars().count() > max {
+        Err(ValidationError::TooLong { field, max })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_accepts_plain_names() {
+        for value in [
+            "router-1",
+            "Debian 12",
+            "web_server.v2",
+            &"a".repeat(MAX_NAME_LEN),
+        ] {
+            assert!(name("name", value).is_ok(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn name_rejects_bad_names() {
+        let long = "a".repeat(MAX_NAME_LEN + 1);
+        let cases = [
+            ("", ValidationError::Empty("name")),
+            (
+                &long,
+                ValidationError::TooLong {
+                    field: "name",
+                    max: MAX_NAME_LEN,
+                },
+            ),
+            (" router", ValidationError::SurroundingWhitespace("name")),
+            ("router\t", ValidationError::SurroundingWhitespace("name")),
+            ("router/1", ValidationError::InvalidCharacters("name")),
+            ("rou\u{7}ter", ValidationError::InvalidCharacters("name")),
+            ("routér", ValidationError::InvalidCharacters("name")),
+        ];
+
+        for (value, expected) in cases {
+            let err = name("name", value).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn relative_path_accepts_paths_below_the_base() {
+        for value in [
+            "debian.qcow2",
+            "layers/debian-nginx.qcow2",
+            "./debian.qcow2",
+            "odd..name.qcow2",
+        ] {
+            assert!(relative_path("path", value).is_ok(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn relative_path_rejects_escapes() {
+        let long = "a".repeat(MAX_PATH_LEN + 1);
+        let cases = [
+            ("", ValidationError::Empty("path")),
+            (
+                &long,
+                ValidationError::TooLong {
+                    field: "path",
+                    max: MAX_PATH_LEN,
+                },
+            ),
+            ("..", ValidationError::ParentComponent("path")),
+            (
+                "layers/../../etc/passwd",
+                ValidationError::ParentComponent("path"),
+            ),
+            ("/etc/passwd", ValidationError::AbsolutePath("path")),
+            ("disk\n.qcow2", ValidationError::ControlCharacters("path")),
+            ("disk\0.qcow2", ValidationError::ControlCharacters("path")),
+        ];
+
+        for (value, expected) in cases {
+            let err = relative_path("path", value).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string(), "{:?}", value);
+        }
+    }
+}